@@ -0,0 +1,26 @@
+#![feature(test)]
+
+extern crate test;
+
+use test::Bencher;
+
+fn bench_memzero(b: &mut Bencher, len: usize) {
+    let mut buf = vec![0xdb; len];
+    b.bytes = len as u64;
+    b.iter(|| sodium_alloc::memzero(test::black_box(&mut buf)));
+}
+
+#[bench]
+fn memzero_4kib(b: &mut Bencher) {
+    bench_memzero(b, 1 << 12);
+}
+
+#[bench]
+fn memzero_1mib(b: &mut Bencher) {
+    bench_memzero(b, 1 << 20);
+}
+
+#[bench]
+fn memzero_64mib(b: &mut Bencher) {
+    bench_memzero(b, 1 << 26);
+}
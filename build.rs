@@ -0,0 +1,40 @@
+//! Records how libsodium is linked, for `linkage_info`.
+//!
+//! libsodium-sys decides how to link libsodium based on environment variables, so we make the same
+//! decision here. It also tells us where libsodium's headers are, if it knows, from which we read
+//! the version of libsodium we were built against.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    for var in ["SODIUM_LIB_DIR", "SODIUM_SHARED", "SODIUM_USE_PKG_CONFIG"] {
+        println!("cargo:rerun-if-env-changed={}", var);
+    }
+
+    let linkage = if env::var_os("SODIUM_LIB_DIR").is_some() {
+        if env::var_os("SODIUM_SHARED").is_some() {
+            "dynamic"
+        } else {
+            "static"
+        }
+    } else if env::var_os("SODIUM_USE_PKG_CONFIG").is_some() {
+        // pkg-config (or vcpkg) chooses how to link
+        "unknown"
+    } else {
+        "vendored"
+    };
+    println!("cargo:rustc-env=SODIUM_ALLOC_LINKAGE={}", linkage);
+
+    let version = env::var_os("DEP_SODIUM_INCLUDE")
+        .and_then(|dir| fs::read_to_string(Path::new(&dir).join("sodium/version.h")).ok())
+        .and_then(|header| {
+            header.lines().find_map(|line| {
+                let version = line.strip_prefix("#define SODIUM_VERSION_STRING")?;
+                Some(version.trim().trim_matches('"').to_owned())
+            })
+        })
+        .unwrap_or_default();
+    println!("cargo:rustc-env=SODIUM_ALLOC_HEADER_VERSION={}", version);
+}
@@ -0,0 +1,149 @@
+//! Type-erased secrets stored in memory allocated using Sodium.
+
+use crate::{SecureBox, SodiumAllocator};
+use std::alloc::AllocError;
+use std::any::Any;
+
+/// A type-erased secret, stored in memory allocated using [`SodiumAllocator`].
+///
+/// This wraps a `SecureBox<dyn Any + Send + Sync>`, and can be downcast back to the concrete type
+/// of the secret. Downcasting never moves the secret: [`downcast_ref`](Self::downcast_ref) and
+/// [`downcast_mut`](Self::downcast_mut) borrow it in place, and [`downcast`](Self::downcast)
+/// returns a [`SecureBox`] of the concrete type which owns the original guarded allocation. This is
+/// useful for storing heterogeneous secrets in a single collection, such as a registry of keys
+/// for different algorithms.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::SecureAny;
+///
+/// struct AesKey([u8; 32]);
+/// struct HmacKey([u8; 64]);
+///
+/// let registry = vec![
+///     SecureAny::new(AesKey([0x13; 32]))?,
+///     SecureAny::new(HmacKey([0x37; 64]))?,
+/// ];
+///
+/// assert!(registry[0].is::<AesKey>());
+/// assert_eq!(registry[1].downcast_ref::<HmacKey>().unwrap().0, [0x37; 64]);
+/// assert!(registry[1].downcast_ref::<AesKey>().is_none());
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+pub struct SecureAny(SecureBox<dyn Any + Send + Sync>);
+
+impl SecureAny {
+    /// Move `value` into guarded memory, as a type-erased secret.
+    ///
+    /// `value` is moved into guarded memory, so a copy of it may remain where it was stored
+    /// before, as explained on [`SecureBox`](crate::SecureBox#moving-values-into-guarded-memory).
+    /// Use [`SecureAny::from_box`] for a secret which is already in guarded memory.
+    ///
+    /// Returns [`AllocError`] if the memory could not be allocated.
+    pub fn new<T: Any + Send + Sync>(value: T) -> Result<Self, AllocError> {
+        Ok(Self::from_box(Box::try_new_in(value, SodiumAllocator)?))
+    }
+
+    /// Erase the type of a secret which is already stored in a [`SecureBox`], without moving it.
+    pub fn from_box<T: Any + Send + Sync>(secret: SecureBox<T>) -> Self {
+        Self(secret)
+    }
+
+    /// Returns whether the secret is of type `T`.
+    pub fn is<T: Any>(&self) -> bool {
+        self.0.is::<T>()
+    }
+
+    /// Borrow the secret as a `T`, or return `None` if it isn't of type `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.0.downcast_ref()
+    }
+
+    /// Mutably borrow the secret as a `T`, or return `None` if it isn't of type `T`.
+    pub fn downcast_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.0.downcast_mut()
+    }
+
+    /// Convert this into a [`SecureBox`] of the secret's concrete type, without moving the
+    /// secret.
+    ///
+    /// Returns `self` unchanged if the secret isn't of type `T`.
+    pub fn downcast<T: Any>(self) -> Result<SecureBox<T>, Self> {
+        self.0.downcast().map_err(Self)
+    }
+
+    /// Unwrap the underlying `SecureBox`.
+    pub fn into_inner(self) -> SecureBox<dyn Any + Send + Sync> {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Key([u8; 32]);
+
+    #[derive(Debug, PartialEq)]
+    struct Password(String);
+
+    #[test]
+    fn downcast_each_type() -> Result<(), AllocError> {
+        let mut registry = [
+            SecureAny::new(Key([0x13; 32]))?,
+            SecureAny::new(Password(String::from("hunter2")))?,
+        ];
+
+        assert!(registry[0].is::<Key>());
+        assert!(!registry[0].is::<Password>());
+        assert_eq!(registry[0].downcast_ref(), Some(&Key([0x13; 32])));
+        assert_eq!(registry[0].downcast_ref::<Password>(), None);
+        assert_eq!(
+            registry[1].downcast_ref(),
+            Some(&Password(String::from("hunter2")))
+        );
+        assert_eq!(registry[1].downcast_ref::<Key>(), None);
+
+        registry[0].downcast_mut::<Key>().unwrap().0[0] = 0xff;
+        assert_eq!(
+            registry[0].downcast_ref::<Key>().unwrap().0[..2],
+            [0xff, 0x13]
+        );
+        assert!(registry[1].downcast_mut::<Key>().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn downcast_keeps_allocation() -> Result<(), AllocError> {
+        let secret = Box::new_in(Key([0x37; 32]), SodiumAllocator);
+        let ptr = &*secret as *const Key;
+        let erased = SecureAny::from_box(secret);
+
+        // A failed downcast returns the secret unchanged
+        let erased = erased.downcast::<Password>().unwrap_err();
+        let key = erased.downcast::<Key>().unwrap();
+        assert_eq!(*key, Key([0x37; 32]));
+        assert!(std::ptr::eq(&*key, ptr));
+
+        #[cfg(feature = "track-allocations")]
+        {
+            use crate::tracking::is_live;
+
+            assert!(is_live(ptr));
+            drop(key);
+            assert!(!is_live(ptr));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn debug_hides_secret() -> Result<(), AllocError> {
+        let secret = SecureAny::new(Password(String::from("hunter2")))?;
+        assert_eq!(format!("{:?}", secret), "[REDACTED]");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+//! Fixed-size byte arrays stored in memory allocated using Sodium.
+
+use crate::ops::volatile_copy;
+use crate::SodiumAllocator;
+use std::alloc::AllocError;
+use std::ops::{Deref, DerefMut};
+
+/// A fixed-size array of `N` bytes, stored in memory allocated using [`SodiumAllocator`].
+///
+/// This is intended for storing fixed-size secrets, such as keys, without the overhead of a
+/// runtime length. The array is stored in a single allocation of exactly `N` bytes, which is
+/// securely zeroed when the `SecureArray` is dropped. It dereferences to `[u8; N]`, so it can be
+/// used much like a standard array.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::SecureArray;
+///
+/// let mut key = SecureArray::<32>::new()?;
+/// key[..4].copy_from_slice(&[0xca, 0xfe, 0xba, 0xbe]);
+/// assert_eq!(key[..4], [0xca, 0xfe, 0xba, 0xbe]);
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+pub struct SecureArray<const N: usize>(Box<[u8; N], SodiumAllocator>);
+
+impl<const N: usize> SecureArray<N> {
+    /// Create a new `SecureArray`, with every byte initialised to zero.
+    ///
+    /// The array is zeroed in place, so no temporary copy of the array is made on the stack.
+    ///
+    /// Returns [`AllocError`] if the memory could not be allocated.
+    pub fn new() -> Result<Self, AllocError> {
+        let array = Box::try_new_zeroed_in(SodiumAllocator)?;
+        // SAFETY: An array of zeroed `u8`s is a valid `[u8; N]`.
+        Ok(Self(unsafe { array.assume_init() }))
+    }
+
+    /// Copy a secret held in a [`Zeroizing`](zeroize::Zeroizing) array into a new `SecureArray`.
+    ///
+    /// This is useful for moving a secret from the stack into guarded memory. The secret is copied
+    /// directly into the guarded allocation, and `secret` is then dropped, which zeroes the
+    /// original copy.
+    ///
+    /// Only available with the `zeroize` feature enabled.
+    ///
+    /// Returns [`AllocError`] if the memory could not be allocated. The original copy is still
+    /// zeroed in this case.
+    ///
+    /// ```
+    /// # #![feature(allocator_api)]
+    /// use sodium_alloc::SecureArray;
+    /// use zeroize::Zeroizing;
+    ///
+    /// let stack_key = Zeroizing::new([0x13u8; 32]);
+    /// let key = SecureArray::from_zeroizing(stack_key)?;
+    /// assert_eq!(*key, [0x13; 32]);
+    /// # Ok::<(), std::alloc::AllocError>(())
+    /// ```
+    #[cfg(feature = "zeroize")]
+    pub fn from_zeroizing(secret: zeroize::Zeroizing<[u8; N]>) -> Result<Self, AllocError> {
+        let mut array = Self::new()?;
+        array.copy_from_slice(&secret[..]);
+
+        Ok(array)
+    }
+
+    /// Move the secret out of guarded memory, returning it as a plain array.
+    ///
+    /// The returned array is **not** protected in any way: It will be stored wherever the caller
+    /// puts it (usually on the stack), and won't be zeroed when it goes out of scope. This should
+    /// only be used when a secret must be passed to code which can't accept guarded memory.
+    ///
+    /// The guarded allocation is freed before this function returns, and Sodium zeroes it as it
+    /// is freed, so the caller holds the only remaining copy of the secret.
+    pub fn into_array(self) -> [u8; N] {
+        *self.0
+    }
+
+    /// Copy the contents of this array into `dst`, using volatile reads and writes.
+    ///
+    /// Volatile accesses can't be elided or merged by the compiler, which gives exact control
+    /// over when secret bytes are read from guarded memory, and prevents them from being cached
+    /// (e.g: in registers) for longer than necessary.
+    ///
+    /// # Panics
+    /// Panics if `dst` is not the same length as this array.
+    pub fn read_volatile_into(&self, dst: &mut [u8]) {
+        volatile_copy(dst, &self[..]);
+    }
+
+    /// Overwrite the contents of this array with `src`, using volatile reads and writes.
+    ///
+    /// See [`SecureArray::read_volatile_into`] for details.
+    ///
+    /// # Panics
+    /// Panics if `src` is not the same length as this array.
+    pub fn write_volatile_from(&mut self, src: &[u8]) {
+        volatile_copy(&mut self[..], src);
+    }
+}
+
+impl<const N: usize> Deref for SecureArray<N> {
+    type Target = [u8; N];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<const N: usize> DerefMut for SecureArray<N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_and_read() -> Result<(), AllocError> {
+        let mut array = SecureArray::<32>::new()?;
+        assert_eq!(*array, [0; 32]);
+
+        for (i, b) in array.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        for (i, &b) in array.iter().enumerate() {
+            assert_eq!(b, i as u8);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn volatile_round_trip() -> Result<(), AllocError> {
+        let key: Vec<u8> = (0..32).collect();
+        let mut array = SecureArray::<32>::new()?;
+        array.write_volatile_from(&key);
+        assert_eq!(array[..], key[..]);
+
+        let mut out = [0; 32];
+        array.read_volatile_into(&mut out);
+        assert_eq!(out[..], key[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn zero_size() -> Result<(), AllocError> {
+        let array = SecureArray::<0>::new()?;
+        assert!(array.is_empty());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "track-allocations")]
+    #[test]
+    fn freed_on_drop() -> Result<(), AllocError> {
+        use crate::tracking::is_live;
+
+        let mut array = SecureArray::<32>::new()?;
+        array.fill(0xff);
+        let ptr = array.as_ptr();
+        assert!(is_live(ptr));
+
+        // Memory freed by `SodiumAllocator` is always zeroed by `sodium_free`
+        drop(array);
+        assert!(!is_live(ptr));
+
+        Ok(())
+    }
+
+    #[test]
+    fn into_array() -> Result<(), AllocError> {
+        let mut array = SecureArray::<32>::new()?;
+        array.fill(0x13);
+        let ptr = array.as_ptr();
+
+        let plain = array.into_array();
+        assert_eq!(plain, [0x13; 32]);
+
+        #[cfg(feature = "track-allocations")]
+        assert!(!crate::tracking::is_live(ptr));
+        #[cfg(not(feature = "track-allocations"))]
+        let _ = ptr;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn from_zeroizing() -> Result<(), AllocError> {
+        let mut secret = zeroize::Zeroizing::new([0u8; 64]);
+        for (i, b) in secret.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+
+        let array = SecureArray::from_zeroizing(secret)?;
+        assert!(array.iter().enumerate().all(|(i, &b)| b == i as u8));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+//! Helpers for working with [`Box`]es whose memory is managed by [`SodiumAllocator`].
+
+use crate::{SodiumAllocator, Zeroable};
+use std::alloc::AllocError;
+
+/// A [`Box`] whose memory is managed by [`SodiumAllocator`].
+///
+/// `T` may be unsized. In particular, a `SecureBox` of a concrete type can be coerced to a
+/// `SecureBox` of a trait object as usual, which allows polymorphic secrets to be stored in
+/// guarded memory. This needs no features beyond `allocator_api`: The value stays in the same
+/// allocation, and is freed using `SodiumAllocator` with its original layout when the box is
+/// dropped.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::{SecureBox, SodiumAllocator};
+///
+/// trait SigningKey {
+///     fn sign(&self, msg: &[u8]) -> u8;
+/// }
+///
+/// struct XorKey([u8; 32]);
+///
+/// impl SigningKey for XorKey {
+///     fn sign(&self, msg: &[u8]) -> u8 {
+///         msg.iter().zip(&self.0).fold(0, |acc, (m, k)| acc ^ m ^ k)
+///     }
+/// }
+///
+/// let key: SecureBox<dyn SigningKey> = Box::new_in(XorKey([0x13; 32]), SodiumAllocator);
+/// assert_eq!(key.sign(&[0x13, 0x00]), 0x13);
+/// ```
+///
+/// # Moving values into guarded memory
+///
+/// A value which is moved into a `SecureBox` (or any other type which stores its value in
+/// guarded memory) is copied there, and Rust never zeroes the place it was moved from. A copy of
+/// the secret may therefore remain wherever it was stored before, e.g: on the stack, or in a
+/// register spilled to the stack. To avoid this, create the value directly in guarded memory,
+/// for example using [`secure_box_zeroed`] and filling it in place, or use a constructor which
+/// takes a value which is already in a `SecureBox`.
+pub type SecureBox<T> = Box<T, SodiumAllocator>;
+
+/// Clone the contents of a [`SecureBox`] into a new `SecureBox`.
+///
+/// `Box<T, A>` implements [`Clone`] when `A: Clone`, but will abort the program if allocation
+/// fails. This function instead returns [`AllocError`] if the memory for the clone could not be
+/// allocated. The clone is always allocated using `SodiumAllocator`.
+pub fn clone_secure<T: Clone>(secret: &SecureBox<T>) -> Result<SecureBox<T>, AllocError> {
+    let clone = Box::try_new_uninit_in(SodiumAllocator)?;
+    Ok(Box::write(clone, T::clone(secret)))
+}
+
+/// Allocate a new [`SecureBox`] containing a zeroed `T`.
+///
+/// The value is zeroed in place, so no temporary copy of it is made on the stack. This is the
+/// preferred way to allocate a large, fixed-size secret buffer: `Box::new_in([0u8; N],
+/// SodiumAllocator)` constructs the array on the stack before copying it into guarded memory,
+/// which needs a stack frame of at least `N` bytes, and leaves a copy of the array behind on the
+/// stack once the buffer has been used.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::secure_box_zeroed;
+///
+/// let mut table = secure_box_zeroed::<[u8; 1 << 16]>()?;
+/// table[..4].copy_from_slice(b"key!");
+/// assert!(table[4..].iter().all(|&b| b == 0));
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+///
+/// Returns [`AllocError`] if the memory could not be allocated.
+pub fn secure_box_zeroed<T: Zeroable>() -> Result<SecureBox<T>, AllocError> {
+    let zeroed = Box::try_new_zeroed_in(SodiumAllocator)?;
+    // SAFETY: `T: Zeroable`, so a zeroed `T` is valid.
+    Ok(unsafe { zeroed.assume_init() })
+}
+
+/// Replace the secret in `slot` with `new`, freeing (and therefore zeroing) the old secret.
+///
+/// Only the box pointers are swapped: Neither secret is moved out of its guarded allocation, so
+/// unlike moving a secret out with [`std::mem::replace`], no unguarded copy of either is made. The
+/// old secret is dropped in place, and its memory is zeroed by Sodium as it is freed.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::{secure_replace, SodiumAllocator};
+///
+/// let mut key = Box::new_in([0x13u8; 32], SodiumAllocator);
+/// secure_replace(&mut key, Box::new_in([0x37; 32], SodiumAllocator));
+/// assert_eq!(*key, [0x37; 32]);
+/// ```
+pub fn secure_replace<T: ?Sized>(slot: &mut SecureBox<T>, new: SecureBox<T>) {
+    drop(std::mem::replace(slot, new));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clone_distinct_allocation() -> Result<(), AllocError> {
+        let original = Box::new_in([0x13u8; 32], SodiumAllocator);
+        let clone = clone_secure(&original)?;
+
+        assert_eq!(*original, *clone);
+        assert_ne!(original.as_ptr(), clone.as_ptr());
+
+        Ok(())
+    }
+
+    #[test]
+    fn clone_non_copy() -> Result<(), AllocError> {
+        let original = Box::new_in(String::from("hunter2"), SodiumAllocator);
+        let clone = clone_secure(&original)?;
+
+        assert_eq!(*original, *clone);
+        assert!(!std::ptr::eq(&*original, &*clone));
+
+        Ok(())
+    }
+
+    #[test]
+    fn zeroed_without_stack_copy() {
+        const LEN: usize = 1 << 20;
+
+        // The thread's stack is much smaller than the array, so this would overflow the stack if
+        // the array were constructed on it first.
+        let all_zero = std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(|| {
+                let mut buf = secure_box_zeroed::<[u8; LEN]>().unwrap();
+                let zeroed = buf.iter().all(|&b| b == 0);
+                buf[LEN - 1] = 0xff;
+                zeroed && buf[LEN - 1] == 0xff
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert!(all_zero);
+    }
+
+    trait Secret {
+        fn expose(&self) -> &[u8];
+    }
+
+    struct Key([u8; 32]);
+
+    impl Secret for Key {
+        fn expose(&self) -> &[u8] {
+            &self.0
+        }
+    }
+
+    struct Password(String);
+
+    impl Secret for Password {
+        fn expose(&self) -> &[u8] {
+            self.0.as_bytes()
+        }
+    }
+
+    #[test]
+    fn trait_objects() {
+        let secrets: Vec<SecureBox<dyn Secret>> = vec![
+            Box::new_in(Key([0x13; 32]), SodiumAllocator),
+            Box::new_in(Password(String::from("hunter2")), SodiumAllocator),
+        ];
+
+        assert_eq!(secrets[0].expose(), &[0x13; 32]);
+        assert_eq!(secrets[1].expose(), b"hunter2");
+
+        #[cfg(feature = "track-allocations")]
+        {
+            use crate::tracking::is_live;
+
+            let ptrs: Vec<_> = secrets.iter().map(|s| &**s as *const dyn Secret).collect();
+            assert!(ptrs.iter().all(|&p| is_live(p as *const u8)));
+            drop(secrets);
+            assert!(ptrs.iter().all(|&p| !is_live(p as *const u8)));
+        }
+    }
+
+    /// Counts the number of times values of this type are dropped.
+    struct DropCounter<'a>(&'a std::cell::Cell<usize>);
+
+    impl Drop for DropCounter<'_> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn replace_frees_old_once() {
+        let old_drops = std::cell::Cell::new(0);
+        let new_drops = std::cell::Cell::new(0);
+
+        let mut slot = Box::new_in(DropCounter(&old_drops), SodiumAllocator);
+        let new = Box::new_in(DropCounter(&new_drops), SodiumAllocator);
+        let old_ptr = &*slot as *const DropCounter;
+        let new_ptr = &*new as *const DropCounter;
+
+        secure_replace(&mut slot, new);
+        assert_eq!(old_drops.get(), 1);
+        assert_eq!(new_drops.get(), 0);
+        // The new value wasn't moved out of its allocation
+        assert!(std::ptr::eq(&*slot, new_ptr));
+
+        #[cfg(feature = "track-allocations")]
+        {
+            use crate::tracking::is_live;
+
+            assert!(!is_live(old_ptr));
+            assert!(is_live(new_ptr));
+        }
+        #[cfg(not(feature = "track-allocations"))]
+        let _ = old_ptr;
+
+        drop(slot);
+        assert_eq!(old_drops.get(), 1);
+        assert_eq!(new_drops.get(), 1);
+    }
+
+    #[test]
+    fn replace_trait_object() {
+        let mut secret: SecureBox<dyn Secret> = Box::new_in(Key([0x13; 32]), SodiumAllocator);
+        secure_replace(
+            &mut secret,
+            Box::new_in(Password(String::from("hunter2")), SodiumAllocator),
+        );
+        assert_eq!(secret.expose(), b"hunter2");
+    }
+}
@@ -0,0 +1,150 @@
+//! Limiting the total amount of memory allocated using Sodium.
+
+use crate::page::{allocation_footprint, page_size};
+use crate::{malloc_size, SodiumAllocator};
+use std::alloc::{AllocError, Allocator, Layout};
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// An [`Allocator`] which allocates memory using [`SodiumAllocator`], but refuses to allocate
+/// more than a fixed budget of memory in total.
+///
+/// The budget accounts for the real amount of memory Sodium reserves for each allocation, rather
+/// than the requested size: Each allocation is rounded up to a whole number of pages, including
+/// space for a canary, and three additional pages are reserved for guard pages and bookkeeping.
+/// This allows the total amount of memory used for secrets to be bounded precisely.
+///
+/// Allocations are made through a reference to the budget, so the same budget can be shared
+/// between many containers:
+///
+/// ```
+/// #![feature(allocator_api)]
+///
+/// use sodium_alloc::MemoryBudget;
+///
+/// let budget = MemoryBudget::new(1 << 20);
+/// let key = Box::new_in([0u8; 32], &budget);
+/// assert!(budget.used() > 0);
+/// ```
+#[derive(Debug)]
+pub struct MemoryBudget {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+impl MemoryBudget {
+    /// Create a new `MemoryBudget`, which will allow at most `limit` bytes of memory to be
+    /// allocated at any one time.
+    pub const fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// The maximum number of bytes which can be allocated at once.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// The number of bytes currently allocated from this budget.
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// The number of bytes which could still be allocated from this budget.
+    pub fn remaining(&self) -> usize {
+        self.limit - self.used()
+    }
+}
+
+/// Calculate the cost of an allocation with the given layout against the budget.
+fn cost(layout: Layout) -> Result<usize, AllocError> {
+    // Zero-size allocations aren't made using Sodium.
+    if layout.size() == 0 {
+        return Ok(0);
+    }
+    let size = malloc_size(layout)?;
+    size.checked_add(page_size() * 4)
+        .map(|_| allocation_footprint(size))
+        .ok_or(AllocError)
+}
+
+unsafe impl Allocator for MemoryBudget {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let cost = cost(layout)?;
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(cost).filter(|&total| total <= self.limit)
+            })
+            .map_err(|_| AllocError)?;
+
+        SodiumAllocator.allocate(layout).inspect_err(|_| {
+            self.used.fetch_sub(cost, Ordering::Relaxed);
+        })
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        SodiumAllocator.deallocate(ptr, layout);
+
+        // `layout` must fit the allocation, so it has the same cost as the original layout, and
+        // calculating it can't fail.
+        if let Ok(cost) = cost(layout) {
+            self.used.fetch_sub(cost, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_exceeded() {
+        // Each small allocation consumes 4 pages, so this budget allows exactly 3 allocations.
+        let budget = MemoryBudget::new(page_size() * 12);
+        let mut keys = Vec::new();
+
+        for _ in 0..3 {
+            keys.push(Box::try_new_in([0xffu8; 32], &budget).unwrap());
+        }
+        assert_eq!(budget.used(), page_size() * 12);
+        assert_eq!(budget.remaining(), 0);
+
+        assert!(Box::try_new_in([0xffu8; 32], &budget).is_err());
+        assert_eq!(budget.used(), page_size() * 12);
+
+        keys.pop();
+        assert_eq!(budget.used(), page_size() * 8);
+        keys.push(Box::try_new_in([0xffu8; 32], &budget).unwrap());
+
+        drop(keys);
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn budget_accounts_for_growth() {
+        let budget = MemoryBudget::new(page_size() * 8);
+        let mut vec: Vec<u8, _> = Vec::new_in(&budget);
+
+        vec.extend_from_slice(&[0; 32]);
+        assert_eq!(budget.used(), page_size() * 4);
+
+        // Growing the vector into a second data page requires a 5 page allocation, which
+        // wouldn't fit in the budget alongside the existing allocation.
+        assert!(vec.try_reserve_exact(page_size()).is_err());
+        assert_eq!(budget.used(), page_size() * 4);
+
+        drop(vec);
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn oversized_allocation() {
+        let budget = MemoryBudget::new(usize::MAX);
+        assert!(budget
+            .allocate(Layout::from_size_align(isize::MAX as usize, 1).unwrap())
+            .is_err());
+        assert_eq!(budget.used(), 0);
+    }
+}
@@ -0,0 +1,49 @@
+//! C API for allocating guarded memory from foreign code.
+//!
+//! This module is only available with the `capi` feature enabled. It exports the following
+//! functions with an unmangled name and C calling convention, so that C code linked against a
+//! library built with this crate can allocate and free memory with Sodium's guarded allocation
+//! functions, sharing the same semantics as [`SodiumAllocator`](crate::SodiumAllocator):
+//!
+//! ```c
+//! unsigned char *sodium_alloc_guarded(size_t size);
+//! void sodium_alloc_free(unsigned char *ptr);
+//! ```
+//!
+//! Neither function will ever unwind across the FFI boundary.
+
+use crate::{init, sys};
+use std::ptr;
+
+/// Allocate `size` bytes of guarded memory.
+///
+/// libsodium is initialised if necessary. Returns a pointer to the allocated memory, or NULL if
+/// libsodium could not be initialised, or the memory could not be allocated. The returned memory
+/// is not aligned to any particular boundary, and its contents are unspecified.
+///
+/// If `size` is zero, a unique non-NULL pointer is returned, which must not be dereferenced, but
+/// should still be freed with [`sodium_alloc_free`].
+#[no_mangle]
+pub extern "C" fn sodium_alloc_guarded(size: usize) -> *mut u8 {
+    if init().is_err() {
+        return ptr::null_mut();
+    }
+
+    // SAFETY: `sodium_malloc` is safe to call with any size once libsodium has been initialised,
+    // it will return NULL if the memory can't be allocated.
+    unsafe { sys::malloc(size) }
+}
+
+/// Free memory allocated with [`sodium_alloc_guarded`].
+///
+/// The memory is securely zeroed before it is freed. If `ptr` is NULL, this function does
+/// nothing.
+///
+/// # Safety
+/// `ptr` must be NULL, or a pointer returned by [`sodium_alloc_guarded`] which has not already
+/// been freed.
+#[no_mangle]
+pub unsafe extern "C" fn sodium_alloc_free(ptr: *mut u8) {
+    // `sodium_free` is defined to do nothing if passed NULL.
+    sys::free(ptr);
+}
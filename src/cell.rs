@@ -0,0 +1,92 @@
+//! Interior mutability for secrets stored in memory allocated using Sodium.
+
+use crate::SodiumAllocator;
+use std::alloc::AllocError;
+use std::cell::{Ref, RefCell, RefMut};
+
+/// A mutable memory location with dynamically checked borrow rules, like a [`RefCell`], stored in
+/// memory allocated using [`SodiumAllocator`].
+///
+/// This allows a secret to be modified in place through a shared reference in single-threaded
+/// code, for example when incrementing a counter-based nonce. The value (along with the borrow
+/// flag) lives in a single guarded allocation, which is securely zeroed when the `SecureCell` is
+/// dropped.
+///
+/// ```
+/// use sodium_alloc::SecureCell;
+///
+/// let nonce = SecureCell::new(0u64)?;
+/// *nonce.borrow_mut() += 1;
+/// assert_eq!(*nonce.borrow(), 1);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct SecureCell<T>(Box<RefCell<T>, SodiumAllocator>);
+
+impl<T> SecureCell<T> {
+    /// Create a new `SecureCell` containing `value`.
+    ///
+    /// `value` is moved into guarded memory, so a copy of it may remain where it was stored
+    /// before, as explained on [`SecureBox`](crate::SecureBox#moving-values-into-guarded-memory).
+    ///
+    /// Returns [`AllocError`] if the memory could not be allocated.
+    pub fn new(value: T) -> Result<Self, AllocError> {
+        Ok(Self(Box::try_new_in(RefCell::new(value), SodiumAllocator)?))
+    }
+
+    /// Immutably borrow the wrapped value.
+    ///
+    /// The borrow lasts until the returned [`Ref`] is dropped. Multiple immutable borrows can be
+    /// taken out at the same time.
+    ///
+    /// # Panics
+    /// Panics if the value is currently mutably borrowed.
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.0.borrow()
+    }
+
+    /// Mutably borrow the wrapped value.
+    ///
+    /// The borrow lasts until the returned [`RefMut`] is dropped. The value cannot be borrowed
+    /// again while this borrow is active.
+    ///
+    /// # Panics
+    /// Panics if the value is currently borrowed.
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        self.0.borrow_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn increment_in_place() -> Result<(), AllocError> {
+        let counter = SecureCell::new([0u8; 12])?;
+        let shared = &counter;
+
+        for _ in 0..300 {
+            let mut nonce = shared.borrow_mut();
+            for b in nonce.iter_mut() {
+                *b = b.wrapping_add(1);
+                if *b != 0 {
+                    break;
+                }
+            }
+        }
+
+        let nonce = counter.borrow();
+        assert_eq!(nonce[..2], [44, 1]);
+        assert_eq!(counter.borrow()[..2], [44, 1]);
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn double_mutable_borrow() {
+        let cell = SecureCell::new(0u8).unwrap();
+        let _a = cell.borrow_mut();
+        let _b = cell.borrow_mut();
+    }
+}
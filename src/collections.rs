@@ -0,0 +1,90 @@
+//! Ordered collections whose nodes are stored in memory allocated using Sodium.
+
+use crate::SodiumAllocator;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A [`BTreeMap`] whose nodes are allocated using [`SodiumAllocator`].
+///
+/// The keys and values are stored inline in the tree's nodes, so every entry lives in guarded
+/// memory. All node allocations go through `SodiumAllocator`, including those made while
+/// rebalancing the tree: Entries are only ever moved between guarded nodes, and any node which is
+/// freed (for example, when two nodes are merged after a removal) is zeroed by Sodium.
+///
+/// Create one using [`secure_btree_map`]. Note that keys and values which own heap memory of
+/// their own (such as `String`) store that memory outside the tree, so should themselves use
+/// guarded memory, for example by using [`SecureVec`](crate::SecureVec) keys.
+///
+/// Each node is a separate guarded allocation, which consumes several pages of memory (see
+/// [`SodiumAllocator`]), and each node holds at most 11 entries. Large maps therefore use a lot of
+/// locked memory: This is best suited to maps with a modest number of entries.
+///
+/// ```
+/// use sodium_alloc::secure_btree_map;
+///
+/// let mut keys = secure_btree_map();
+/// keys.insert(1u32, [0x13u8; 32]);
+/// keys.insert(2, [0x37; 32]);
+/// assert_eq!(keys[&2], [0x37; 32]);
+/// ```
+pub type SecureBTreeMap<K, V> = BTreeMap<K, V, SodiumAllocator>;
+
+/// A [`BTreeSet`] whose nodes are allocated using [`SodiumAllocator`].
+///
+/// Create one using [`secure_btree_set`]. See [`SecureBTreeMap`] for details.
+pub type SecureBTreeSet<T> = BTreeSet<T, SodiumAllocator>;
+
+/// Create a new, empty [`SecureBTreeMap`].
+///
+/// No memory is allocated until the first entry is inserted.
+pub fn secure_btree_map<K, V>() -> SecureBTreeMap<K, V> {
+    BTreeMap::new_in(SodiumAllocator)
+}
+
+/// Create a new, empty [`SecureBTreeSet`].
+///
+/// No memory is allocated until the first value is inserted.
+pub fn secure_btree_set<T>() -> SecureBTreeSet<T> {
+    BTreeSet::new_in(SodiumAllocator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_and_remove() {
+        let mut map = secure_btree_map();
+        // Enough entries to need internal nodes, and rebalancing on removal
+        for i in 0..1000u32 {
+            assert_eq!(map.insert(i, [i as u8; 16]), None);
+        }
+
+        #[cfg(feature = "track-allocations")]
+        assert!(crate::stats().total.live_allocations >= 1000 / 11);
+
+        for i in (0..1000).step_by(2) {
+            assert_eq!(map.remove(&i), Some([i as u8; 16]));
+        }
+
+        assert_eq!(map.len(), 500);
+        assert!(map.keys().copied().eq((1..1000).step_by(2)));
+        assert!(map.iter().all(|(&k, v)| *v == [k as u8; 16]));
+
+        map.retain(|&k, _| k < 100);
+        assert_eq!(map.len(), 50);
+        map.clear();
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn set_operations() {
+        let mut set = secure_btree_set();
+        set.extend([5u8, 3, 9, 1, 3]);
+        assert!(set.iter().copied().eq([1, 3, 5, 9]));
+
+        assert!(set.remove(&3));
+        assert!(!set.contains(&3));
+        assert_eq!(set.first(), Some(&1));
+        assert_eq!(set.last(), Some(&9));
+    }
+}
@@ -0,0 +1,618 @@
+//! Allocators with configurable behaviour.
+
+use crate::{malloc_size, memlock, memzero, padded_size, sodium_base, sys, SodiumAllocator};
+use std::alloc::{AllocError, Allocator, Layout};
+use std::ptr::NonNull;
+
+/// An [`Allocator`] which allocates memory using Sodium, like [`SodiumAllocator`], with
+/// additional configurable behaviour.
+///
+/// A `ConfiguredAllocator` created with [`ConfiguredAllocator::new`] (or
+/// [`SodiumAllocator::configure`]) behaves identically to `SodiumAllocator`. Options can then be
+/// enabled using the builder methods on this type:
+///
+/// ```
+/// #![feature(allocator_api)]
+///
+/// use sodium_alloc::SodiumAllocator;
+///
+/// let allocator = SodiumAllocator::configure().antiforensic(true);
+/// let key = Box::new_in([0xca, 0xfe, 0xba, 0xbe], allocator);
+/// ```
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ConfiguredAllocator {
+    antiforensic: bool,
+    mlock: bool,
+    zero_on_alloc: bool,
+    #[cfg(target_os = "linux")]
+    wipe_on_fork: bool,
+    #[cfg(target_os = "linux")]
+    dont_fork: bool,
+    #[cfg(feature = "log")]
+    warn_above: Option<usize>,
+    #[cfg(unix)]
+    enforce_memlock_limit: bool,
+    #[cfg(all(target_os = "linux", feature = "anti-debug"))]
+    anti_debug: bool,
+}
+
+impl SodiumAllocator {
+    /// Create a [`ConfiguredAllocator`], which can be used to customise the behaviour of the
+    /// allocator.
+    pub const fn configure() -> ConfiguredAllocator {
+        ConfiguredAllocator::new()
+    }
+
+    /// Create a [`ConfiguredAllocator`] which doesn't lock allocated memory.
+    ///
+    /// This is equivalent to `SodiumAllocator::configure().mlock(false)`, see
+    /// [`ConfiguredAllocator::mlock`].
+    pub const fn without_mlock() -> ConfiguredAllocator {
+        ConfiguredAllocator::new().mlock(false)
+    }
+
+    /// Create a [`ConfiguredAllocator`] which zeroes every allocation before returning it.
+    ///
+    /// This is equivalent to `SodiumAllocator::configure().zero_on_alloc(true)`, see
+    /// [`ConfiguredAllocator::zero_on_alloc`].
+    pub const fn zero_on_alloc() -> ConfiguredAllocator {
+        ConfiguredAllocator::new().zero_on_alloc(true)
+    }
+}
+
+impl ConfiguredAllocator {
+    /// Create a new `ConfiguredAllocator`, with the default behaviour of [`SodiumAllocator`].
+    pub const fn new() -> Self {
+        Self {
+            antiforensic: false,
+            mlock: true,
+            zero_on_alloc: false,
+            #[cfg(target_os = "linux")]
+            wipe_on_fork: false,
+            #[cfg(target_os = "linux")]
+            dont_fork: false,
+            #[cfg(feature = "log")]
+            warn_above: None,
+            #[cfg(unix)]
+            enforce_memlock_limit: false,
+            #[cfg(all(target_os = "linux", feature = "anti-debug"))]
+            anti_debug: false,
+        }
+    }
+
+    /// Set whether memory should be overwritten with random data before it is freed.
+    ///
+    /// Sodium always zeroes memory when it is freed. With this option enabled, the memory will
+    /// first be overwritten with random data from `randombytes_buf`, then zeroed and freed as
+    /// usual. This is intended as a defense-in-depth measure against memory remanence analysis,
+    /// and makes freeing memory noticeably more expensive, especially for large allocations.
+    ///
+    /// Defaults to `false`.
+    pub const fn antiforensic(mut self, enabled: bool) -> Self {
+        self.antiforensic = enabled;
+        self
+    }
+
+    /// Set whether allocated memory should be locked, preventing it from being swapped to disk.
+    ///
+    /// Sodium always attempts to lock its allocations using `mlock` (or `VirtualLock` on
+    /// Windows), but allocation still succeeds if locking fails, for example because the
+    /// `RLIMIT_MEMLOCK` limit has been reached, or a sandbox denies the system call. With this
+    /// option disabled, memory is unlocked again immediately after it is allocated, so it never
+    /// counts against the locked memory limit. This is intended for environments where locking
+    /// memory is forbidden, or where locked memory is a scarce resource.
+    ///
+    /// **Without locking, secrets may be written to swap space**, and so may persist on disk long
+    /// after they are freed. All other protections (guard pages, the canary, excluding the memory
+    /// from core dumps where supported, and zeroing on free) still apply.
+    ///
+    /// Note that because Sodium always attempts to lock memory, this can't help in a sandbox which
+    /// terminates the process on any call to `mlock`, rather than returning an error.
+    ///
+    /// Defaults to `true`.
+    pub const fn mlock(mut self, enabled: bool) -> Self {
+        self.mlock = enabled;
+        self
+    }
+
+    /// Set whether memory should be zeroed when it is allocated.
+    ///
+    /// Sodium always zeroes memory when it is freed, but fills new allocations with garbage bytes
+    /// (`0xdb`), and only [`Allocator::allocate_zeroed`] returns zeroed memory. Which of the two
+    /// methods is called depends on the container. With this option enabled, every allocation is
+    /// zeroed before it is returned, regardless of how it was requested. This is a defensive
+    /// measure: Code which reads memory before initialising it (for example, by misusing
+    /// [`Vec::set_len`]) will then read zeroes, rather than garbage or data left behind by other
+    /// code, at the cost of zeroing every allocation.
+    ///
+    /// Note that this only applies when memory is first allocated. Memory which has been written
+    /// and is then reused within an allocation (such as a `Vec`'s spare capacity after
+    /// [`Vec::truncate`]) is unaffected, see [`SecureVecExt`](crate::SecureVecExt).
+    ///
+    /// Defaults to `false`.
+    pub const fn zero_on_alloc(mut self, enabled: bool) -> Self {
+        self.zero_on_alloc = enabled;
+        self
+    }
+
+    /// Set whether allocations should be zeroed in child processes created by `fork`.
+    ///
+    /// With this option enabled, allocated memory is marked with `MADV_WIPEONFORK`, so any child
+    /// process created by `fork` will see zeroes in place of the parent's secrets. Allocation will
+    /// fail if the kernel doesn't support `MADV_WIPEONFORK` (it was added in Linux 4.14).
+    ///
+    /// Note that Sodium's canary is also zeroed in the child. Freeing an allocation in the child
+    /// process will therefore be detected as a corrupted canary, and terminate the child: The
+    /// child must not free memory allocated by the parent with this option.
+    ///
+    /// Only available on Linux. Defaults to `false`.
+    #[cfg(target_os = "linux")]
+    pub const fn wipe_on_fork(mut self, enabled: bool) -> Self {
+        self.wipe_on_fork = enabled;
+        self
+    }
+
+    /// Set whether allocations should be unmapped in child processes created by `fork`.
+    ///
+    /// With this option enabled, allocated memory is marked with `MADV_DONTFORK`, so the pages
+    /// holding each allocation are not mapped at all in any child process created by `fork`. Any
+    /// attempt by the child to access the memory will fault, terminating the child, rather than
+    /// reading zeroes (as with [`wipe_on_fork`](Self::wipe_on_fork)) or the parent's secrets.
+    /// This suits designs where child processes should never touch the parent's secrets.
+    /// Allocation will fail if `madvise` fails.
+    ///
+    /// As with `wipe_on_fork`, the child must not free memory allocated by the parent with this
+    /// option.
+    ///
+    /// Only available on Linux. Defaults to `false`.
+    #[cfg(target_os = "linux")]
+    pub const fn dont_fork(mut self, enabled: bool) -> Self {
+        self.dont_fork = enabled;
+        self
+    }
+
+    /// Log a warning whenever a single allocation larger than `bytes` is made.
+    ///
+    /// Guarded memory is intended for small secrets such as keys and passwords, and large
+    /// allocations quickly exhaust the locked memory limit. An allocation of more than a megabyte
+    /// or so is usually a sign that a general-purpose buffer has been routed through this
+    /// allocator by mistake. With this option set, such allocations still succeed, but a warning
+    /// is logged via the [`log`](https://docs.rs/log) crate, to help track them down.
+    ///
+    /// Only available with the `log` feature enabled. Defaults to never warning.
+    #[cfg(feature = "log")]
+    pub const fn warn_above(mut self, bytes: usize) -> Self {
+        self.warn_above = Some(bytes);
+        self
+    }
+
+    /// Set whether allocations which would exceed the locked memory limit should fail.
+    ///
+    /// Sodium ignores failure to lock memory, so once the `RLIMIT_MEMLOCK` limit has been reached,
+    /// further allocations still succeed, but are silently left unlocked, and may be swapped to
+    /// disk. With this option enabled, the allocator instead checks each allocation against the
+    /// limit before making it, using the total memory locked by live allocations (see
+    /// [`locked_bytes`](crate::locked_bytes)), and returns [`AllocError`] if the allocation
+    /// wouldn't fit. This makes running out of locked memory a predictable, recoverable error.
+    ///
+    /// Only memory locked by this crate's allocators is counted, so memory locked by other code in
+    /// the process may still cause the limit to be reached first. Concurrent allocations are
+    /// checked independently, so may together slightly exceed the limit. This option has no
+    /// effect if [`mlock`](Self::mlock) is disabled.
+    ///
+    /// Only available on Unix platforms. Defaults to `false`.
+    #[cfg(unix)]
+    pub const fn enforce_memlock_limit(mut self, enabled: bool) -> Self {
+        self.enforce_memlock_limit = enabled;
+        self
+    }
+
+    /// Set whether allocation should fail if the process is being traced, for example by a
+    /// debugger.
+    ///
+    /// A process attached with `ptrace` (as debuggers and tools like `strace` are) can read all of
+    /// the traced process's memory, including guarded memory, so for the most sensitive
+    /// deployments it may be preferable to refuse to handle secrets at all. With this option
+    /// enabled, the `TracerPid` field of `/proc/self/status` is checked before every allocation,
+    /// and [`AllocError`] is returned if it is non-zero, or if it can't be read.
+    ///
+    /// This is a defense-in-depth measure with significant limitations:
+    ///
+    /// * The check is only made when allocating: A tracer attached afterwards can still read
+    ///   secrets which have already been allocated.
+    /// * Only `ptrace` is detected. A sufficiently privileged attacker can read the process's
+    ///   memory in other ways (e.g: through `/proc/<pid>/mem` as root, or from the kernel).
+    /// * The check reads a file on every allocation, making allocation noticeably slower, and
+    ///   fails closed, so allocation always fails if `/proc` isn't mounted.
+    ///
+    /// Only available on Linux, with the `anti-debug` feature enabled. Defaults to `false`.
+    #[cfg(all(target_os = "linux", feature = "anti-debug"))]
+    pub const fn anti_debug(mut self, enabled: bool) -> Self {
+        self.anti_debug = enabled;
+        self
+    }
+}
+
+impl Default for ConfiguredAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Get the start address and length of the page-aligned region of memory which Sodium locked for
+/// the allocation at `ptr`, made for `layout`.
+///
+/// This covers the whole block returned by `sodium_malloc`, which for an over-aligned allocation
+/// starts before `ptr` (see [`sodium_base`]). We round out to the start of the page containing
+/// the canary before the block. Sodium places the end of the block at the end of a page. The
+/// region lies entirely within the unprotected pages Sodium allocated for `ptr`, and its length is
+/// [`memlock::locked_size`].
+///
+/// # Safety
+/// `ptr` must be a live allocation made by [`SodiumAllocator`] for `layout`.
+unsafe fn data_pages(ptr: NonNull<[u8]>, layout: Layout) -> (*mut u8, usize) {
+    use crate::page::{page_size, CANARY_SIZE};
+
+    let base = sodium_base(ptr.cast(), layout) as usize;
+    // The layout was already validated when it was allocated.
+    let end = base + malloc_size(layout).unwrap_or(ptr.len());
+    let start = (base - CANARY_SIZE) & !(page_size() - 1);
+
+    (start as *mut u8, end - start)
+}
+
+/// Returns whether the process is currently being traced, according to `/proc/self/status`, or
+/// `None` if this couldn't be determined.
+#[cfg(all(target_os = "linux", feature = "anti-debug"))]
+fn is_traced() -> Option<bool> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    tracer_pid(&status).map(|pid| pid != 0)
+}
+
+/// Parse the `TracerPid` field of the contents of `/proc/<pid>/status`.
+#[cfg(all(target_os = "linux", feature = "anti-debug"))]
+fn tracer_pid(status: &str) -> Option<u32> {
+    let line = status.lines().find(|l| l.starts_with("TracerPid:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Apply `advice` to the pages of memory containing the allocation at `ptr`, made for `layout`,
+/// using `madvise`.
+///
+/// Returns [`AllocError`] if `madvise` failed.
+///
+/// # Safety
+/// `ptr` must be a live allocation made by [`SodiumAllocator`] for `layout`.
+#[cfg(target_os = "linux")]
+unsafe fn advise(
+    ptr: NonNull<[u8]>,
+    layout: Layout,
+    advice: libc::c_int,
+) -> Result<(), AllocError> {
+    #[cfg(test)]
+    if tests::FAIL_ADVICE.with(|f| f.get()) {
+        return Err(AllocError);
+    }
+
+    let (start, len) = data_pages(ptr, layout);
+
+    // SAFETY: This range lies entirely within the unprotected pages Sodium allocated for `ptr`.
+    // `madvise` with these advice values doesn't affect the contents of memory in this process.
+    match unsafe { libc::madvise(start as *mut libc::c_void, len, advice) } {
+        0 => Ok(()),
+        _ => Err(AllocError),
+    }
+}
+
+unsafe impl Allocator for ConfiguredAllocator {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        // Zero-size blocks aren't backed by any memory, so there's nothing to configure.
+        if layout.size() == 0 {
+            return SodiumAllocator.allocate(layout);
+        }
+
+        #[cfg(feature = "log")]
+        if self.warn_above.is_some_and(|limit| layout.size() > limit) {
+            log::warn!(
+                "allocating {} bytes of guarded memory, which is unusually large for a secret",
+                layout.size()
+            );
+
+            #[cfg(test)]
+            tests::record_warning(layout.size());
+        }
+
+        #[cfg(unix)]
+        if self.enforce_memlock_limit && self.mlock && memlock::exceeds_limit(layout) {
+            return Err(AllocError);
+        }
+
+        #[cfg(all(target_os = "linux", feature = "anti-debug"))]
+        if self.anti_debug && is_traced() != Some(false) {
+            return Err(AllocError);
+        }
+
+        let ptr = SodiumAllocator.allocate(layout)?;
+
+        if !self.mlock {
+            // SAFETY: We just allocated this memory with this layout. `start` is page-aligned, and
+            // the region lies within the allocation.
+            unsafe {
+                let (start, len) = data_pages(ptr, layout);
+                sys::munlock(start, len);
+            }
+            memlock::record_unlocked(memlock::locked_size(layout));
+        }
+
+        #[cfg(target_os = "linux")]
+        for (enabled, advice) in [
+            (self.wipe_on_fork, libc::MADV_WIPEONFORK),
+            (self.dont_fork, libc::MADV_DONTFORK),
+        ] {
+            if !enabled {
+                continue;
+            }
+            // SAFETY: We just allocated this memory with this layout.
+            if let Err(e) = unsafe { advise(ptr, layout, advice) } {
+                // Free the memory through `self`, which restores the locked byte count if we
+                // unlocked the memory above.
+                // SAFETY: We just allocated this memory with this layout.
+                unsafe { self.deallocate(ptr.cast(), layout) };
+                return Err(e);
+            }
+        }
+
+        if self.zero_on_alloc {
+            // SAFETY: We just allocated `ptr.len()` bytes at `ptr`, and Sodium has initialised
+            // them.
+            memzero(unsafe { &mut *ptr.as_ptr() });
+        }
+
+        Ok(ptr)
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = self.allocate(layout)?;
+
+        // Avoid zeroing the memory twice.
+        if !self.zero_on_alloc {
+            // SAFETY: We just allocated `ptr.len()` bytes at `ptr`, and Sodium has initialised
+            // them.
+            memzero(unsafe { &mut *ptr.as_ptr() });
+        }
+
+        Ok(ptr)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() == 0 {
+            return SodiumAllocator.deallocate(ptr, layout);
+        }
+
+        if self.antiforensic {
+            // `layout` must fit the allocation, so we know this succeeded when it was allocated.
+            if let Ok(size) = padded_size(layout.size(), layout.align()) {
+                let buf = std::slice::from_raw_parts_mut(ptr.as_ptr(), size);
+                // libsodium must already have been initialised to allocate `ptr`.
+                sys::randombytes(buf);
+
+                #[cfg(test)]
+                tests::record_fill(buf);
+            }
+        }
+
+        if !self.mlock {
+            // `SodiumAllocator` counts the memory as locked until it is freed, but we unlocked it
+            // when it was allocated.
+            memlock::record_locked(memlock::locked_size(layout));
+        }
+
+        SodiumAllocator.deallocate(ptr, layout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+
+    thread_local! {
+        /// Set to make `advise` fail on this thread.
+        pub(super) static FAIL_ADVICE: Cell<bool> = const { Cell::new(false) };
+    }
+
+    thread_local! {
+        /// The contents of the last buffer overwritten with random data on this thread.
+        static LAST_FILL: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+    }
+
+    pub(super) fn record_fill(buf: &[u8]) {
+        LAST_FILL.with(|f| *f.borrow_mut() = Some(buf.to_vec()));
+    }
+
+    fn take_last_fill() -> Option<Vec<u8>> {
+        LAST_FILL.with(|f| f.borrow_mut().take())
+    }
+
+    #[cfg(feature = "log")]
+    thread_local! {
+        /// The sizes of the allocations which triggered a warning on this thread.
+        static WARNINGS: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+    }
+
+    #[cfg(feature = "log")]
+    pub(super) fn record_warning(size: usize) {
+        WARNINGS.with(|w| w.borrow_mut().push(size));
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn warn_above_threshold() {
+        let allocator = SodiumAllocator::configure().warn_above(1 << 20);
+
+        let small = Box::new_in([0u8; 1024], allocator);
+        let mut exact = Vec::with_capacity_in(1 << 20, allocator);
+        exact.push(0u8);
+        assert!(WARNINGS.with(|w| w.borrow().is_empty()));
+
+        let mut large = Vec::with_capacity_in((1 << 20) + 1, allocator);
+        large.push(0u8);
+        assert_eq!(WARNINGS.with(|w| w.take()), [(1 << 20) + 1]);
+
+        drop((small, exact, large));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn advise_failure_restores_locked_bytes() {
+        let layout = Layout::new::<[u8; 32]>();
+        for allocator in [
+            SodiumAllocator::without_mlock().wipe_on_fork(true),
+            SodiumAllocator::without_mlock().dont_fork(true),
+            SodiumAllocator::configure().wipe_on_fork(true),
+        ] {
+            let before = crate::memlock::tests::thread_delta();
+            FAIL_ADVICE.with(|f| f.set(true));
+            let result = allocator.allocate(layout);
+            FAIL_ADVICE.with(|f| f.set(false));
+
+            assert_eq!(result, Err(AllocError));
+            assert_eq!(crate::memlock::tests::thread_delta(), before);
+            // Subtracting the same allocation twice would wrap the global count around
+            assert!(memlock::locked_bytes() <= isize::MAX as usize);
+        }
+    }
+
+    #[test]
+    fn default_matches_sodium_allocator() {
+        assert_eq!(SodiumAllocator::configure(), ConfiguredAllocator::default());
+
+        let mut vec = Vec::new_in(ConfiguredAllocator::new());
+        vec.extend_from_slice(&[0u8; 4096]);
+        drop(vec);
+        assert_eq!(take_last_fill(), None);
+    }
+
+    #[test]
+    fn antiforensic_fills_before_free() {
+        let allocator = SodiumAllocator::configure().antiforensic(true);
+
+        let mut vec = Vec::with_capacity_in(4096, allocator);
+        vec.extend_from_slice(&[0u8; 4096]);
+        drop(vec);
+
+        let fill = take_last_fill().expect("memory wasn't overwritten before free");
+        assert_eq!(fill.len(), 4096);
+        // The chances of 4096 random bytes all being zero are negligible
+        assert!(fill.iter().any(|&b| b != 0));
+    }
+
+    #[test]
+    fn zero_on_alloc() {
+        let allocator = SodiumAllocator::zero_on_alloc();
+        assert_eq!(allocator, SodiumAllocator::configure().zero_on_alloc(true));
+
+        for len in [1, 100, 4096, 10000] {
+            // `Vec::with_capacity_in` calls `allocate`, not `allocate_zeroed`
+            let mut vec = Vec::<u8, _>::with_capacity_in(len, allocator);
+            // SAFETY: The allocator zeroed the whole capacity, so every byte is initialised.
+            unsafe { vec.set_len(len) };
+            assert!(vec.iter().all(|&b| b == 0));
+        }
+
+        let key = Box::<[u8; 64], _>::try_new_zeroed_in(allocator).unwrap();
+        // SAFETY: The memory was zeroed, and zero is a valid `u8`.
+        assert_eq!(*unsafe { key.assume_init() }, [0; 64]);
+
+        // Without the option, `allocate_zeroed` still zeroes
+        let key = Box::<[u8; 64], _>::try_new_zeroed_in(ConfiguredAllocator::new()).unwrap();
+        assert_eq!(*unsafe { key.assume_init() }, [0; 64]);
+    }
+
+    #[test]
+    fn antiforensic_box() {
+        let allocator = SodiumAllocator::configure().antiforensic(true);
+        let key = Box::new_in([0xcau8; 32], allocator);
+        assert_eq!(*key, [0xca; 32]);
+        drop(key);
+
+        assert_eq!(take_last_fill().map(|f| f.len()), Some(32));
+    }
+
+    #[cfg(all(target_os = "linux", feature = "anti-debug"))]
+    #[test]
+    fn tracer_pid_parsed() {
+        let status = "Name:\ttest\nState:\tR (running)\nTracerPid:\t0\nUid:\t0\t0\t0\t0\n";
+        assert_eq!(tracer_pid(status), Some(0));
+        assert_eq!(
+            tracer_pid("Name:\tgdb-target\nTracerPid:\t4242\n"),
+            Some(4242)
+        );
+        assert_eq!(tracer_pid("Name:\ttest\n"), None);
+        assert_eq!(tracer_pid("TracerPid:\tgarbage\n"), None);
+    }
+
+    // Miri can't read `/proc` with isolation enabled. This assumes the tests aren't being run under
+    // a debugger (or `strace`).
+    #[cfg(all(target_os = "linux", feature = "anti-debug", not(miri)))]
+    #[test]
+    fn anti_debug_allows_untraced() {
+        assert_eq!(is_traced(), Some(false));
+
+        let allocator = SodiumAllocator::configure().anti_debug(true);
+        let key = Box::try_new_in([0x13u8; 32], allocator).unwrap();
+        assert_eq!(*key, [0x13; 32]);
+    }
+
+    // The fork tests rely on `sodium_free` unmapping freed memory, so the advice applied by
+    // `madvise` doesn't outlive the allocation. This isn't true of the shim used under Miri
+    // (or with the `test-backend` feature).
+    #[cfg(all(target_os = "linux", not(any(miri, feature = "test-backend"))))]
+    use crate::test_util::fork_test;
+
+    #[cfg(all(target_os = "linux", not(any(miri, feature = "test-backend"))))]
+    #[test]
+    fn wipe_on_fork() {
+        let allocator = SodiumAllocator::configure().wipe_on_fork(true);
+        for size in [1, 32, 4096, 10000] {
+            let mut vec = Vec::with_capacity_in(size, allocator);
+            vec.resize(size, 0xffu8);
+            let ptr = vec.as_ptr();
+
+            // SAFETY: `ptr` is valid for reads of `size` bytes.
+            let child_sees_zeroes = fork_test(|| {
+                (0..size).all(|i| unsafe { std::ptr::read_volatile(ptr.add(i)) } == 0)
+            });
+            assert!(child_sees_zeroes);
+            assert!(vec.iter().all(|&b| b == 0xff));
+        }
+    }
+
+    #[cfg(all(target_os = "linux", not(any(miri, feature = "test-backend"))))]
+    #[test]
+    fn no_wipe_on_fork_by_default() {
+        let mut vec = Vec::with_capacity_in(32, SodiumAllocator::configure());
+        vec.resize(32, 0xffu8);
+        let ptr = vec.as_ptr();
+
+        let child_sees_secret =
+            fork_test(|| (0..32).all(|i| unsafe { std::ptr::read_volatile(ptr.add(i)) } == 0xff));
+        assert!(child_sees_secret);
+    }
+
+    #[cfg(all(target_os = "linux", not(any(miri, feature = "test-backend"))))]
+    #[test]
+    fn dont_fork() {
+        let allocator = SodiumAllocator::configure().dont_fork(true);
+        for size in [1, 4096, 10000] {
+            let mut vec = Vec::with_capacity_in(size, allocator);
+            vec.resize(size, 0xffu8);
+            let ptr = vec.as_ptr();
+
+            // The memory isn't mapped in the child, so reading it should fault.
+            let child_read = fork_test(|| unsafe { std::ptr::read_volatile(ptr) } == 0xff);
+            assert!(!child_read);
+            assert!(vec.iter().all(|&b| b == 0xff));
+        }
+    }
+}
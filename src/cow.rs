@@ -0,0 +1,158 @@
+//! Clone-on-write secrets, which are only copied into guarded memory when necessary.
+
+use crate::vec::secure_copy;
+use crate::SodiumAllocator;
+use std::alloc::AllocError;
+use std::ops::Deref;
+
+/// A clone-on-write byte string, which either borrows a secret or owns a copy of it stored in
+/// memory allocated using [`SodiumAllocator`].
+///
+/// This is analogous to [`Cow<[u8]>`](std::borrow::Cow), but the owned variant is always stored
+/// in guarded memory. It is useful for APIs which accept secrets, but only sometimes need to take
+/// ownership of them: The guarded copy is only made when it is actually needed.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::SecureCow;
+///
+/// let password = b"hunter2";
+/// let mut secret = SecureCow::Borrowed(password);
+/// assert!(secret.is_borrowed());
+///
+/// // Modifying the secret requires ownership, so a guarded copy is made
+/// secret.to_mut()?.push(b'!');
+/// assert!(secret.is_owned());
+/// assert_eq!(&secret[..], b"hunter2!");
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+pub enum SecureCow<'a> {
+    /// A borrowed secret.
+    Borrowed(&'a [u8]),
+
+    /// A secret stored in guarded memory.
+    Owned(Vec<u8, SodiumAllocator>),
+}
+
+impl SecureCow<'_> {
+    /// Returns true if this secret is borrowed.
+    pub fn is_borrowed(&self) -> bool {
+        matches!(self, Self::Borrowed(_))
+    }
+
+    /// Returns true if this secret is stored in guarded memory owned by this value.
+    pub fn is_owned(&self) -> bool {
+        matches!(self, Self::Owned(_))
+    }
+
+    /// Copy the secret into a new `Vec` allocated using Sodium.
+    ///
+    /// This always makes a new copy of the secret, even if it is already owned, leaving `self`
+    /// unchanged. Returns [`AllocError`] if the memory for the copy could not be allocated.
+    pub fn to_owned_secure(&self) -> Result<Vec<u8, SodiumAllocator>, AllocError> {
+        secure_copy(self)
+    }
+
+    /// Get a mutable reference to the owned secret, copying it into guarded memory first if it
+    /// is currently borrowed.
+    ///
+    /// Returns [`AllocError`] if the memory for the copy could not be allocated.
+    pub fn to_mut(&mut self) -> Result<&mut Vec<u8, SodiumAllocator>, AllocError> {
+        if let Self::Borrowed(secret) = self {
+            *self = Self::Owned(secure_copy(secret)?);
+        }
+
+        match self {
+            Self::Owned(secret) => Ok(secret),
+            Self::Borrowed(_) => unreachable!(),
+        }
+    }
+
+    /// Take ownership of the secret, copying it into guarded memory if it is currently borrowed.
+    ///
+    /// Returns [`AllocError`] if the memory for the copy could not be allocated.
+    pub fn into_owned(self) -> Result<Vec<u8, SodiumAllocator>, AllocError> {
+        match self {
+            Self::Borrowed(secret) => secure_copy(secret),
+            Self::Owned(secret) => Ok(secret),
+        }
+    }
+}
+
+impl Deref for SecureCow<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Borrowed(secret) => secret,
+            Self::Owned(secret) => secret,
+        }
+    }
+}
+
+impl<'a> From<&'a [u8]> for SecureCow<'a> {
+    fn from(secret: &'a [u8]) -> Self {
+        Self::Borrowed(secret)
+    }
+}
+
+impl From<Vec<u8, SodiumAllocator>> for SecureCow<'_> {
+    fn from(secret: Vec<u8, SodiumAllocator>) -> Self {
+        Self::Owned(secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn borrowed() -> Result<(), AllocError> {
+        let data = [1, 2, 3, 4];
+        let cow = SecureCow::from(&data[..]);
+
+        assert!(cow.is_borrowed());
+        assert_eq!(&cow[..], &data);
+        assert_eq!(cow.as_ptr(), data.as_ptr());
+
+        let owned = cow.to_owned_secure()?;
+        assert_eq!(&owned[..], &data);
+        assert_ne!(owned.as_ptr(), data.as_ptr());
+
+        Ok(())
+    }
+
+    #[test]
+    fn owned() -> Result<(), AllocError> {
+        let mut vec = Vec::new_in(SodiumAllocator);
+        vec.extend_from_slice(&[1, 2, 3, 4]);
+        let ptr = vec.as_ptr();
+        let mut cow = SecureCow::from(vec);
+
+        assert!(cow.is_owned());
+        assert_eq!(&cow[..], &[1, 2, 3, 4]);
+
+        // An owned secret shouldn't be copied to get a mutable reference, or take ownership
+        assert_eq!(cow.to_mut()?.as_ptr(), ptr);
+        let owned = cow.into_owned()?;
+        assert_eq!(owned.as_ptr(), ptr);
+
+        Ok(())
+    }
+
+    #[test]
+    fn borrowed_to_owned() -> Result<(), AllocError> {
+        let data = [1, 2, 3, 4];
+        let mut cow = SecureCow::Borrowed(&data);
+
+        cow.to_mut()?[0] = 0xff;
+        assert!(cow.is_owned());
+        assert_eq!(&cow[..], &[0xff, 2, 3, 4]);
+        assert_eq!(data, [1, 2, 3, 4]);
+
+        let owned = SecureCow::Borrowed(&data).into_owned()?;
+        assert_eq!(&owned[..], &data);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+//! Encryption of guarded buffers at rest, using the Windows Data Protection API.
+
+use crate::SecureVec;
+use std::convert::TryFrom;
+use std::ffi::c_void;
+use std::io;
+use windows_sys::Win32::Security::Cryptography::{
+    CryptProtectMemory, CryptUnprotectMemory, CRYPTPROTECTMEMORY_BLOCK_SIZE,
+    CRYPTPROTECTMEMORY_SAME_PROCESS,
+};
+
+/// The size of the blocks encrypted by `CryptProtectMemory`, in bytes.
+///
+/// The length of a buffer must be a multiple of this to be protected at rest.
+pub const PROTECT_BLOCK_SIZE: usize = CRYPTPROTECTMEMORY_BLOCK_SIZE as usize;
+
+impl SecureVec {
+    /// Encrypt the contents of the vector in place, so the secret isn't held in plaintext while it
+    /// isn't in use.
+    ///
+    /// This uses
+    /// [`CryptProtectMemory`](https://learn.microsoft.com/en-us/windows/win32/api/dpapi/nf-dpapi-cryptprotectmemory),
+    /// with a key which is only available to the current process. Encrypting secrets between
+    /// uses complements the guard pages and locking already provided by Sodium: A copy of the
+    /// vector's memory (for example, in a crash dump, or read by another process) only reveals
+    /// ciphertext. Call [`SecureVec::unprotect`] to decrypt the contents before using them again.
+    ///
+    /// While the vector is protected, its contents are ciphertext: Reading them yields garbage,
+    /// and writing to them will corrupt the secret. The vector doesn't keep track of whether it
+    /// is currently protected, so each call to `protect_at_rest` must be paired with exactly one
+    /// call to `unprotect`.
+    ///
+    /// Only available on Windows.
+    ///
+    /// ```
+    /// use sodium_alloc::{SecureVec, PROTECT_BLOCK_SIZE};
+    ///
+    /// let mut key = SecureVec::new();
+    /// key.extend_from_slice(&[0x42; 2 * PROTECT_BLOCK_SIZE]);
+    /// key.protect_at_rest()?;
+    /// // ...while the key isn't needed...
+    /// key.unprotect()?;
+    /// assert_eq!(key[0], 0x42);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    ///
+    /// Returns an error of kind [`InvalidInput`](io::ErrorKind::InvalidInput) if the length of
+    /// the vector isn't a multiple of [`PROTECT_BLOCK_SIZE`] (pad the secret if necessary), or
+    /// the error reported by Windows if encryption failed.
+    pub fn protect_at_rest(&mut self) -> io::Result<()> {
+        let len = block_len(self)?;
+        // SAFETY: The vector's buffer is valid for reads and writes of `len` bytes.
+        let ok = unsafe {
+            CryptProtectMemory(
+                self.as_mut_ptr() as *mut c_void,
+                len,
+                CRYPTPROTECTMEMORY_SAME_PROCESS,
+            )
+        };
+
+        match ok {
+            0 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Decrypt the contents of a vector which was encrypted using
+    /// [`SecureVec::protect_at_rest`], in place.
+    ///
+    /// Only available on Windows.
+    ///
+    /// Returns an error of kind [`InvalidInput`](io::ErrorKind::InvalidInput) if the length of
+    /// the vector isn't a multiple of [`PROTECT_BLOCK_SIZE`], or the error reported by Windows if
+    /// decryption failed.
+    pub fn unprotect(&mut self) -> io::Result<()> {
+        let len = block_len(self)?;
+        // SAFETY: The vector's buffer is valid for reads and writes of `len` bytes.
+        let ok = unsafe {
+            CryptUnprotectMemory(
+                self.as_mut_ptr() as *mut c_void,
+                len,
+                CRYPTPROTECTMEMORY_SAME_PROCESS,
+            )
+        };
+
+        match ok {
+            0 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Get the length of `vec` as a `u32`, checking that it can be passed to `CryptProtectMemory`.
+fn block_len(vec: &SecureVec) -> io::Result<u32> {
+    if !vec.len().is_multiple_of(PROTECT_BLOCK_SIZE) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "length must be a multiple of PROTECT_BLOCK_SIZE",
+        ));
+    }
+
+    u32::try_from(vec.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "buffer too large for CryptProtectMemory",
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protect_round_trip() -> io::Result<()> {
+        let mut key = SecureVec::new();
+        key.extend((0..4 * PROTECT_BLOCK_SIZE).map(|i| i as u8));
+        let original = key.to_vec();
+
+        key.protect_at_rest()?;
+        assert_ne!(&key[..], &original[..]);
+        key.unprotect()?;
+        assert_eq!(&key[..], &original[..]);
+
+        // Repeated cycles work too
+        for _ in 0..3 {
+            key.protect_at_rest()?;
+            key.unprotect()?;
+        }
+        assert_eq!(&key[..], &original[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unaligned_length_rejected() {
+        let mut key = SecureVec::new();
+        key.extend_from_slice(&[0x13; PROTECT_BLOCK_SIZE + 1]);
+
+        let err = key.protect_at_rest().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(&key[..], &[0x13; PROTECT_BLOCK_SIZE + 1][..]);
+    }
+}
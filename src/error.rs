@@ -0,0 +1,78 @@
+//! Error types.
+
+use std::alloc::AllocError;
+use std::fmt;
+
+/// An error which occurred while attempting to allocate memory using Sodium.
+///
+/// The [`Allocator`](std::alloc::Allocator) API only allows us to return the opaque
+/// [`AllocError`], so this type is only returned by methods such as
+/// [`SodiumAllocator::try_allocate`](crate::SodiumAllocator::try_allocate). It can be converted
+/// into an `AllocError` using [`From`].
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum AllocationError {
+    /// The requested layout was invalid: Either the alignment was not a power of 2, or padding
+    /// the size to a multiple of the alignment overflowed or exceeded `isize::MAX`.
+    ///
+    /// This indicates a programming error, rather than a lack of available memory.
+    LayoutError,
+
+    /// libsodium could not be initialised.
+    ///
+    /// With the `require-guard-pages` feature enabled, this is also returned if the linked
+    /// libsodium doesn't support guard pages (see [`aborts_on_misuse`](crate::aborts_on_misuse)).
+    InitFailed,
+
+    /// Sodium was unable to allocate the requested memory.
+    OutOfMemory,
+}
+
+impl fmt::Display for AllocationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LayoutError => f.write_str("invalid layout for allocation"),
+            Self::InitFailed => f.write_str("failed to initialise libsodium"),
+            Self::OutOfMemory => f.write_str("failed to allocate memory"),
+        }
+    }
+}
+
+impl std::error::Error for AllocationError {}
+
+impl From<AllocationError> for AllocError {
+    fn from(_: AllocationError) -> Self {
+        AllocError
+    }
+}
+
+/// An error which occurred while building a [`SecureString`](crate::SecureString) or
+/// [`SecureCString`](crate::SecureCString).
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum StringError {
+    /// The bytes were not valid UTF-8.
+    InvalidUtf8,
+
+    /// The bytes for a C string contained a NUL byte before the end.
+    InteriorNul,
+
+    /// The memory for the string could not be allocated.
+    OutOfMemory,
+}
+
+impl fmt::Display for StringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidUtf8 => f.write_str("invalid UTF-8"),
+            Self::InteriorNul => f.write_str("interior NUL byte in C string"),
+            Self::OutOfMemory => f.write_str("failed to allocate memory"),
+        }
+    }
+}
+
+impl std::error::Error for StringError {}
+
+impl From<AllocError> for StringError {
+    fn from(_: AllocError) -> Self {
+        Self::OutOfMemory
+    }
+}
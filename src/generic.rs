@@ -0,0 +1,102 @@
+//! Arrays with a type-level length, stored in memory allocated using Sodium.
+
+use crate::SodiumAllocator;
+use generic_array::{ArrayLength, GenericArray};
+use std::alloc::AllocError;
+use std::ops::{Deref, DerefMut};
+
+/// A [`GenericArray`] of bytes, stored in memory allocated using [`SodiumAllocator`].
+///
+/// This is the equivalent of [`SecureArray`](crate::SecureArray) for code which is generic over
+/// the size of a key using [`typenum`](generic_array::typenum) lengths, as used by many crypto
+/// crates. The array is securely zeroed when the `SecureGenericArray` is dropped. It dereferences
+/// to `GenericArray<u8, N>`, so it can be passed wherever a reference to a `GenericArray` is
+/// expected.
+///
+/// Only available with the `generic-array` feature enabled.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use generic_array::typenum::U32;
+/// use sodium_alloc::SecureGenericArray;
+///
+/// let mut key = SecureGenericArray::<U32>::new()?;
+/// key[..4].copy_from_slice(&[0xca, 0xfe, 0xba, 0xbe]);
+/// assert_eq!(key.len(), 32);
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+pub struct SecureGenericArray<N: ArrayLength>(Box<GenericArray<u8, N>, SodiumAllocator>);
+
+impl<N: ArrayLength> SecureGenericArray<N> {
+    /// Create a new `SecureGenericArray`, with every byte initialised to zero.
+    ///
+    /// The array is zeroed in place, so no temporary copy of the array is made on the stack.
+    ///
+    /// Returns [`AllocError`] if the memory could not be allocated.
+    pub fn new() -> Result<Self, AllocError> {
+        let array = Box::try_new_zeroed_in(SodiumAllocator)?;
+        // SAFETY: A `GenericArray<u8, N>` is an array of `N` `u8`s, so zeroed memory is valid.
+        Ok(Self(unsafe { array.assume_init() }))
+    }
+}
+
+impl<N: ArrayLength> Deref for SecureGenericArray<N> {
+    type Target = GenericArray<u8, N>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<N: ArrayLength> DerefMut for SecureGenericArray<N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generic_array::typenum::{U0, U32};
+
+    /// Accepts a key the way a crypto crate generic over key sizes would.
+    fn first_and_last<N: ArrayLength>(key: &GenericArray<u8, N>) -> (u8, u8) {
+        (key[0], key[key.len() - 1])
+    }
+
+    #[test]
+    fn write_and_read() -> Result<(), AllocError> {
+        let mut key = SecureGenericArray::<U32>::new()?;
+        assert_eq!(key.as_slice(), &[0; 32]);
+
+        for (i, b) in key.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        assert_eq!(first_and_last(&key), (0, 31));
+        assert_eq!(*key, GenericArray::<u8, U32>::try_from_iter(0..32).unwrap());
+
+        Ok(())
+    }
+
+    #[test]
+    fn zero_size() -> Result<(), AllocError> {
+        let key = SecureGenericArray::<U0>::new()?;
+        assert!(key.is_empty());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "track-allocations")]
+    #[test]
+    fn freed_on_drop() -> Result<(), AllocError> {
+        use crate::tracking::is_live;
+
+        let key = SecureGenericArray::<U32>::new()?;
+        let ptr = key.as_ptr();
+        assert!(is_live(ptr));
+        drop(key);
+        assert!(!is_live(ptr));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+//! Large guarded buffers backed by huge pages.
+
+use crate::{memzero, secure_vec_with_capacity, SodiumAllocator};
+use std::alloc::AllocError;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+
+/// A large buffer of guarded memory, backed by huge pages if possible.
+///
+/// Created by [`allocate_hugepages`]. The buffer dereferences to `[u8]`, and is securely zeroed
+/// when it is dropped, regardless of how it is backed.
+pub struct HugePageBuffer {
+    inner: Inner,
+}
+
+enum Inner {
+    /// Memory mapped directly, with the data in huge pages.
+    Mapped(Mapping),
+    /// A regular allocation made using Sodium.
+    Sodium(Vec<u8, SodiumAllocator>),
+}
+
+/// Allocate a zeroed buffer of `size` bytes of guarded memory, backed by huge pages if possible.
+///
+/// For large secrets (such as an in-memory table of keys), using huge pages (usually 2 MiB on
+/// x86_64) reduces TLB pressure. This function attempts to map the data using `MAP_HUGETLB`,
+/// surrounded by inaccessible guard pages, and placed so that the end of the buffer is
+/// immediately followed by a guard page, as Sodium does. The data is locked using `mlock` (failure
+/// to lock is ignored, as with Sodium), and excluded from core dumps. There is no canary before
+/// the data.
+///
+/// If huge pages aren't available (for example, because none have been reserved via
+/// `/proc/sys/vm/nr_hugepages`), this falls back to a regular allocation made using
+/// [`SodiumAllocator`]. Use [`HugePageBuffer::is_huge_page_backed`] to check which was used.
+///
+/// Only available on Linux.
+///
+/// Returns [`AllocError`] if the fallback allocation failed.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::allocate_hugepages;
+///
+/// let mut table = allocate_hugepages(4 << 20)?;
+/// table[..4].copy_from_slice(&[0xca, 0xfe, 0xba, 0xbe]);
+/// println!("backed by huge pages: {}", table.is_huge_page_backed());
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+pub fn allocate_hugepages(size: usize) -> Result<HugePageBuffer, AllocError> {
+    if let Some(mapping) = Mapping::new(size) {
+        return Ok(HugePageBuffer {
+            inner: Inner::Mapped(mapping),
+        });
+    }
+
+    let mut vec = secure_vec_with_capacity(size)?;
+    // The capacity has already been reserved, so this never reallocates.
+    vec.resize(size, 0);
+
+    Ok(HugePageBuffer {
+        inner: Inner::Sodium(vec),
+    })
+}
+
+impl HugePageBuffer {
+    /// Returns whether this buffer is backed by huge pages, rather than a regular allocation.
+    pub fn is_huge_page_backed(&self) -> bool {
+        matches!(self.inner, Inner::Mapped(_))
+    }
+}
+
+impl Deref for HugePageBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match &self.inner {
+            // SAFETY: The data is valid for reads of `len` bytes while the mapping is alive.
+            Inner::Mapped(m) => unsafe { std::slice::from_raw_parts(m.data.as_ptr(), m.len) },
+            Inner::Sodium(vec) => vec,
+        }
+    }
+}
+
+impl DerefMut for HugePageBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match &mut self.inner {
+            // SAFETY: As above, and `&mut self` guarantees exclusive access.
+            Inner::Mapped(m) => unsafe { std::slice::from_raw_parts_mut(m.data.as_ptr(), m.len) },
+            Inner::Sodium(vec) => vec,
+        }
+    }
+}
+
+// SAFETY: The mapping is owned exclusively by the buffer, like the memory of a `Vec`.
+unsafe impl Send for HugePageBuffer {}
+unsafe impl Sync for HugePageBuffer {}
+
+/// A region of memory mapped with huge pages for the data, surrounded by guard pages.
+///
+/// Never created under Miri.
+#[cfg_attr(miri, allow(dead_code))]
+struct Mapping {
+    /// The start of the whole region, including guard pages.
+    base: *mut u8,
+    /// The length of the whole region, including guard pages.
+    total: usize,
+    /// The start of the huge pages containing the data.
+    pages: *mut u8,
+    /// The length of the huge pages containing the data.
+    pages_len: usize,
+    /// The start of the data, placed so that it ends at the end of the last huge page.
+    data: NonNull<u8>,
+    /// The length of the data.
+    len: usize,
+}
+
+impl Mapping {
+    /// Map `len` bytes of zeroed data in huge pages, returning `None` if this isn't possible.
+    #[cfg(not(miri))]
+    fn new(len: usize) -> Option<Self> {
+        use crate::page::page_size;
+
+        if len == 0 {
+            return None;
+        }
+        let huge = huge_page_size()?;
+        let pages_len = len.checked_next_multiple_of(huge)?;
+        // Reserve enough address space to align the data to a huge page boundary, with at least
+        // one guard page on either side of it.
+        let total = pages_len.checked_add(2 * huge)?;
+
+        // SAFETY: We create a new anonymous mapping, so no existing memory is affected. Each call
+        // is checked for failure.
+        unsafe {
+            let base = libc::mmap(
+                std::ptr::null_mut(),
+                total,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            );
+            if base == libc::MAP_FAILED {
+                return None;
+            }
+            let base = base as *mut u8;
+
+            // `huge` is a multiple of the page size, so this leaves at least one page of the
+            // reservation before and after the data, which remain inaccessible.
+            let offset = (base as usize + page_size()).next_multiple_of(huge) - base as usize;
+            let pages = base.add(offset);
+            let mapped = libc::mmap(
+                pages as *mut libc::c_void,
+                pages_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED | libc::MAP_HUGETLB,
+                -1,
+                0,
+            );
+            if mapped == libc::MAP_FAILED {
+                libc::munmap(base as *mut libc::c_void, total);
+                return None;
+            }
+
+            // As in Sodium, failure to lock the memory isn't fatal.
+            libc::mlock(pages as *const libc::c_void, pages_len);
+            libc::madvise(pages as *mut libc::c_void, pages_len, libc::MADV_DONTDUMP);
+
+            Some(Self {
+                base,
+                total,
+                pages,
+                pages_len,
+                data: NonNull::new_unchecked(pages.add(pages_len - len)),
+                len,
+            })
+        }
+    }
+
+    /// Miri can't call `mmap`, so we always fall back to a regular allocation.
+    #[cfg(miri)]
+    fn new(_len: usize) -> Option<Self> {
+        None
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: `pages` is valid for writes of `pages_len` bytes until the region is unmapped,
+        // which only happens here.
+        unsafe {
+            memzero(std::slice::from_raw_parts_mut(self.pages, self.pages_len));
+            #[cfg(not(miri))]
+            {
+                libc::munlock(self.pages as *const libc::c_void, self.pages_len);
+                libc::munmap(self.base as *mut libc::c_void, self.total);
+            }
+        }
+    }
+}
+
+/// Get the default huge page size from `/proc/meminfo`, or `None` if it couldn't be determined.
+#[cfg(not(miri))]
+fn huge_page_size() -> Option<usize> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|l| l.starts_with("Hugepagesize:"))?;
+    let kib = line.split_whitespace().nth(1)?.parse::<usize>().ok()?;
+
+    kib.checked_mul(1024).filter(|&size| size > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huge_pages_or_fallback() -> Result<(), AllocError> {
+        let size = (4 << 20) + 5;
+        let mut buf = allocate_hugepages(size)?;
+        assert_eq!(buf.len(), size);
+        assert!(buf.iter().all(|&b| b == 0));
+
+        // Both ends of the buffer are writable
+        buf[0] = 0xaa;
+        buf[size - 1] = 0xbb;
+        assert_eq!(buf[0], 0xaa);
+        assert_eq!(buf[size - 1], 0xbb);
+
+        #[cfg(not(miri))]
+        {
+            // The end of the buffer is always placed at the end of a page, before a guard page
+            let end = buf.as_ptr() as usize + buf.len();
+            assert_eq!(end % crate::page_size(), 0);
+
+            if buf.is_huge_page_backed() {
+                assert_eq!(end % huge_page_size().unwrap(), 0);
+            }
+        }
+        #[cfg(miri)]
+        assert!(!buf.is_huge_page_backed());
+
+        Ok(())
+    }
+
+    #[test]
+    fn empty_buffer_falls_back() -> Result<(), AllocError> {
+        let buf = allocate_hugepages(0)?;
+        assert!(buf.is_empty());
+        assert!(!buf.is_huge_page_backed());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+//! Checking guarded allocations for corruption.
+
+use crate::page::CANARY_SIZE;
+use crate::sys;
+use std::sync::OnceLock;
+
+/// Address of an allocation kept alive for the lifetime of the program, to compare other
+/// allocations' canaries against, or `None` if it could not be allocated.
+static REFERENCE: OnceLock<Option<usize>> = OnceLock::new();
+
+/// Get a pointer to the canary of the reference allocation.
+fn reference_canary() -> Option<*const u8> {
+    let ptr = (*REFERENCE.get_or_init(|| {
+        if !sys::init() {
+            return None;
+        }
+        // SAFETY: libsodium has been initialised. The allocation is deliberately never freed.
+        let ptr = unsafe { sys::malloc(0) };
+        (!ptr.is_null()).then_some(ptr as usize)
+    }))?;
+
+    Some((ptr - CANARY_SIZE) as *const u8)
+}
+
+/// Check whether the canary before the allocation at `ptr` is intact, without freeing it.
+///
+/// Sodium places a canary immediately before each allocation, and checks it when the allocation
+/// is freed, terminating the program if it has been modified. libsodium provides no way to perform
+/// this check without freeing the memory, so this function reimplements it: The canary is the
+/// same for every allocation in a process, so it is compared (in constant time) against the
+/// canary of a reference allocation, which is made on the first call and never freed.
+///
+/// This can be used to audit long-lived secrets for tampering, or to detect an out-of-bounds write
+/// in `unsafe` code at a controlled point, rather than when the memory is eventually freed. Note
+/// that only writes immediately before the allocation can be detected: Any access to the guard
+/// pages terminates the program immediately.
+///
+/// Returns `false` if the canary has been modified. Also returns `false` if the linked libsodium
+/// doesn't place canaries before allocations (see [`aborts_on_misuse`](crate::aborts_on_misuse)),
+/// or for allocations with an alignment greater than the page size, whose canaries are not
+/// immediately before the returned pointer.
+///
+/// # Safety
+/// `ptr` must be the start of a live, non-zero-size allocation made by
+/// [`SodiumAllocator`](crate::SodiumAllocator) (or another allocator in this crate which allocates
+/// using Sodium). Zero-size allocations aren't backed by any memory, so have no canary.
+pub unsafe fn verify_integrity(ptr: *const u8) -> bool {
+    // Without page protection, there may be no canary to compare.
+    #[cfg(not(any(miri, feature = "test-backend")))]
+    if !crate::aborts_on_misuse() {
+        return false;
+    }
+
+    let Some(reference) = reference_canary() else {
+        return false;
+    };
+
+    let canary = std::slice::from_raw_parts(ptr.sub(CANARY_SIZE), CANARY_SIZE);
+    let reference = std::slice::from_raw_parts(reference, CANARY_SIZE);
+    sys::memcmp(canary, reference)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SodiumAllocator;
+    use std::alloc::{Allocator, Layout};
+    use std::error::Error;
+
+    #[test]
+    fn intact_allocations() -> Result<(), Box<dyn Error>> {
+        for size in [1, 32, 4096, 10000] {
+            let layout = Layout::from_size_align(size, 1)?;
+            let ptr = SodiumAllocator.allocate(layout)?.cast::<u8>();
+            assert!(unsafe { verify_integrity(ptr.as_ptr()) });
+            unsafe { SodiumAllocator.deallocate(ptr, layout) };
+        }
+
+        let key = Box::new_in([0xffu8; 32], SodiumAllocator);
+        assert!(unsafe { verify_integrity(key.as_ptr()) });
+
+        Ok(())
+    }
+
+    #[test]
+    fn corrupted_canary() -> Result<(), Box<dyn Error>> {
+        let layout = Layout::from_size_align(32, 1)?;
+        let ptr = SodiumAllocator.allocate(layout)?.cast::<u8>();
+
+        unsafe {
+            let canary = ptr.as_ptr().sub(1);
+            let original = canary.read();
+            canary.write(!original);
+            assert!(!verify_integrity(ptr.as_ptr()));
+
+            // Restore the canary, otherwise Sodium would terminate the program on free
+            canary.write(original);
+            assert!(verify_integrity(ptr.as_ptr()));
+            SodiumAllocator.deallocate(ptr, layout);
+        }
+
+        Ok(())
+    }
+}
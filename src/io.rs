@@ -0,0 +1,392 @@
+//! Reading and writing secrets in memory allocated using Sodium.
+
+use crate::{memzero, SecureVecExt, SodiumAllocator};
+use std::io::{self, Read, Write};
+
+/// The amount of space initially reserved when reading into a new buffer.
+const INITIAL_CAPACITY: usize = 32;
+
+/// Read all bytes from `reader` until EOF, directly into a `Vec` allocated using Sodium.
+///
+/// This is equivalent to [`Read::read_to_end`], but the data is read directly into memory
+/// managed by [`SodiumAllocator`], so no secret data is stored in memory from the standard
+/// allocator at any point by this function. The buffer is grown as necessary as data is read:
+/// When this happens, the old buffer is freed using Sodium, so it is securely zeroed.
+///
+/// [`io::ErrorKind::Interrupted`] errors from the reader are retried, any other errors are
+/// returned. If the buffer couldn't be grown, an error of kind [`io::ErrorKind::OutOfMemory`] is
+/// returned.
+pub fn read_secret_to_end<R: Read + ?Sized>(
+    reader: &mut R,
+) -> io::Result<Vec<u8, SodiumAllocator>> {
+    let mut buf: Vec<u8, _> = Vec::new_in(SodiumAllocator);
+    let mut len = 0;
+
+    loop {
+        if len == buf.len() {
+            // `try_reserve` grows the buffer exponentially, so we don't need to reallocate too
+            // often for large readers.
+            buf.try_reserve(INITIAL_CAPACITY)
+                .map_err(|_| io::Error::from(io::ErrorKind::OutOfMemory))?;
+            // The reader needs initialised memory to read into, so we zero the new capacity.
+            buf.resize(buf.capacity(), 0);
+        }
+
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    buf.truncate_wiping(len);
+    Ok(buf)
+}
+
+/// An [`io::Write`] sink which appends to a `Vec` allocated using Sodium.
+///
+/// This allows secrets to be serialized directly into guarded memory, by anything which writes to
+/// an `impl Write`. The buffer grows as necessary: When this happens, the old buffer is freed using
+/// Sodium, so it is securely zeroed. Use [`SecureWriter::into_inner`] to retrieve the written
+/// bytes.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::SecureWriter;
+/// use std::io::Write;
+///
+/// let mut writer = SecureWriter::new();
+/// write!(writer, "{}:{}", "user", "hunter2")?;
+/// assert_eq!(&writer.into_inner()[..], b"user:hunter2");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// Unlike `Vec`'s own `Write` implementation, a write which can't grow the buffer returns an
+/// error of kind [`io::ErrorKind::OutOfMemory`], rather than aborting the program.
+pub struct SecureWriter {
+    buf: Vec<u8, SodiumAllocator>,
+}
+
+impl SecureWriter {
+    /// Create a new `SecureWriter`, with an empty buffer.
+    ///
+    /// No memory is allocated until the first write.
+    pub const fn new() -> Self {
+        Self {
+            buf: Vec::new_in(SodiumAllocator),
+        }
+    }
+
+    /// Get the bytes written so far.
+    pub fn get_ref(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Consume the writer, returning the buffer of written bytes.
+    pub fn into_inner(self) -> Vec<u8, SodiumAllocator> {
+        self.buf
+    }
+}
+
+impl Default for SecureWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for SecureWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf
+            .try_reserve(buf.len())
+            .map_err(|_| io::Error::from(io::ErrorKind::OutOfMemory))?;
+        self.buf.extend_from_slice(buf);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An [`io::Read`] source which reads from a `Vec` allocated using Sodium, securely zeroing each
+/// byte as soon as it has been read.
+///
+/// This is intended for secrets which should only be consumed once, such as when passing a key to
+/// a parser which reads from an `impl Read`: Once the parser has read each chunk of the secret,
+/// that chunk no longer exists in the reader's buffer. The buffer itself is freed (and zeroed
+/// again) when the reader is dropped.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::{secure_concat, SecureReader};
+/// use std::io::Read;
+///
+/// let mut reader = SecureReader::new(secure_concat(&[b"secret"])?);
+/// let mut buf = [0; 4];
+/// reader.read_exact(&mut buf)?;
+/// assert_eq!(&buf, b"secr");
+/// assert_eq!(reader.remaining(), 2);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct SecureReader {
+    buf: Vec<u8, SodiumAllocator>,
+    pos: usize,
+}
+
+impl SecureReader {
+    /// Create a new `SecureReader`, which reads the contents of `buf`.
+    pub fn new(buf: Vec<u8, SodiumAllocator>) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// The number of bytes which have not been read yet.
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+}
+
+impl Read for SecureReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.remaining());
+        let read = &mut self.buf[self.pos..self.pos + n];
+        buf[..n].copy_from_slice(read);
+        memzero(read);
+        self.pos += n;
+
+        Ok(n)
+    }
+}
+
+/// A cursor over a `Vec` allocated using Sodium, for transforming a secret in place.
+///
+/// The cursor tracks a position within the buffer, and [`SecureCursor::remaining_mut`] gives
+/// mutable access to the bytes from that position onwards, in guarded memory. This allows an
+/// in-place transform (such as decrypting with a stream cipher) to be applied to the buffer chunk
+/// by chunk, without copying any of it elsewhere.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::{secure_concat, SecureCursor};
+///
+/// let mut cursor = SecureCursor::new(secure_concat(&[b"ciphertext"])?);
+/// while !cursor.remaining_mut().is_empty() {
+///     let chunk = cursor.remaining_mut().len().min(4);
+///     for byte in &mut cursor.remaining_mut()[..chunk] {
+///         *byte ^= 0x20;
+///     }
+///     cursor.advance(chunk);
+/// }
+/// assert_eq!(&cursor.into_inner()[..], b"CIPHERTEXT");
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+pub struct SecureCursor {
+    buf: Vec<u8, SodiumAllocator>,
+    pos: usize,
+}
+
+impl SecureCursor {
+    /// Create a new `SecureCursor` over `buf`, starting at position 0.
+    pub fn new(buf: Vec<u8, SodiumAllocator>) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// The current position of the cursor.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Set the position of the cursor.
+    ///
+    /// The position may be past the end of the buffer, in which case there are no remaining
+    /// bytes.
+    pub fn set_position(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    /// Move the cursor forward by `n` bytes.
+    pub fn advance(&mut self, n: usize) {
+        self.pos = self.pos.saturating_add(n);
+    }
+
+    /// The bytes from the current position to the end of the buffer.
+    pub fn remaining(&self) -> &[u8] {
+        self.buf.get(self.pos..).unwrap_or_default()
+    }
+
+    /// Mutable access to the bytes from the current position to the end of the buffer.
+    pub fn remaining_mut(&mut self) -> &mut [u8] {
+        self.buf.get_mut(self.pos..).unwrap_or_default()
+    }
+
+    /// Get the whole buffer.
+    pub fn get_ref(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Consume the cursor, returning the buffer.
+    pub fn into_inner(self) -> Vec<u8, SodiumAllocator> {
+        self.buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A reader which returns at most `chunk` bytes per read, and is sometimes interrupted.
+    struct ChunkedReader<'a> {
+        data: &'a [u8],
+        chunk: usize,
+        interrupt: bool,
+    }
+
+    impl Read for ChunkedReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+
+            let n = self.chunk.min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn read_from_slice() -> io::Result<()> {
+        let data: Vec<u8> = (0..10000).map(|i| i as u8).collect();
+
+        let secret: Vec<u8, SodiumAllocator> = read_secret_to_end(&mut &data[..])?;
+        assert_eq!(&secret[..], &data[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_chunked() -> io::Result<()> {
+        let data: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+        let mut reader = ChunkedReader {
+            data: &data,
+            chunk: 13,
+            interrupt: false,
+        };
+
+        let secret = read_secret_to_end(&mut reader)?;
+        assert_eq!(&secret[..], &data[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_empty() -> io::Result<()> {
+        let secret = read_secret_to_end(&mut io::empty())?;
+        assert!(secret.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_all_grows() -> io::Result<()> {
+        let data: Vec<u8> = (0..10000).map(|i| i as u8).collect();
+
+        let mut writer = SecureWriter::new();
+        assert!(writer.get_ref().is_empty());
+        for chunk in data.chunks(999) {
+            writer.write_all(chunk)?;
+        }
+        writer.flush()?;
+        assert_eq!(writer.get_ref(), &data[..]);
+
+        let secret: Vec<u8, SodiumAllocator> = writer.into_inner();
+        assert_eq!(&secret[..], &data[..]);
+        #[cfg(feature = "track-allocations")]
+        assert!(crate::tracking::is_live(secret.as_ptr()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_formatted() -> io::Result<()> {
+        let mut writer = SecureWriter::default();
+        write!(writer, "{:02x}{:02x}", 0xcau8, 0xfeu8)?;
+        assert_eq!(&writer.into_inner()[..], b"cafe");
+
+        Ok(())
+    }
+
+    #[test]
+    fn reader_wipes_consumed() -> io::Result<()> {
+        let data: Vec<u8> = (1..=100).collect();
+        let mut secret = Vec::new_in(SodiumAllocator);
+        secret.extend_from_slice(&data);
+        let mut reader = SecureReader::new(secret);
+
+        let mut buf = [0; 30];
+        reader.read_exact(&mut buf)?;
+        assert_eq!(&buf[..], &data[..30]);
+        assert_eq!(reader.remaining(), 70);
+        assert!(reader.buf[..30].iter().all(|&b| b == 0));
+        assert_eq!(&reader.buf[30..], &data[30..]);
+
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest)?;
+        assert_eq!(&rest[..], &data[30..]);
+        assert_eq!(reader.remaining(), 0);
+        assert!(reader.buf.iter().all(|&b| b == 0));
+
+        // Reading past the end returns EOF
+        assert_eq!(reader.read(&mut buf)?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn cursor_xor_transform() -> Result<(), Box<dyn std::error::Error>> {
+        let plaintext: Vec<u8> = (0..100).collect();
+        let keystream: Vec<u8> = (0..100).map(|i| (i * 7) as u8).collect();
+        let mut cursor = SecureCursor::new(crate::secure_concat(&[&plaintext])?);
+
+        // Apply the keystream in uneven chunks
+        for chunk in [1, 10, 33, 56] {
+            let pos = cursor.position();
+            let ks = &keystream[pos..pos + chunk];
+            for (b, k) in cursor.remaining_mut()[..chunk].iter_mut().zip(ks) {
+                *b ^= k;
+            }
+            cursor.advance(chunk);
+        }
+        assert_eq!(cursor.position(), 100);
+        assert!(cursor.remaining().is_empty());
+
+        let expected: Vec<u8> = plaintext
+            .iter()
+            .zip(&keystream)
+            .map(|(p, k)| p ^ k)
+            .collect();
+        assert_eq!(cursor.get_ref(), &expected[..]);
+
+        // Seeking back and applying the keystream again recovers the plaintext
+        cursor.set_position(0);
+        for (b, k) in cursor.remaining_mut().iter_mut().zip(&keystream) {
+            *b ^= k;
+        }
+        assert_eq!(&cursor.into_inner()[..], &plaintext[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn cursor_past_end() -> Result<(), Box<dyn std::error::Error>> {
+        let mut cursor = SecureCursor::new(crate::secure_concat(&[b"key"])?);
+        cursor.set_position(10);
+        assert!(cursor.remaining_mut().is_empty());
+        cursor.advance(usize::MAX);
+        assert!(cursor.remaining().is_empty());
+
+        Ok(())
+    }
+}
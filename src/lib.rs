@@ -65,16 +65,157 @@
 //! let key = Box::new_in([0xca, 0xfe, 0xba, 0xbe], SodiumAllocator);
 //! println!("{:x?}", key);
 //! ```
+//!
+//! As do the reference-counted pointers [`Rc`](std::rc::Rc) and [`Arc`](std::sync::Arc), which
+//! can be used to share a secret between many owners, or across threads:
+//!
+//! ```
+//! #![feature(allocator_api)]
+//!
+//! use sodium_alloc::SodiumAllocator;
+//! use std::sync::Arc;
+//! use std::thread;
+//!
+//! let key = Arc::new_in([0xca, 0xfe, 0xba, 0xbe], SodiumAllocator);
+//! let key_clone = Arc::clone(&key);
+//! thread::spawn(move || println!("{:x?}", key_clone)).join().unwrap();
+//! ```
+//!
+//! The memory for an `Rc` or `Arc` is only freed (and therefore zeroed) once the last strong
+//! *and* weak reference to it has been dropped. Note that the strong and weak reference counts
+//! are stored in the same guarded allocation as the secret itself, so the allocation is slightly
+//! larger than the secret. If only weak references remain, the secret value is dropped, but its
+//! memory is not zeroed by Sodium until the weak references are also dropped: Avoid holding
+//! long-lived [`Weak`](std::sync::Weak) references to secrets stored in this way.
+//!
+//! ## Miri
+//! [Miri](https://github.com/rust-lang/miri) can't call foreign functions, so when running under
+//! Miri, this library replaces Sodium's allocation functions with a shim built on the standard
+//! allocator. The shim preserves the size, alignment, and zero-on-free behaviour of Sodium, so
+//! `cargo miri test` can be used to check the memory safety of code using `SodiumAllocator`, but
+//! it provides **none** of Sodium's security properties: There are no guard pages or canaries, and
+//! memory is not locked. It is intended for testing only, and is never used outside of Miri unless
+//! the `test-backend` feature is enabled.
+//!
+//! ## Testing
+//! Real guarded allocations lock memory, so tests which allocate heavily can fail on machines
+//! with a small limit on locked memory (such as many CI runners). The `test-backend` feature
+//! selects the same shim used under Miri at compile time, which never locks memory or creates
+//! guard pages, but otherwise behaves like Sodium: Allocations still end at the end of a page, and
+//! memory is still zeroed when freed. This lets tests of logic built on this crate run
+//! deterministically anywhere.
+//!
+//! The shim provides no protection at all for secrets, so **`test-backend` must never be enabled
+//! outside of tests**. Enable it only as a dev-dependency feature:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! sodium-alloc = { version = "0.1", features = ["test-backend"] }
+//! ```
+//!
+//! Note that Cargo unifies features, so a dev-dependency feature is also enabled for a normal
+//! dependency on the same crate when building tests, but not in other builds.
 #![doc(html_root_url = "https://docs.rs/sodium-alloc/0.1.1")]
-#![feature(allocator_api)]
-#![feature(nonnull_slice_from_raw_parts)]
-#![feature(slice_ptr_get)]
-#![feature(slice_ptr_len)]
+#![feature(allocator_api, btreemap_alloc)]
+
+mod any;
+mod array;
+mod boxed;
+mod budget;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod cell;
+mod collections;
+mod config;
+mod cow;
+#[cfg(windows)]
+mod dpapi;
+mod error;
+#[cfg(feature = "generic-array")]
+mod generic;
+#[cfg(target_os = "linux")]
+mod huge;
+mod integrity;
+mod io;
+mod linkage;
+mod matrix;
+mod memlock;
+mod misuse;
+mod noaccess;
+mod ops;
+mod option;
+mod owned;
+mod page;
+mod redact;
+mod slab;
+mod slice;
+mod state;
+mod string;
+mod structs;
+mod sys;
+#[cfg(test)]
+mod test_util;
+#[cfg(feature = "rustls")]
+mod tls;
+#[cfg(feature = "track-allocations")]
+mod tracking;
+mod vec;
+mod view;
+#[cfg(feature = "watchdog")]
+mod watchdog;
+mod wipe;
+
+pub use any::SecureAny;
+pub use array::SecureArray;
+pub use boxed::{clone_secure, secure_box_zeroed, secure_replace, SecureBox};
+pub use budget::MemoryBudget;
+pub use cell::SecureCell;
+pub use collections::{secure_btree_map, secure_btree_set, SecureBTreeMap, SecureBTreeSet};
+pub use config::ConfiguredAllocator;
+pub use cow::SecureCow;
+#[cfg(windows)]
+pub use dpapi::PROTECT_BLOCK_SIZE;
+pub use error::{AllocationError, StringError};
+#[cfg(feature = "generic-array")]
+pub use generic::SecureGenericArray;
+#[cfg(target_os = "linux")]
+pub use huge::{allocate_hugepages, HugePageBuffer};
+pub use integrity::verify_integrity;
+pub use io::{read_secret_to_end, SecureCursor, SecureReader, SecureWriter};
+pub use linkage::{linkage_info, Linkage, LinkageInfo};
+pub use matrix::SecureMatrix;
+pub use memlock::{locked_bytes, locked_bytes_for};
+pub use misuse::aborts_on_misuse;
+pub use noaccess::{allocate_noaccess, NoAccessSecret};
+pub use ops::{clone_into_secure, constant_time_eq_padded, constant_time_select, secure_swap};
+pub use option::SecureOption;
+pub use owned::OwnedAllocation;
+pub use page::{overhead_report, page_offset, page_size, OverheadReport};
+pub use redact::Redacted;
+pub use slab::{PageProvider, SecureSlab, SharedSlab, SharedSlot, Slot, SodiumPages};
+pub use slice::SecureSlice;
+pub use state::{init_state, InitState};
+pub use string::{SecureCString, SecureString, SecureStringBuilder};
+pub use structs::Zeroable;
+#[cfg(feature = "rustls")]
+pub use tls::GuardedEd25519Key;
+#[cfg(feature = "debug-backtrace")]
+pub use tracking::LiveAllocation;
+#[cfg(feature = "track-allocations")]
+pub use tracking::{allocation_size, stats, AllocStats, TagStats};
+#[cfg(feature = "rand")]
+pub use vec::fill_secure_from_rng;
+pub use vec::{
+    partition_secure, secure_concat, secure_fold, secure_vec_from_slice_with_capacity,
+    secure_vec_with_capacity, with_guarded_buffer, DrainSecure, SecureVec, SecureVecExt,
+};
+pub use view::SecureView;
+#[cfg(feature = "watchdog")]
+pub use watchdog::{CorruptAllocation, Watchdog};
+pub use wipe::memzero;
 
-use libsodium_sys as sodium;
 use std::alloc::{AllocError, Allocator, Layout};
-use std::ffi::c_void;
-use std::ptr::NonNull;
+use std::ptr::{self, NonNull};
 
 /// An [`Allocator`](std::alloc::Allocator) which allocates and frees memory using Sodium's secure
 /// memory utilities.
@@ -88,57 +229,320 @@ use std::ptr::NonNull;
 ///
 /// If the canary Sodium places before the allocated memory is altered, or if an attempt to access
 /// a guard page surrounding the allocated memory is made, the program will automatically
-/// terminate. This behaviour should never occur in safe Rust.
+/// terminate. This behaviour should never occur in safe Rust. Guard pages and canaries are not
+/// supported on all platforms, see [`aborts_on_misuse`] to check whether they are available.
+///
+/// Any alignment up to the [page size](page_size) can be requested, and is achieved by padding
+/// the allocation so that it still ends at the end of a page, immediately before a guard page.
+/// Larger alignments are satisfied by over-allocating and aligning the pointer within the
+/// allocation. The allocation is still surrounded by guard pages and protected by the canary,
+/// but in this case there may be some unused memory between the end of the allocation and the
+/// guard page which follows it.
+///
+/// Zero-size allocations don't use Sodium at all: They return a dangling pointer aligned to the
+/// requested alignment, as with the standard allocators.
 #[derive(Copy, Clone, Debug)]
 pub struct SodiumAllocator;
 
+impl SodiumAllocator {
+    /// Attempt to allocate a block of memory for the given [`Layout`].
+    ///
+    /// This is equivalent to [`Allocator::allocate`], but returns an [`AllocationError`]
+    /// describing why the allocation failed, rather than the opaque [`AllocError`]. In
+    /// particular, an invalid layout (which indicates a programming error) can be distinguished
+    /// from a genuine failure to allocate memory.
+    pub fn try_allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocationError> {
+        if layout.size() == 0 {
+            return Ok(dangling(layout));
+        }
+
+        let ptr = sodium_allocate(layout)?;
+
+        #[cfg(feature = "track-allocations")]
+        tracking::record_allocation(ptr, layout, None);
+
+        Ok(ptr)
+    }
+
+    /// Allocate a block of memory for the given [`Layout`], associating it with `tag`.
+    ///
+    /// This is equivalent to [`Allocator::allocate`], but the allocation will be grouped under
+    /// `tag` in the report produced by [`stats`], which can help to identify which code is
+    /// responsible for an allocation. The memory should be freed with
+    /// [`Allocator::deallocate`] as usual.
+    ///
+    /// Only available with the `track-allocations` feature enabled.
+    #[cfg(feature = "track-allocations")]
+    pub fn tagged_allocate(
+        &self,
+        layout: Layout,
+        tag: &'static str,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            return Ok(dangling(layout));
+        }
+
+        let ptr = sodium_allocate(layout)?;
+        tracking::record_allocation(ptr, layout, Some(tag));
+
+        Ok(ptr)
+    }
+
+    /// Reset the peak memory usage reported in [`AllocStats::peak_bytes`] to the current total
+    /// size of live allocations.
+    ///
+    /// This is useful for measuring the peak memory usage of each phase of a program separately.
+    /// The counts of live allocations always reflect the allocations which currently exist, so
+    /// can't be reset.
+    ///
+    /// Only available with the `track-allocations` feature enabled.
+    #[cfg(feature = "track-allocations")]
+    pub fn reset_peak() {
+        tracking::reset_peak();
+    }
+}
+
 unsafe impl Allocator for SodiumAllocator {
-    fn allocate(&self, mut layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
-        // Initialise libsodium, okay to call this multiple times from multiple threads, the actual
-        // initialisation will only happen once.
-        // We don't call this in other functions, as it's assumed we have to have called
-        // `Self::allocate` to get some memory to do other things with (e.g: deallocate, grow).
-        init()?;
-
-        // Increase the size of the layout so it's a multiple of layout.align - as Sodium allocates
-        // memory at the end of the page, as long as the layout size is a multiple of the
-        // alignment, and the alignment is a power of 2, the allocation will be correctly aligned.
-        layout = layout.pad_to_align();
-
-        // Calling `sodium_malloc` with a size that's a multiple of n produces a pointer aligned to
-        // n.
-        // SAFETY: This function returns a pointer to `layout.size()` of allocated memory, or NULL
-        // if allocation failed. We immediately check for NULL in the next line, and return an
-        // error if it occurs. If the result is not NULL, Sodium guarantees that the pointer will
-        // reference at least `layout.size()` of allocated, mutable memory.
-        let ptr = unsafe { sodium::sodium_malloc(layout.size()) as *mut u8 };
-        // NonNull::new() will return Some if `ptr` was non-null, but will return None if `ptr` was
-        // null. We convert the latter result into an error.
-        let ptr = NonNull::new(ptr).ok_or(AllocError)?;
-
-        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
-    }
-
-    unsafe fn deallocate(&self, ptr: NonNull<u8>, _layout: Layout) {
-        sodium::sodium_free(ptr.as_ptr() as *mut c_void);
-    }
-
-    // We just use the default implementations of the other methods: Sodium doesn't provide any API
-    // to grow/shrink memory, so we would have to just allocate new memory then copy for any of
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        Ok(self.try_allocate(layout)?)
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = self.allocate(layout)?;
+
+        // Sodium fills newly allocated memory with garbage bytes (0xdb), so we always need to
+        // zero it explicitly.
+        // SAFETY: We just allocated `ptr.len()` bytes at `ptr`, and Sodium has initialised them.
+        memzero(unsafe { &mut *ptr.as_ptr() });
+
+        Ok(ptr)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        // Zero-size blocks are never allocated by Sodium (see `dangling`).
+        if layout.size() == 0 {
+            return;
+        }
+
+        #[cfg(feature = "track-allocations")]
+        tracking::record_deallocation(ptr, layout);
+
+        sodium_free(ptr, layout);
+    }
+
+    // We just use the default implementations of the remaining methods: Sodium doesn't provide any
+    // API to grow/shrink memory, so we would have to just allocate new memory then copy for any of
     // these types of operations, which is what the default operations already do.
 }
 
+/// Get a dangling pointer for a zero-size allocation with the given layout.
+///
+/// Zero-size blocks hold no secrets, so there's no need to guard them. `sodium_malloc(0)` would
+/// still use several pages, and the pointer it returns isn't guaranteed to be aligned to
+/// `layout.align()`. Instead, like the standard allocators, we return a pointer which is suitably
+/// aligned, but not backed by any memory. Such blocks aren't tracked, as they don't have unique
+/// addresses, and deallocating them does nothing.
+fn dangling(layout: Layout) -> NonNull<[u8]> {
+    // SAFETY: An alignment is always non-zero.
+    let ptr = unsafe { NonNull::new_unchecked(ptr::without_provenance_mut(layout.align())) };
+
+    NonNull::slice_from_raw_parts(ptr, 0)
+}
+
+/// Allocate memory for `layout` using `sodium_malloc`.
+fn sodium_allocate(layout: Layout) -> Result<NonNull<[u8]>, AllocationError> {
+    // Initialise libsodium, okay to call this multiple times from multiple threads, the actual
+    // initialisation will only happen once.
+    // We don't call this in other functions, as it's assumed we have to have called
+    // `Self::allocate` to get some memory to do other things with (e.g: deallocate, grow).
+    init()?;
+
+    let ptr = if layout.align() > page_size() {
+        overaligned_allocate(layout)?
+    } else {
+        aligned_allocate(layout)?
+    };
+    memlock::record_locked(memlock::locked_size(layout));
+
+    Ok(ptr)
+}
+
+/// Allocate memory for a layout with an alignment no greater than the page size.
+///
+/// libsodium must have been initialised.
+fn aligned_allocate(layout: Layout) -> Result<NonNull<[u8]>, AllocationError> {
+    // Increase the size of the layout so it's a multiple of layout.align - as Sodium allocates
+    // memory at the end of the page, as long as the layout size is a multiple of the
+    // alignment, and the alignment is a power of 2, the allocation will be correctly aligned.
+    let size = padded_size(layout.size(), layout.align())?;
+
+    // Calling `sodium_malloc` with a size that's a multiple of n produces a pointer aligned to
+    // n.
+    // SAFETY: This function returns a pointer to `size` bytes of allocated memory, or NULL if
+    // allocation failed. We immediately check for NULL in the next line, and return an error
+    // if it occurs. If the result is not NULL, Sodium guarantees that the pointer will
+    // reference at least `size` bytes of allocated, mutable memory.
+    let ptr = unsafe { sys::malloc(size) };
+    // NonNull::new() will return Some if `ptr` was non-null, but will return None if `ptr` was
+    // null. We convert the latter result into an error.
+    let ptr = NonNull::new(ptr).ok_or(AllocationError::OutOfMemory)?;
+
+    Ok(NonNull::slice_from_raw_parts(ptr, size))
+}
+
+/// Allocate memory for a layout with an alignment greater than the page size.
+///
+/// Placing the allocation at the end of a page only guarantees alignment to the page size, so
+/// instead we allocate an extra `layout.align()` bytes, and return a suitably aligned pointer
+/// within the allocation. The pointer returned by Sodium is stored immediately before the aligned
+/// pointer, so that it can be recovered by [`sodium_free`].
+///
+/// libsodium must have been initialised.
+fn overaligned_allocate(layout: Layout) -> Result<NonNull<[u8]>, AllocationError> {
+    let size = padded_size(layout.size(), layout.align())?;
+    let total = malloc_size(layout)?;
+
+    // SAFETY: libsodium has been initialised, and we check for NULL below.
+    let base = unsafe { sys::malloc(total) };
+    let base = NonNull::new(base).ok_or(AllocationError::OutOfMemory)?;
+
+    // `total` is a multiple of the page size (as `align` is), and the allocation ends at the end
+    // of a page, so `base` is page-aligned. We always move forward by at least one page, leaving
+    // room to store `base`, and at most `align` bytes, so the aligned block still fits within the
+    // allocation.
+    let offset = layout.align() - (base.as_ptr() as usize & (layout.align() - 1));
+    // SAFETY: `offset <= align`, so `ptr + size` is within the allocation. `offset` is at least
+    // one page, so the bytes immediately before `ptr` are within the allocation, and suitably
+    // aligned to store a pointer, as `ptr` is page-aligned.
+    let ptr = unsafe {
+        let ptr = base.as_ptr().add(offset);
+        (ptr as *mut *mut u8).sub(1).write(base.as_ptr());
+        NonNull::new_unchecked(ptr)
+    };
+
+    Ok(NonNull::slice_from_raw_parts(ptr, size))
+}
+
+/// Calculate the number of bytes requested from `sodium_malloc` to allocate `layout`.
+///
+/// This is the padded size of the layout, plus space to align the allocation within the block
+/// returned by Sodium if the alignment is greater than the page size.
+fn malloc_size(layout: Layout) -> Result<usize, AllocationError> {
+    let size = padded_size(layout.size(), layout.align())?;
+    if layout.align() > page_size() {
+        size.checked_add(layout.align())
+            .filter(|&s| s <= isize::MAX as usize)
+            .ok_or(AllocationError::LayoutError)
+    } else {
+        Ok(size)
+    }
+}
+
+/// Free memory allocated for `layout` by [`sodium_allocate`].
+///
+/// # Safety
+/// `ptr` must have been allocated by [`sodium_allocate`] with a layout which fits `layout`, and
+/// must not have been freed already.
+unsafe fn sodium_free(ptr: NonNull<u8>, layout: Layout) {
+    memlock::record_unlocked(memlock::locked_size(layout));
+    sys::free(sodium_base(ptr, layout));
+}
+
+/// Get the pointer returned by `sodium_malloc` for the allocation at `ptr`.
+///
+/// # Safety
+/// `ptr` must be a live allocation returned by [`sodium_allocate`] for `layout`.
+unsafe fn sodium_base(ptr: NonNull<u8>, layout: Layout) -> *mut u8 {
+    if layout.align() > page_size() {
+        // Recover the pointer stored by `overaligned_allocate`.
+        (ptr.as_ptr() as *mut *mut u8).sub(1).read()
+    } else {
+        ptr.as_ptr()
+    }
+}
+
+/// Calculate the size of an allocation of `size` bytes, padded to a multiple of `align`.
+///
+/// The padded size must not exceed `isize::MAX`, the largest size of any allocation in Rust.
+///
+/// A [`Layout`] should always satisfy these requirements, but we check them explicitly before
+/// passing anything to Sodium, so that a bad layout is reported as such, rather than as a failure
+/// to allocate.
+fn padded_size(size: usize, align: usize) -> Result<usize, AllocationError> {
+    if !align.is_power_of_two() {
+        return Err(AllocationError::LayoutError);
+    }
+
+    // As `align` is a power of 2, `align - 1` is a mask of the bits which must be zero in a
+    // multiple of `align`.
+    let mask = align - 1;
+    size.checked_add(mask)
+        .map(|s| s & !mask)
+        .filter(|&s| s <= isize::MAX as usize)
+        .ok_or(AllocationError::LayoutError)
+}
+
 /// Initialise libsodium.
 ///
-/// Called automatically when an attempt to allocate is made.
-fn init() -> Result<(), AllocError> {
-    unsafe {
-        if sodium::sodium_init() >= 0 {
-            Ok(())
-        } else {
-            Err(AllocError)
-        }
+/// Called automatically when an attempt to allocate is made. Once initialisation has succeeded,
+/// or failed permanently, the result is cached (see [`init_state`]).
+fn init() -> Result<(), AllocationError> {
+    state::INIT.get_or_init(init_sodium)
+}
+
+/// Attempt to initialise libsodium, classifying any failure as transient or permanent.
+fn init_sodium() -> state::InitOutcome {
+    if !sys::init() {
+        return state::InitOutcome::Transient;
+    }
+
+    // The shim selected by `test-backend` never provides guard pages, but is chosen deliberately
+    // for testing, so it isn't refused.
+    #[cfg(all(feature = "require-guard-pages", not(feature = "test-backend")))]
+    if !aborts_on_misuse() {
+        return state::InitOutcome::Permanent;
     }
+
+    state::InitOutcome::Ready
+}
+
+/// Initialise libsodium when the program is loaded, before `main` runs.
+///
+/// Only used with the `global-init` feature enabled. This means initialisation fails fast, rather
+/// than on the first allocation. The tradeoff is that this runs before `main`, where very little
+/// of the standard library can be relied upon, and failure can't be handled: If libsodium can't be
+/// initialised, the program is terminated with a panic message.
+#[cfg(feature = "global-init")]
+#[ctor::ctor]
+fn global_init() {
+    if init().is_err() {
+        panic!("sodium-alloc: failed to initialise libsodium");
+    }
+}
+
+/// Initialise libsodium, and prime the allocation code paths.
+///
+/// The first allocation made using Sodium pays several one-time costs: libsodium must be
+/// initialised (which includes seeding its random number generator and generating the canary),
+/// and the allocator's own code and data must be faulted into memory. This function performs
+/// these steps, by initialising libsodium and then allocating and freeing a small guarded buffer.
+/// Calling it during startup moves these costs off of latency-sensitive code paths.
+///
+/// Calling this function is never required, and it's fine to call it more than once. With the
+/// `global-init` feature enabled, libsodium is instead initialised before `main` runs, but this
+/// function can still be used to prime the allocation code paths.
+///
+/// Returns [`AllocError`] if libsodium could not be initialised, or the buffer could not be
+/// allocated.
+pub fn warmup() -> Result<(), AllocError> {
+    init()?;
+
+    let layout = Layout::new::<[u8; 32]>();
+    let ptr = SodiumAllocator.allocate_zeroed(layout)?;
+    // SAFETY: We just allocated `ptr` with this layout.
+    unsafe { SodiumAllocator.deallocate(ptr.cast(), layout) };
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -146,6 +550,25 @@ mod tests {
     use super::*;
     use std::alloc::Layout;
     use std::error::Error;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    /// Wraps `SodiumAllocator`, counting the number of times memory is freed.
+    #[derive(Clone)]
+    struct CountingAllocator(Arc<AtomicUsize>);
+
+    unsafe impl Allocator for CountingAllocator {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            SodiumAllocator.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            SodiumAllocator.deallocate(ptr, layout);
+        }
+    }
 
     #[test]
     fn basic_allocation() -> Result<(), Box<dyn Error>> {
@@ -189,6 +612,129 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn cache_line_alignment_guarded() -> Result<(), Box<dyn Error>> {
+        for size in [1, 64, 100, 4096] {
+            let layout = Layout::from_size_align(size, 64)?;
+            let ptr = SodiumAllocator.allocate(layout)?;
+
+            let start = ptr.cast::<u8>().as_ptr() as usize;
+            assert_eq!(start % 64, 0);
+            // The allocation should still end at the end of a page, immediately before the
+            // trailing guard page.
+            assert_eq!((start + ptr.len()) % page_size(), 0);
+            assert!(ptr.len() >= size);
+
+            unsafe {
+                (*ptr.as_ptr()).fill(0x13);
+                SodiumAllocator.deallocate(ptr.cast(), layout);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn simd_alignment() {
+        // An AES-128 key schedule, whose size isn't a multiple of its alignment in general
+        #[repr(C, align(16))]
+        struct RoundKeys<const N: usize>([u32; N]);
+
+        fn check<const N: usize>() {
+            let keys = Box::new_in(RoundKeys([0x13u32; N]), SodiumAllocator);
+            assert_eq!(&*keys as *const _ as usize % 16, 0);
+            assert!(keys.0.iter().all(|&k| k == 0x13));
+        }
+        check::<44>();
+        check::<43>();
+        check::<1>();
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::arch::x86_64::{__m128i, _mm_load_si128, _mm_set1_epi32, _mm_storeu_si128};
+
+            for len in 1..=15 {
+                let mut schedule = Vec::with_capacity_in(len, SodiumAllocator);
+                // SAFETY: SSE2 is always available on x86_64.
+                schedule.resize(len, unsafe { _mm_set1_epi32(0x13) });
+                let schedule: Box<[__m128i], _> = schedule.into_boxed_slice();
+                assert_eq!(schedule.as_ptr() as usize % 16, 0);
+
+                for round_key in schedule.iter() {
+                    let mut out = [0u32; 4];
+                    // SAFETY: `round_key` is 16-byte aligned, as required by `_mm_load_si128`.
+                    unsafe {
+                        let loaded = _mm_load_si128(round_key);
+                        _mm_storeu_si128(out.as_mut_ptr() as *mut __m128i, loaded);
+                    }
+                    assert_eq!(out, [0x13; 4]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn page_overaligned_allocation() -> Result<(), Box<dyn Error>> {
+        for align in [page_size() * 2, page_size() * 16] {
+            for size in [0, 1, align, align + 1] {
+                let layout = Layout::from_size_align(size, align)?;
+                let ptr = SodiumAllocator.allocate(layout)?;
+                assert_eq!(ptr.cast::<u8>().as_ptr() as usize % align, 0);
+                assert!(ptr.len() >= size);
+
+                unsafe {
+                    (*ptr.as_ptr()).fill(0x13);
+                    SodiumAllocator.deallocate(ptr.cast(), layout);
+                }
+            }
+        }
+
+        // Larger than any page size in common use
+        #[repr(align(0x10000))]
+        struct Aligned([u8; 32]);
+
+        let boxed = Box::<Aligned, _>::new_zeroed_in(SodiumAllocator);
+        let boxed = unsafe { boxed.assume_init() };
+        assert_eq!(&*boxed as *const _ as usize % 0x10000, 0);
+        assert_eq!(boxed.0, [0; 32]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn zeroed_allocation() -> Result<(), Box<dyn Error>> {
+        for size in [1, 13, 4096, 10000] {
+            let layout = Layout::from_size_align(size, 1)?;
+
+            // Sodium fills uninitialised memory with garbage, rather than zeroes
+            let ptr = SodiumAllocator.allocate(layout)?;
+            assert!(unsafe { ptr.as_ref() }.iter().all(|&b| b == 0xdb));
+            unsafe { SodiumAllocator.deallocate(ptr.cast(), layout) };
+
+            let ptr = SodiumAllocator.allocate_zeroed(layout)?;
+            assert_eq!(ptr.len(), size);
+            assert!(unsafe { ptr.as_ref() }.iter().all(|&b| b == 0));
+            unsafe { SodiumAllocator.deallocate(ptr.cast(), layout) };
+
+            let zeroed = Box::<[u8], _>::new_zeroed_slice_in(size, SodiumAllocator);
+            let zeroed = unsafe { zeroed.assume_init() };
+            assert!(zeroed.iter().all(|&b| b == 0));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn warmup_then_allocate() -> Result<(), Box<dyn Error>> {
+        warmup()?;
+        warmup()?;
+
+        let key = Box::try_new_in([0x13u8; 32], SodiumAllocator)?;
+        assert_eq!(*key, [0x13; 32]);
+
+        Ok(())
+    }
+
     #[test]
     fn zero_size_alloc() -> Result<(), Box<dyn Error>> {
         let layout = Layout::from_size_align(0, 1)?;
@@ -204,6 +750,101 @@ mod tests {
     }
 
     #[test]
+    fn zero_size_alignment() -> Result<(), Box<dyn Error>> {
+        #[repr(align(16))]
+        struct Align16;
+        #[repr(align(64))]
+        struct Align64;
+
+        let layouts = [
+            Layout::new::<()>(),
+            Layout::new::<Align16>(),
+            Layout::new::<Align64>(),
+        ];
+        for layout in layouts {
+            let ptr = SodiumAllocator.allocate(layout)?;
+            assert_eq!(ptr.len(), 0);
+            assert_eq!(ptr.cast::<u8>().as_ptr() as usize % layout.align(), 0);
+
+            let zeroed = SodiumAllocator.allocate_zeroed(layout)?;
+            assert_eq!(zeroed.cast::<u8>().as_ptr() as usize % layout.align(), 0);
+
+            unsafe {
+                SodiumAllocator.deallocate(ptr.cast(), layout);
+                SodiumAllocator.deallocate(zeroed.cast(), layout);
+            }
+        }
+
+        let boxed = Box::new_in(Align64, SodiumAllocator);
+        assert_eq!(&*boxed as *const Align64 as usize % 64, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn oversized_allocation() -> Result<(), Box<dyn Error>> {
+        // Far larger than any system could provide, this should fail cleanly
+        let layout = Layout::from_size_align(usize::MAX / 2, 1)?;
+        assert_eq!(SodiumAllocator.allocate(layout), Err(AllocError));
+        assert_eq!(
+            SodiumAllocator.try_allocate(layout),
+            Err(AllocationError::OutOfMemory)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn padding_overflow() {
+        assert_eq!(
+            padded_size(usize::MAX, 8),
+            Err(AllocationError::LayoutError)
+        );
+        assert_eq!(
+            padded_size(usize::MAX - 6, 8),
+            Err(AllocationError::LayoutError)
+        );
+        assert_eq!(
+            padded_size(usize::MAX - 7, 8),
+            Err(AllocationError::LayoutError)
+        );
+    }
+
+    #[test]
+    fn isize_max_boundary() -> Result<(), Box<dyn Error>> {
+        let max = isize::MAX as usize;
+        assert_eq!(padded_size(max, 1), Ok(max));
+        assert_eq!(padded_size(max + 1, 1), Err(AllocationError::LayoutError));
+        // Padding must not take the size past the boundary either
+        assert_eq!(padded_size(max, 2), Err(AllocationError::LayoutError));
+        assert_eq!(padded_size(max - 7, 8), Ok(max - 7));
+
+        // The largest valid layout is too big to allocate, but isn't rejected as invalid
+        let layout = Layout::from_size_align(max, 1)?;
+        assert_eq!(
+            SodiumAllocator.try_allocate(layout),
+            Err(AllocationError::OutOfMemory)
+        );
+
+        // Over-aligned allocations need extra space, which may exceed the boundary
+        let layout = Layout::from_size_align(max - page_size() * 2 + 1, page_size() * 2)?;
+        assert_eq!(
+            SodiumAllocator.try_allocate(layout),
+            Err(AllocationError::LayoutError)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn non_power_of_two_align() {
+        assert_eq!(padded_size(16, 0), Err(AllocationError::LayoutError));
+        assert_eq!(padded_size(16, 3), Err(AllocationError::LayoutError));
+        assert_eq!(padded_size(13, 4), Ok(16));
+    }
+
+    #[test]
+    #[allow(clippy::same_item_push)]
     fn test_writing() {
         for i in 0..29 {
             let mut v: Vec<u8, _> = Vec::with_capacity_in(1 << i, SodiumAllocator);
@@ -212,4 +853,58 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn arc_shared_across_threads() {
+        let frees = Arc::new(AtomicUsize::new(0));
+        let key = Arc::new_in([0x13u8; 32], CountingAllocator(frees.clone()));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let key = Arc::clone(&key);
+                thread::spawn(move || assert_eq!(*key, [0x13; 32]))
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // Only the original reference remains, the memory should not have been freed yet.
+        assert_eq!(Arc::strong_count(&key), 1);
+        assert_eq!(frees.load(Ordering::SeqCst), 0);
+
+        drop(key);
+        assert_eq!(frees.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn arc_weak_delays_free() {
+        let frees = Arc::new(AtomicUsize::new(0));
+        let key = Arc::new_in([0x13u8; 32], CountingAllocator(frees.clone()));
+        let weak = Arc::downgrade(&key);
+
+        drop(key);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(frees.load(Ordering::SeqCst), 0);
+
+        drop(weak);
+        assert_eq!(frees.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn rc_new_in() {
+        let frees = Arc::new(AtomicUsize::new(0));
+        let key = Rc::new_in([0x13u8; 32], CountingAllocator(frees.clone()));
+        let key_clone = Rc::clone(&key);
+
+        drop(key);
+        assert_eq!(*key_clone, [0x13; 32]);
+        assert_eq!(frees.load(Ordering::SeqCst), 0);
+
+        drop(key_clone);
+        assert_eq!(frees.load(Ordering::SeqCst), 1);
+
+        let key = Rc::new_in([0x13u8; 32], SodiumAllocator);
+        assert_eq!(*key, [0x13; 32]);
+    }
 }
@@ -0,0 +1,101 @@
+//! Information about how libsodium was linked.
+
+use crate::sys;
+
+/// How libsodium was linked into the program.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Linkage {
+    /// libsodium was built from the source bundled with `libsodium-sys`, and linked statically.
+    ///
+    /// This is the default.
+    Vendored,
+
+    /// A prebuilt libsodium from `SODIUM_LIB_DIR` was linked statically.
+    Static,
+
+    /// A prebuilt libsodium from `SODIUM_LIB_DIR` was linked dynamically (`SODIUM_SHARED` was
+    /// set).
+    Dynamic,
+
+    /// A system libsodium was found using pkg-config or vcpkg, which may have linked it either
+    /// statically or dynamically.
+    Unknown,
+}
+
+/// Information about how libsodium was linked, returned by [`linkage_info`].
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct LinkageInfo {
+    /// The version of libsodium in use at runtime, as reported by `sodium_version_string`.
+    pub version: &'static str,
+
+    /// The version of the libsodium headers this crate was built against, if known.
+    ///
+    /// This is always known for [`Linkage::Vendored`] builds.
+    pub expected_version: Option<&'static str>,
+
+    /// How libsodium was linked.
+    pub linkage: Linkage,
+}
+
+impl LinkageInfo {
+    /// Returns whether the version of libsodium in use at runtime is the version this crate was
+    /// built against.
+    ///
+    /// This may be `false` if libsodium was linked dynamically, and a different version of the
+    /// library was loaded at runtime. Returns `false` if the expected version is unknown.
+    pub fn is_expected_version(&self) -> bool {
+        self.expected_version == Some(self.version)
+    }
+}
+
+/// Get information about how libsodium was linked into the program.
+///
+/// The linkage is determined at build time, from the same environment variables used by
+/// `libsodium-sys` to decide how to link libsodium (`SODIUM_LIB_DIR`, `SODIUM_SHARED` and
+/// `SODIUM_USE_PKG_CONFIG`). This can be used in a startup self-check, to ensure a vetted
+/// libsodium is in use, rather than a system shared library:
+///
+/// ```
+/// use sodium_alloc::{linkage_info, Linkage};
+///
+/// let info = linkage_info();
+/// if info.linkage != Linkage::Vendored || !info.is_expected_version() {
+///     eprintln!("warning: unexpected libsodium in use: {:?}", info);
+/// }
+/// ```
+pub fn linkage_info() -> LinkageInfo {
+    let expected_version = env!("SODIUM_ALLOC_HEADER_VERSION");
+    let linkage = match env!("SODIUM_ALLOC_LINKAGE") {
+        "vendored" => Linkage::Vendored,
+        "static" => Linkage::Static,
+        "dynamic" => Linkage::Dynamic,
+        _ => Linkage::Unknown,
+    };
+
+    LinkageInfo {
+        version: sys::version(),
+        expected_version: (!expected_version.is_empty()).then_some(expected_version),
+        linkage,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consistent_info() {
+        let info = linkage_info();
+        assert_eq!(linkage_info(), info);
+        assert!(!info.version.is_empty());
+
+        // Unless configured otherwise, libsodium is built from source and statically linked
+        if option_env!("SODIUM_LIB_DIR").is_none() && option_env!("SODIUM_USE_PKG_CONFIG").is_none()
+        {
+            assert_eq!(info.linkage, Linkage::Vendored);
+            assert!(info.expected_version.is_some());
+            #[cfg(not(any(miri, feature = "test-backend")))]
+            assert!(info.is_expected_version());
+        }
+    }
+}
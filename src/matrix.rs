@@ -0,0 +1,201 @@
+//! Two-dimensional arrays of secrets, stored in memory allocated using Sodium.
+
+use crate::{SodiumAllocator, Zeroable};
+use std::alloc::AllocError;
+use std::ops::{Index, IndexMut};
+
+/// A matrix with `rows` rows and `cols` columns of `T`, stored in row-major order in a single
+/// allocation made using [`SodiumAllocator`].
+///
+/// This is intended for secret matrices (such as those used by lattice-based schemes). Storing the
+/// whole matrix in one allocation amortises the overhead of Sodium's guard pages across every
+/// element, rather than paying it for each row. The elements are zeroed when the matrix is
+/// created, and the memory is securely zeroed when the `SecureMatrix` is dropped.
+///
+/// Elements can be accessed with bounds-checked [`get`](Self::get) and
+/// [`get_mut`](Self::get_mut), or by indexing with a `(row, col)` pair, which panics if out of
+/// bounds:
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::SecureMatrix;
+///
+/// let mut s = SecureMatrix::<i16>::new(2, 3)?;
+/// s[(0, 2)] = -1;
+/// *s.get_mut(1, 0).unwrap() = 7;
+/// assert_eq!(s.row(0), &[0, 0, -1]);
+/// assert_eq!(s.get(1, 0), Some(&7));
+/// assert_eq!(s.get(2, 0), None);
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+pub struct SecureMatrix<T> {
+    data: Box<[T], SodiumAllocator>,
+    rows: usize,
+    cols: usize,
+}
+
+impl<T: Zeroable> SecureMatrix<T> {
+    /// Create a new `SecureMatrix` with `rows` rows and `cols` columns, with every element
+    /// initialised to zero.
+    ///
+    /// The matrix is zeroed in place, so no temporary copy of it is made.
+    ///
+    /// Returns [`AllocError`] if the total size of the matrix overflows, or the memory could not
+    /// be allocated.
+    pub fn new(rows: usize, cols: usize) -> Result<Self, AllocError> {
+        let len = rows.checked_mul(cols).ok_or(AllocError)?;
+        let data = Box::try_new_zeroed_slice_in(len, SodiumAllocator)?;
+
+        Ok(Self {
+            // SAFETY: `T: Zeroable`, so a zeroed `T` is valid.
+            data: unsafe { data.assume_init() },
+            rows,
+            cols,
+        })
+    }
+}
+
+impl<T> SecureMatrix<T> {
+    /// The number of rows in the matrix.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// The number of columns in the matrix.
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Get a reference to the element at (`row`, `col`), or `None` if it is out of bounds.
+    pub fn get(&self, row: usize, col: usize) -> Option<&T> {
+        self.offset(row, col).map(|i| &self.data[i])
+    }
+
+    /// Get a mutable reference to the element at (`row`, `col`), or `None` if it is out of
+    /// bounds.
+    pub fn get_mut(&mut self, row: usize, col: usize) -> Option<&mut T> {
+        let i = self.offset(row, col)?;
+        Some(&mut self.data[i])
+    }
+
+    /// Get the elements of row `row`.
+    ///
+    /// # Panics
+    /// Panics if `row` is out of bounds.
+    pub fn row(&self, row: usize) -> &[T] {
+        assert!(row < self.rows, "row out of bounds");
+        &self.data[row * self.cols..][..self.cols]
+    }
+
+    /// Get the elements of row `row`, mutably.
+    ///
+    /// # Panics
+    /// Panics if `row` is out of bounds.
+    pub fn row_mut(&mut self, row: usize) -> &mut [T] {
+        assert!(row < self.rows, "row out of bounds");
+        &mut self.data[row * self.cols..][..self.cols]
+    }
+
+    /// Get every element of the matrix, in row-major order.
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    /// Get every element of the matrix mutably, in row-major order.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+
+    /// Calculate the index of (`row`, `col`) in `data`, or `None` if it is out of bounds.
+    fn offset(&self, row: usize, col: usize) -> Option<usize> {
+        (row < self.rows && col < self.cols).then(|| row * self.cols + col)
+    }
+}
+
+impl<T> Index<(usize, usize)> for SecureMatrix<T> {
+    type Output = T;
+
+    fn index(&self, (row, col): (usize, usize)) -> &Self::Output {
+        self.get(row, col).expect("matrix index out of bounds")
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for SecureMatrix<T> {
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut Self::Output {
+        self.get_mut(row, col).expect("matrix index out of bounds")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_and_read() -> Result<(), AllocError> {
+        let mut m = SecureMatrix::<u32>::new(3, 4)?;
+        assert_eq!((m.rows(), m.cols()), (3, 4));
+        assert!(m.as_slice().iter().all(|&x| x == 0));
+
+        for r in 0..3 {
+            for c in 0..4 {
+                *m.get_mut(r, c).unwrap() = (r * 10 + c) as u32;
+            }
+        }
+        assert_eq!(m.get(2, 3), Some(&23));
+        assert_eq!(m[(1, 2)], 12);
+        assert_eq!(m.row(1), &[10, 11, 12, 13]);
+        m.row_mut(0).fill(7);
+        assert_eq!(m.as_slice()[..5], [7, 7, 7, 7, 10]);
+
+        // Out of bounds in either dimension
+        assert_eq!(m.get(3, 0), None);
+        assert_eq!(m.get(0, 4), None);
+        assert!(m.get_mut(0, 4).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn single_allocation() -> Result<(), AllocError> {
+        let m = SecureMatrix::<[u8; 32]>::new(16, 16)?;
+        let start = m.as_slice().as_ptr() as usize;
+
+        // Every row lies within one contiguous block
+        for r in 0..m.rows() {
+            let row = m.row(r).as_ptr() as usize;
+            assert_eq!(row, start + r * 16 * 32);
+        }
+
+        #[cfg(feature = "track-allocations")]
+        {
+            use crate::tracking::is_live;
+
+            let ptr = m.as_slice().as_ptr();
+            assert!(is_live(ptr));
+            // No other element starts an allocation
+            assert!(!is_live(m.row(1).as_ptr()));
+            drop(m);
+            assert!(!is_live(ptr));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn empty_and_overflowing() -> Result<(), AllocError> {
+        let m = SecureMatrix::<u64>::new(0, 5)?;
+        assert!(m.as_slice().is_empty());
+        assert_eq!(m.get(0, 0), None);
+
+        assert!(SecureMatrix::<u8>::new(usize::MAX, 2).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn index_out_of_bounds() {
+        let m = SecureMatrix::<u8>::new(2, 2).unwrap();
+        let _ = m[(0, 2)];
+    }
+}
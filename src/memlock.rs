@@ -0,0 +1,184 @@
+//! Accounting of the memory locked by allocations made using Sodium.
+
+use crate::malloc_size;
+use crate::page::{round_to_page, CANARY_SIZE};
+use std::alloc::Layout;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The total number of bytes locked by live allocations.
+static LOCKED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the total amount of memory locked by live allocations made using
+/// [`SodiumAllocator`](crate::SodiumAllocator) (or a
+/// [`ConfiguredAllocator`](crate::ConfiguredAllocator)), in bytes.
+///
+/// Sodium locks the pages holding each allocation and its canary, so each allocation locks at
+/// least one page. Allocations made by a `ConfiguredAllocator` with
+/// [`mlock`](crate::ConfiguredAllocator::mlock) disabled aren't counted. Memory locked by other
+/// means (including by this crate's [`SecureSlab`](crate::SecureSlab) and
+/// [`allocate_noaccess`](crate::allocate_noaccess), and by other code in the process) isn't
+/// counted either.
+pub fn locked_bytes() -> usize {
+    LOCKED_BYTES.load(Ordering::Relaxed)
+}
+
+/// Returns the amount of memory Sodium locks for an allocation with the given layout, in bytes.
+///
+/// This is the number of bytes each allocation counts against the process's `RLIMIT_MEMLOCK`
+/// limit, and is what each live allocation contributes to [`locked_bytes`]. Sodium only locks the
+/// pages holding the allocation and its canary: The guard pages surrounding them are mapped
+/// inaccessible and are never locked, and neither is the page Sodium uses to store the size of the
+/// allocation. So a small allocation locks a single page, even though it occupies several.
+///
+/// This doesn't check whether locking actually succeeded: Sodium ignores failure to lock memory
+/// (see [`overhead_report`](crate::overhead_report) for the total footprint of an allocation).
+/// Returns 0 for a zero-size layout, which doesn't use any memory, or a layout which can't be
+/// allocated.
+///
+/// ```
+/// use sodium_alloc::{locked_bytes_for, page_size};
+/// use std::alloc::Layout;
+///
+/// // 1000 small keys lock 1000 pages of memory
+/// let per_key = locked_bytes_for(Layout::new::<[u8; 32]>());
+/// assert_eq!(per_key, page_size());
+/// println!("1000 keys need a memlock limit of {} bytes", 1000 * per_key);
+/// ```
+pub fn locked_bytes_for(layout: Layout) -> usize {
+    locked_size(layout)
+}
+
+/// The number of bytes Sodium locks for an allocation with the given layout.
+pub(crate) fn locked_size(layout: Layout) -> usize {
+    if layout.size() == 0 {
+        return 0;
+    }
+    // The layout has already been validated if it was used to allocate.
+    malloc_size(layout).map_or(0, |size| round_to_page(size + CANARY_SIZE))
+}
+
+/// Record that `bytes` more bytes of memory have been locked.
+pub(crate) fn record_locked(bytes: usize) {
+    LOCKED_BYTES.fetch_add(bytes, Ordering::Relaxed);
+
+    #[cfg(test)]
+    tests::THREAD_DELTA.with(|d| d.set(d.get().wrapping_add(bytes as isize)));
+}
+
+/// Record that `bytes` bytes of memory have been unlocked.
+pub(crate) fn record_unlocked(bytes: usize) {
+    LOCKED_BYTES.fetch_sub(bytes, Ordering::Relaxed);
+
+    #[cfg(test)]
+    tests::THREAD_DELTA.with(|d| d.set(d.get().wrapping_sub(bytes as isize)));
+}
+
+/// Returns whether locking memory for an allocation with the given layout would exceed the
+/// process's `RLIMIT_MEMLOCK` soft limit, given the memory already locked by live allocations.
+///
+/// The limit is queried on each call, as it may change while the program runs. If it can't be
+/// queried, the allocation is assumed to fit.
+#[cfg(unix)]
+pub(crate) fn exceeds_limit(layout: Layout) -> bool {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is valid for writes.
+    if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } != 0
+        || limit.rlim_cur == libc::RLIM_INFINITY
+    {
+        return false;
+    }
+
+    let total = locked_bytes() as u128 + locked_size(layout) as u128;
+    total > limit.rlim_cur as u128
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{page_size, SodiumAllocator};
+    use std::cell::Cell;
+
+    thread_local! {
+        /// The net change in locked bytes recorded on this thread.
+        ///
+        /// Tests run concurrently, so [`locked_bytes`] can change at any time. Allocations made
+        /// by a single test are only recorded on its own thread, so this can be checked exactly.
+        pub(crate) static THREAD_DELTA: Cell<isize> = const { Cell::new(0) };
+    }
+
+    /// Get the net change in locked bytes recorded on this thread.
+    pub(crate) fn thread_delta() -> isize {
+        THREAD_DELTA.with(Cell::get)
+    }
+
+    #[test]
+    fn locked_size_page_rounded() {
+        let page = page_size();
+        assert_eq!(locked_size(Layout::new::<u8>()), page);
+        assert_eq!(locked_size(Layout::array::<u8>(page).unwrap()), 2 * page);
+        assert_eq!(
+            locked_size(Layout::array::<u8>(page - CANARY_SIZE).unwrap()),
+            page
+        );
+    }
+
+    /// Sum a field (measured in kB) of the memory mappings in `/proc/self/smaps` which contain
+    /// `addr`.
+    #[cfg(all(target_os = "linux", not(any(miri, feature = "test-backend"))))]
+    fn smaps_field(addr: usize, field: &str) -> Option<usize> {
+        let smaps = std::fs::read_to_string("/proc/self/smaps").ok()?;
+        let mut in_mapping = false;
+        for line in smaps.lines() {
+            let first = line.split_whitespace().next()?;
+            if let Some((start, end)) = first.split_once('-') {
+                let start = usize::from_str_radix(start, 16).ok()?;
+                let end = usize::from_str_radix(end, 16).ok()?;
+                in_mapping = (start..end).contains(&addr);
+            } else if in_mapping && first == field {
+                let kb = line.split_whitespace().nth(1)?.parse::<usize>().ok()?;
+                return Some(kb * 1024);
+            }
+        }
+
+        None
+    }
+
+    #[cfg(all(target_os = "linux", not(any(miri, feature = "test-backend"))))]
+    #[test]
+    fn guard_pages_not_locked() {
+        use std::alloc::Allocator;
+
+        if !crate::aborts_on_misuse() {
+            return;
+        }
+
+        let page = page_size();
+        let layout = Layout::new::<[u8; 32]>();
+        let ptr = SodiumAllocator.allocate(layout).unwrap().cast::<u8>();
+        let addr = ptr.as_ptr() as usize;
+
+        let locked = smaps_field(addr, "Locked:").unwrap();
+        // Sodium ignores failure to lock, e.g. if the memlock limit has been reached.
+        if locked != 0 {
+            // The data is in its own mapping, which is locked in its entirety...
+            assert_eq!(locked, locked_bytes_for(layout));
+            assert_eq!(smaps_field(addr, "Size:"), Some(page));
+            // ...but the guard pages on either side aren't locked
+            assert_eq!(smaps_field(addr - page, "Locked:"), Some(0));
+            assert_eq!(smaps_field(addr + layout.size(), "Locked:"), Some(0));
+        }
+
+        unsafe { SodiumAllocator.deallocate(ptr, layout) };
+    }
+
+    #[test]
+    fn allocations_counted() {
+        // Other tests allocate concurrently, so only check that this allocation is included.
+        let vec = Vec::<u8, _>::with_capacity_in(1 << 22, SodiumAllocator);
+        assert!(locked_bytes() >= 1 << 22);
+        drop(vec);
+    }
+}
@@ -0,0 +1,69 @@
+//! Detection of the protection model provided by the linked libsodium.
+
+use crate::sys;
+use std::sync::OnceLock;
+
+static ABORTS_ON_MISUSE: OnceLock<bool> = OnceLock::new();
+
+/// Returns whether the linked libsodium terminates the program when guarded memory is misused.
+///
+/// On platforms with support for page protection (including Linux, macOS and Windows), Sodium
+/// places guard pages around each allocation, and a canary before it, terminating the program if
+/// either is accessed or modified. However, if libsodium was built for a platform without page
+/// protection, `sodium_malloc` falls back to a plain `malloc` without any guard pages or canary,
+/// and out-of-bounds accesses will *not* be detected. Memory is still zeroed when freed in either
+/// case.
+///
+/// This function returns `true` if the linked libsodium provides the full guarded allocation
+/// model described in the documentation for [`SodiumAllocator`](crate::SodiumAllocator), and
+/// `false` if it only provides the weaker fallback (or libsodium could not be initialised). The
+/// result is determined on the first call, by checking whether libsodium's page protection
+/// functions are supported, and cached for subsequent calls.
+///
+/// If the `require-guard-pages` feature is enabled, [`SodiumAllocator`](crate::SodiumAllocator)
+/// will refuse to allocate any memory if this function returns `false`, unless the
+/// `test-backend` feature is also enabled. This function always returns `false` with
+/// `test-backend` enabled.
+pub fn aborts_on_misuse() -> bool {
+    *ABORTS_ON_MISUSE.get_or_init(probe)
+}
+
+fn probe() -> bool {
+    // SAFETY: We initialise libsodium before calling any other functions, and only call
+    // `sodium_mprotect_readwrite` and `sodium_free` if `sodium_malloc` succeeded.
+    unsafe {
+        if !sys::init() {
+            return false;
+        }
+
+        let ptr = sys::malloc(1);
+        if ptr.is_null() {
+            return false;
+        }
+        // Without page protection, the `sodium_mprotect_*` functions always fail with ENOSYS.
+        // With page protection, the memory is already read/write, so this is a no-op.
+        let protected = sys::mprotect_readwrite(ptr);
+        sys::free(ptr);
+
+        protected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probe_consistent() {
+        let result = aborts_on_misuse();
+        assert_eq!(aborts_on_misuse(), result);
+        assert_eq!(probe(), result);
+
+        // All of the major desktop platforms support page protection
+        #[cfg(all(
+            any(target_os = "linux", target_os = "macos", windows),
+            not(any(miri, feature = "test-backend"))
+        ))]
+        assert!(result);
+    }
+}
@@ -0,0 +1,217 @@
+//! Secrets which are kept inaccessible except while they are being read.
+
+use crate::{init, sys};
+use std::alloc::AllocError;
+use std::cell::Cell;
+use std::ptr::NonNull;
+
+/// A secret stored in guarded memory which is marked as inaccessible, except while it is being
+/// read using [`NoAccessSecret::unlock_for_read`].
+///
+/// Created by [`allocate_noaccess`]. Any access to the secret's memory outside of
+/// `unlock_for_read` (for example, through a stray pointer) will fault, terminating the program.
+/// This is suited to secrets which are loaded early, but only needed occasionally, such as a
+/// backup key, minimising the time for which they are readable at all.
+///
+/// If the linked libsodium doesn't support page protection (see
+/// [`aborts_on_misuse`](crate::aborts_on_misuse)), the memory simply remains accessible.
+///
+/// The memory is zeroed and freed when the `NoAccessSecret` is dropped.
+pub struct NoAccessSecret {
+    ptr: NonNull<u8>,
+    len: usize,
+    /// Whether the memory was successfully made inaccessible when it was created.
+    protected: bool,
+    /// The number of calls to `unlock_for_read` currently in progress on this thread.
+    readers: Cell<usize>,
+}
+
+// SAFETY: The secret is owned exclusively by this handle. It is not `Sync`: Unlocking the memory
+// for reading from several threads at once would race on the page protection.
+unsafe impl Send for NoAccessSecret {}
+
+/// Allocate a secret of `len` bytes, which is inaccessible until it is read using
+/// [`NoAccessSecret::unlock_for_read`].
+///
+/// The memory is zeroed, then passed to `init`, which should write the secret into it (for
+/// example, by reading it from a file). Once `init` returns, the memory is marked as
+/// inaccessible. If `init` panics, the memory is zeroed and freed while unwinding.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::allocate_noaccess;
+///
+/// let backup_key = allocate_noaccess(32, |buf| buf.fill(0x13))?;
+/// // ...much later...
+/// let sum = backup_key.unlock_for_read(|key| key.iter().map(|&b| b as u32).sum::<u32>());
+/// assert_eq!(sum, 0x13 * 32);
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+///
+/// Returns [`AllocError`] if the memory could not be allocated.
+pub fn allocate_noaccess<F: FnOnce(&mut [u8])>(
+    len: usize,
+    init_secret: F,
+) -> Result<NoAccessSecret, AllocError> {
+    init()?;
+    // SAFETY: libsodium has been initialised, and we check for NULL below.
+    let ptr = NonNull::new(unsafe { sys::malloc(len) }).ok_or(AllocError)?;
+    let mut secret = NoAccessSecret {
+        ptr,
+        len,
+        protected: false,
+        readers: Cell::new(0),
+    };
+
+    // SAFETY: We just allocated `len` bytes at `ptr`.
+    let buf = unsafe { std::slice::from_raw_parts_mut(ptr.as_ptr(), len) };
+    // Sodium fills new allocations with garbage bytes.
+    crate::memzero(buf);
+    init_secret(buf);
+
+    // SAFETY: `ptr` was allocated using `sys::malloc`, and hasn't been freed.
+    secret.protected = unsafe { sys::mprotect_noaccess(ptr.as_ptr()) };
+
+    Ok(secret)
+}
+
+impl NoAccessSecret {
+    /// The length of the secret, in bytes.
+    ///
+    /// This doesn't require access to the secret's memory.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the secret has a length of zero.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Make the secret readable, and call `f` with it, before making it inaccessible again.
+    ///
+    /// The memory is only read-only while `f` runs: Any attempt to write to it will fault. It is
+    /// made inaccessible again when `f` returns, or if it panics. Calls to `unlock_for_read` may
+    /// be nested (for example, within `f`), in which case the memory is made inaccessible once the
+    /// outermost call returns.
+    ///
+    /// # Panics
+    /// Panics if the memory was made inaccessible, but couldn't be made readable.
+    pub fn unlock_for_read<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+        /// Makes the memory inaccessible again once the outermost reader has finished.
+        struct Relock<'a>(&'a NoAccessSecret);
+
+        impl Drop for Relock<'_> {
+            fn drop(&mut self) {
+                let secret = self.0;
+                secret.readers.set(secret.readers.get() - 1);
+                if secret.protected && secret.readers.get() == 0 {
+                    // SAFETY: The pointer was allocated using `sys::malloc`, and hasn't been
+                    // freed. No references to the memory outlive `f`.
+                    unsafe { sys::mprotect_noaccess(secret.ptr.as_ptr()) };
+                }
+            }
+        }
+
+        if self.protected && self.readers.get() == 0 {
+            // SAFETY: The pointer was allocated using `sys::malloc`, and hasn't been freed.
+            let readable = unsafe { sys::mprotect_readonly(self.ptr.as_ptr()) };
+            assert!(readable, "failed to make secret readable");
+        }
+        self.readers.set(self.readers.get() + 1);
+        let _relock = Relock(self);
+
+        // SAFETY: The memory is readable, holds `len` initialised bytes, and is never written
+        // while this handle exists.
+        f(unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) })
+    }
+}
+
+impl Drop for NoAccessSecret {
+    fn drop(&mut self) {
+        // SAFETY: The pointer was allocated using `sys::malloc`, and is only freed here. The
+        // memory must be writable for `sodium_free` to zero it.
+        unsafe {
+            if self.protected {
+                sys::mprotect_readwrite(self.ptr.as_ptr());
+            }
+            sys::free(self.ptr.as_ptr());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_cycles() -> Result<(), AllocError> {
+        let secret = allocate_noaccess(64, |buf| {
+            assert!(buf.iter().all(|&b| b == 0));
+            for (i, b) in buf.iter_mut().enumerate() {
+                *b = i as u8;
+            }
+        })?;
+        assert_eq!(secret.len(), 64);
+
+        for _ in 0..3 {
+            let ok = secret.unlock_for_read(|s| s.iter().enumerate().all(|(i, &b)| b == i as u8));
+            assert!(ok);
+        }
+
+        // Nested reads keep the memory readable until the outer read finishes
+        let sum = secret.unlock_for_read(|outer| {
+            let first = secret.unlock_for_read(|inner| inner[0]);
+            first as usize + outer.iter().map(|&b| b as usize).sum::<usize>()
+        });
+        assert_eq!(sum, (0..64).sum::<usize>());
+
+        Ok(())
+    }
+
+    #[test]
+    fn relocked_after_panic() -> Result<(), AllocError> {
+        let secret = allocate_noaccess(8, |buf| buf.fill(0xaa))?;
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            secret.unlock_for_read(|_| panic!("reader panicked"))
+        }));
+        assert!(result.is_err());
+        assert_eq!(secret.readers.get(), 0);
+        assert_eq!(secret.unlock_for_read(|s| s[7]), 0xaa);
+
+        Ok(())
+    }
+
+    #[cfg(all(target_os = "linux", not(any(miri, feature = "test-backend"))))]
+    #[test]
+    fn inaccessible_outside_reads() -> Result<(), AllocError> {
+        use crate::test_util::fork_test;
+
+        let secret = allocate_noaccess(32, |buf| buf.fill(0x13))?;
+        if !crate::aborts_on_misuse() {
+            return Ok(());
+        }
+        let ptr = secret.ptr.as_ptr();
+
+        // Reading through the handle works, even after several lock cycles
+        assert!(fork_test(|| {
+            (0..3).all(|_| secret.unlock_for_read(|s| s.iter().all(|&b| b == 0x13)))
+        }));
+        // But reading directly, before or after, faults
+        // SAFETY: Not safe, the memory is inaccessible: This terminates the child process.
+        assert!(!fork_test(
+            || unsafe { std::ptr::read_volatile(ptr) } == 0x13
+        ));
+        assert!(!fork_test(|| {
+            secret.unlock_for_read(|_| ());
+            unsafe { std::ptr::read_volatile(ptr) == 0x13 }
+        }));
+        // Writing faults even while the memory is readable
+        assert!(!fork_test(|| {
+            secret.unlock_for_read(|_| unsafe { std::ptr::write_volatile(ptr, 0) });
+            true
+        }));
+
+        Ok(())
+    }
+}
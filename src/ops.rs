@@ -0,0 +1,247 @@
+//! Operations on secrets stored in memory.
+
+/// Swap the contents of two equal-length slices in place, without using a temporary buffer.
+///
+/// Swapping two secrets with a temporary buffer would leave a third copy of one of the secrets in
+/// memory, which might not be guarded or zeroed. This function instead swaps the slices element by
+/// element, so no copy of either secret is made outside of the two slices (other than transiently
+/// in registers). This is useful for key rotation, for example.
+///
+/// # Panics
+/// Panics if the two slices have different lengths.
+pub fn secure_swap(a: &mut [u8], b: &mut [u8]) {
+    assert_eq!(
+        a.len(),
+        b.len(),
+        "secure_swap requires slices of equal length"
+    );
+
+    for (x, y) in a.iter_mut().zip(b.iter_mut()) {
+        std::mem::swap(x, y);
+    }
+}
+
+/// Refresh the secret stored in `dst` by overwriting it with `src`, reusing the existing buffer.
+///
+/// This is intended for secrets which are periodically replaced with a new value of the same
+/// size (such as a rotating session key), where allocating a new guarded buffer for every refresh
+/// would be wasteful. The copy uses volatile writes, so the previous secret is always overwritten,
+/// even if `dst` is never read again.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::{clone_into_secure, SecureArray};
+///
+/// let mut session_key = SecureArray::<32>::new()?;
+/// for epoch in 0..3u8 {
+///     let fresh = [epoch; 32];
+///     clone_into_secure(&mut session_key[..], &fresh);
+/// }
+/// assert_eq!(*session_key, [2; 32]);
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+///
+/// # Panics
+/// Panics if the two slices have different lengths.
+pub fn clone_into_secure(dst: &mut [u8], src: &[u8]) {
+    assert_eq!(
+        dst.len(),
+        src.len(),
+        "clone_into_secure requires slices of equal length"
+    );
+
+    volatile_copy(dst, src);
+}
+
+/// Compare two slices for equality in constant time, even if they have different lengths.
+///
+/// `sodium_memcmp` (and most other constant-time comparisons) only runs in constant time for
+/// inputs of equal length, and comparing inputs of different lengths would usually return early,
+/// revealing that the lengths differ. This function instead compares every byte up to the length
+/// of the longer slice, treating the shorter slice as if it were padded with zeroes, and only
+/// then takes the lengths into account. The time taken therefore depends only on the length of
+/// the longer input, which makes it suitable for comparing an attacker-controlled input (such as
+/// a password) against a stored secret.
+///
+/// Returns `true` if the slices have the same length and contents.
+pub fn constant_time_eq_padded(a: &[u8], b: &[u8]) -> bool {
+    let len = a.len().max(b.len());
+
+    let mut diff = 0u8;
+    for i in 0..len {
+        let x = a.get(i).copied().unwrap_or(0);
+        let y = b.get(i).copied().unwrap_or(0);
+        diff |= x ^ y;
+    }
+    // Fold the difference in lengths into a single byte without branching
+    let len_diff = a.len() ^ b.len();
+    for byte in len_diff.to_ne_bytes() {
+        diff |= byte;
+    }
+
+    // Prevent the compiler from turning the loops above into an early-exit comparison
+    std::hint::black_box(diff) == 0
+}
+
+/// Select `table[index]` in constant time, without branching on `index` or using it to compute a
+/// memory address.
+///
+/// Indexing a table with a secret value directly can leak the value through cache timing, as
+/// which cache lines are loaded depends on the index. This function instead reads *every* entry
+/// in the table, combining them with a mask which is all ones for the selected entry and all
+/// zeroes otherwise, so the memory accessed and the time taken depend only on the size of the
+/// table. The table itself can be stored in guarded memory, for example in a
+/// `Vec<[u8; N], SodiumAllocator>`.
+///
+/// ```
+/// use sodium_alloc::constant_time_select;
+///
+/// let table = [[1, 2], [3, 4], [5, 6]];
+/// assert_eq!(constant_time_select(&table, 1), [3, 4]);
+/// ```
+///
+/// # Panics
+/// Panics if `index` is out of bounds. Only the comparison of `index` against the (public) length
+/// of the table depends on the index.
+pub fn constant_time_select<const N: usize>(table: &[[u8; N]], index: usize) -> [u8; N] {
+    assert!(index < table.len(), "index out of bounds");
+
+    let mut out = [0; N];
+    for (i, entry) in table.iter().enumerate() {
+        // `diff | -diff` has its top bit set unless `diff` is zero, i.e: unless `i == index`.
+        let diff = i ^ index;
+        let nonzero = (diff | diff.wrapping_neg()) >> (usize::BITS - 1);
+        // All ones if this is the selected entry, all zeroes otherwise.
+        let mask = std::hint::black_box((nonzero as u8) ^ 1).wrapping_neg();
+
+        for (o, &b) in out.iter_mut().zip(entry) {
+            *o |= b & mask;
+        }
+    }
+
+    out
+}
+
+/// Copy `src` into `dst` using volatile reads and writes.
+///
+/// # Panics
+/// Panics if the two slices have different lengths.
+pub(crate) fn volatile_copy(dst: &mut [u8], src: &[u8]) {
+    assert_eq!(
+        dst.len(),
+        src.len(),
+        "volatile copies require slices of equal length"
+    );
+
+    for (d, s) in dst.iter_mut().zip(src) {
+        // SAFETY: Both pointers come from references, so are valid and aligned.
+        unsafe { std::ptr::write_volatile(d, std::ptr::read_volatile(s)) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SodiumAllocator;
+
+    #[test]
+    fn swap_contents() {
+        let mut old_key = Vec::new_in(SodiumAllocator);
+        old_key.extend(0..64u8);
+        let mut new_key = Vec::new_in(SodiumAllocator);
+        new_key.extend((0..64u8).map(|b| !b));
+
+        secure_swap(&mut old_key, &mut new_key);
+        assert!(old_key.iter().enumerate().all(|(i, &b)| b == !(i as u8)));
+        assert!(new_key.iter().enumerate().all(|(i, &b)| b == i as u8));
+
+        secure_swap(&mut old_key, &mut new_key);
+        assert!(old_key.iter().enumerate().all(|(i, &b)| b == i as u8));
+    }
+
+    #[test]
+    fn swap_equal_bytes() {
+        let mut a = [0xaa, 0x55, 0x00];
+        let mut b = [0xaa, 0x00, 0x55];
+
+        secure_swap(&mut a, &mut b);
+        assert_eq!(a, [0xaa, 0x00, 0x55]);
+        assert_eq!(b, [0xaa, 0x55, 0x00]);
+    }
+
+    #[test]
+    fn repeated_refresh() {
+        let mut key = Vec::new_in(SodiumAllocator);
+        key.resize(64, 0u8);
+        let ptr = key.as_ptr();
+
+        for epoch in 1..=10u8 {
+            let fresh: Vec<u8> = (0..64).map(|i| i ^ epoch).collect();
+            clone_into_secure(&mut key, &fresh);
+            assert_eq!(key[..], fresh[..]);
+        }
+        assert!(key.iter().enumerate().all(|(i, &b)| b == i as u8 ^ 10));
+        // The same buffer was reused throughout
+        assert_eq!(key.as_ptr(), ptr);
+    }
+
+    #[test]
+    #[should_panic(expected = "equal length")]
+    fn refresh_mismatched_lengths() {
+        clone_into_secure(&mut [0; 32], &[0; 16]);
+    }
+
+    #[test]
+    fn constant_time_eq_cases() {
+        assert!(constant_time_eq_padded(b"hunter2", b"hunter2"));
+        assert!(constant_time_eq_padded(b"", b""));
+
+        // Same length, different contents
+        assert!(!constant_time_eq_padded(b"hunter2", b"hunter3"));
+        assert!(!constant_time_eq_padded(b"xunter2", b"hunter2"));
+
+        // Different lengths, including a trailing zero which matches the padding
+        assert!(!constant_time_eq_padded(b"hunter2", b"hunter"));
+        assert!(!constant_time_eq_padded(b"hunter", b"hunter2"));
+        assert!(!constant_time_eq_padded(b"hunter2", b"hunter2\0"));
+        assert!(!constant_time_eq_padded(b"", b"\0"));
+        assert!(!constant_time_eq_padded(&[0; 256], &[0; 512]));
+    }
+
+    #[test]
+    fn constant_time_select_entries() {
+        let mut table = Vec::new_in(SodiumAllocator);
+        table.extend((0..=255u8).map(|i| [i, !i, i.wrapping_mul(3)]));
+
+        for (i, &entry) in table.iter().enumerate() {
+            assert_eq!(constant_time_select(&table, i), entry);
+        }
+        assert_eq!(constant_time_select(&[[0xaa; 0]; 4], 3), []);
+    }
+
+    #[test]
+    #[should_panic]
+    fn constant_time_select_out_of_bounds() {
+        constant_time_select(&[[0u8; 4]; 4], 4);
+    }
+
+    #[test]
+    fn volatile_copy_contents() {
+        let src: Vec<u8> = (0..=255).collect();
+        let mut dst = vec![0; 256];
+        volatile_copy(&mut dst, &src);
+        assert_eq!(dst, src);
+    }
+
+    #[test]
+    #[should_panic]
+    fn volatile_copy_mismatched_lengths() {
+        volatile_copy(&mut [0; 4], &[0; 5]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn swap_mismatched_lengths() {
+        secure_swap(&mut [0; 4], &mut [0; 5]);
+    }
+}
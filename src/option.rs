@@ -0,0 +1,208 @@
+//! Optional secrets, which are wiped in place when cleared.
+
+use crate::wipe::memzero_raw;
+use crate::SodiumAllocator;
+use std::alloc::AllocError;
+use std::mem::{self, MaybeUninit};
+
+/// An optional value of type `T`, stored in memory allocated using [`SodiumAllocator`].
+///
+/// This is suited to secrets which are only sometimes present, such as a session key which is
+/// discarded on logout. Storage for the value is allocated once, when the `SecureOption` is
+/// created, and reused for every value it holds. Whenever the value is removed (by
+/// [`clear`](Self::clear), [`take`](Self::take), replacing it with
+/// [`insert`](Self::insert), or dropping the `SecureOption`), it is dropped in place, and its
+/// storage is securely zeroed immediately, rather than only once the allocation is eventually
+/// freed.
+///
+/// By contrast, calling [`Option::take`] on an `Option<T>` moves the value out, but leaves its
+/// bytes behind in the old location, which is never wiped.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::SecureOption;
+///
+/// let mut session_key = SecureOption::none()?;
+/// session_key.insert([0x42u8; 32]);
+/// assert_eq!(session_key.as_ref().map(|k| k[0]), Some(0x42));
+///
+/// // Logging out wipes the key in place
+/// session_key.clear();
+/// assert!(session_key.is_none());
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+pub struct SecureOption<T> {
+    slot: Box<MaybeUninit<T>, SodiumAllocator>,
+    /// Whether `slot` currently holds an initialised value.
+    present: bool,
+}
+
+impl<T> SecureOption<T> {
+    /// Create an empty `SecureOption`, allocating storage for a value.
+    ///
+    /// Returns [`AllocError`] if the memory could not be allocated.
+    pub fn none() -> Result<Self, AllocError> {
+        let mut slot = Box::<T, _>::try_new_uninit_in(SodiumAllocator)?;
+        // Sodium fills new allocations with garbage bytes, so start from a clean slate.
+        // SAFETY: `slot` is valid for writes of `size_of::<T>()` bytes.
+        unsafe { memzero_raw(slot.as_mut_ptr().cast(), mem::size_of::<T>()) };
+
+        Ok(Self {
+            slot,
+            present: false,
+        })
+    }
+
+    /// Create a `SecureOption` containing `value`.
+    ///
+    /// `value` is moved into guarded memory, so a copy of it may remain where it was stored
+    /// before, as explained on [`SecureBox`](crate::SecureBox#moving-values-into-guarded-memory).
+    ///
+    /// Returns [`AllocError`] if the memory could not be allocated.
+    pub fn some(value: T) -> Result<Self, AllocError> {
+        let mut option = Self::none()?;
+        option.insert(value);
+        Ok(option)
+    }
+
+    /// Returns `true` if the option contains a value.
+    pub fn is_some(&self) -> bool {
+        self.present
+    }
+
+    /// Returns `true` if the option is empty.
+    pub fn is_none(&self) -> bool {
+        !self.present
+    }
+
+    /// Get a reference to the contained value, if any.
+    pub fn as_ref(&self) -> Option<&T> {
+        // SAFETY: The slot is initialised while `present` is set.
+        self.present.then(|| unsafe { self.slot.assume_init_ref() })
+    }
+
+    /// Get a mutable reference to the contained value, if any.
+    pub fn as_mut(&mut self) -> Option<&mut T> {
+        if !self.present {
+            return None;
+        }
+        // SAFETY: The slot is initialised while `present` is set.
+        Some(unsafe { self.slot.assume_init_mut() })
+    }
+
+    /// Store `value` in the option, returning a mutable reference to it.
+    ///
+    /// If the option already contained a value, it is dropped and wiped first.
+    pub fn insert(&mut self, value: T) -> &mut T {
+        self.clear();
+        let value = self.slot.write(value);
+        self.present = true;
+        value
+    }
+
+    /// Remove the contained value, if any, dropping it and securely zeroing its storage in place.
+    pub fn clear(&mut self) {
+        if mem::replace(&mut self.present, false) {
+            // SAFETY: The slot was initialised, and `present` has been cleared, so the value
+            // won't be used (or dropped) again, even if its destructor panics.
+            unsafe { self.slot.assume_init_drop() };
+        }
+        self.wipe();
+    }
+
+    /// Move the contained value out of the option, if any, securely zeroing its storage.
+    ///
+    /// The returned value is no longer in guarded memory, so this should only be used if it is
+    /// needed elsewhere; prefer [`clear`](Self::clear) to discard a secret.
+    pub fn take(&mut self) -> Option<T> {
+        if !mem::replace(&mut self.present, false) {
+            return None;
+        }
+        // SAFETY: The slot was initialised, and `present` has been cleared, so the value is only
+        // read out once.
+        let value = unsafe { self.slot.assume_init_read() };
+        self.wipe();
+        Some(value)
+    }
+
+    /// Securely zero the storage for the value.
+    fn wipe(&mut self) {
+        // SAFETY: The slot is valid for writes of `size_of::<T>()` bytes, and holds no value.
+        unsafe { memzero_raw(self.slot.as_mut_ptr().cast(), mem::size_of::<T>()) };
+    }
+}
+
+impl<T> Drop for SecureOption<T> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    /// Get the bytes of the storage for the option's value.
+    ///
+    /// # Safety
+    /// Every byte of the storage must be initialised.
+    unsafe fn storage<T>(option: &SecureOption<T>) -> &[u8] {
+        std::slice::from_raw_parts(option.slot.as_ptr().cast(), mem::size_of::<T>())
+    }
+
+    #[test]
+    fn set_and_clear() -> Result<(), AllocError> {
+        let mut option = SecureOption::none()?;
+        assert!(option.is_none());
+        assert_eq!(option.as_ref(), None);
+
+        option.insert([0xaau8; 64]);
+        assert!(option.is_some());
+        option.as_mut().unwrap()[0] = 0xbb;
+        assert_eq!(option.as_ref().unwrap()[..2], [0xbb, 0xaa]);
+
+        option.clear();
+        assert!(option.is_none());
+        assert_eq!(option.as_mut(), None);
+        // SAFETY: The storage has just been wiped, so it's initialised.
+        assert_eq!(unsafe { storage(&option) }, &[0; 64]);
+
+        // Clearing an empty option is fine
+        option.clear();
+        assert!(option.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn take_wipes() -> Result<(), AllocError> {
+        let mut option = SecureOption::some(0x1122_3344_5566_7788u64)?;
+        assert_eq!(option.take(), Some(0x1122_3344_5566_7788));
+        assert_eq!(option.take(), None);
+        // SAFETY: The storage has just been wiped, so it's initialised.
+        assert_eq!(unsafe { storage(&option) }, &[0; 8]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn values_dropped_once() -> Result<(), AllocError> {
+        let value = Rc::new(());
+        let mut option = SecureOption::some(Rc::clone(&value))?;
+        assert_eq!(Rc::strong_count(&value), 2);
+
+        // Replacing the value drops the old one
+        option.insert(Rc::clone(&value));
+        assert_eq!(Rc::strong_count(&value), 2);
+
+        option.clear();
+        assert_eq!(Rc::strong_count(&value), 1);
+
+        option.insert(Rc::clone(&value));
+        drop(option);
+        assert_eq!(Rc::strong_count(&value), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+//! An owning handle to a raw guarded allocation.
+
+use crate::SodiumAllocator;
+use std::alloc::{AllocError, Allocator, Layout};
+use std::ptr::NonNull;
+
+/// A raw block of memory allocated using [`SodiumAllocator`], which is freed (and therefore
+/// securely zeroed) when the `OwnedAllocation` is dropped.
+///
+/// Created by [`SodiumAllocator::allocate_owned`]. This is an ownership token for code which builds
+/// its own containers on top of raw allocations, rather than using [`Box`] or [`Vec`]: Since the
+/// memory is only freed when the token is dropped, it can't be used after it has been freed, or
+/// freed twice, or freed with the wrong layout.
+///
+/// The memory is zeroed when allocated, and can be accessed through
+/// [`as_slice`](Self::as_slice) and [`as_mut_slice`](Self::as_mut_slice), or through
+/// [`as_ptr`](Self::as_ptr) while the token is alive.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::SodiumAllocator;
+/// use std::alloc::Layout;
+///
+/// let mut block = SodiumAllocator.allocate_owned(Layout::from_size_align(64, 8).unwrap())?;
+/// block.as_mut_slice()[..4].copy_from_slice(b"key!");
+/// assert_eq!(&block.as_slice()[..4], b"key!");
+/// // The memory is freed here
+/// drop(block);
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+pub struct OwnedAllocation {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+
+// SAFETY: The allocation is owned exclusively by the token, like the memory of a `Box<[u8]>`.
+unsafe impl Send for OwnedAllocation {}
+unsafe impl Sync for OwnedAllocation {}
+
+impl SodiumAllocator {
+    /// Allocate a zeroed block of memory for the given [`Layout`], returning a token which frees
+    /// it when dropped.
+    ///
+    /// See [`OwnedAllocation`] for details.
+    ///
+    /// Returns [`AllocError`] if the memory could not be allocated.
+    pub fn allocate_owned(&self, layout: Layout) -> Result<OwnedAllocation, AllocError> {
+        let ptr = self.allocate_zeroed(layout)?;
+
+        Ok(OwnedAllocation {
+            ptr: ptr.cast(),
+            layout,
+        })
+    }
+}
+
+impl OwnedAllocation {
+    /// The layout the memory was allocated for.
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Get a pointer to the start of the allocation.
+    ///
+    /// The pointer is valid for reads and writes of `layout().size()` bytes, and aligned to
+    /// `layout().align()`, until the token is dropped.
+    pub fn as_ptr(&self) -> NonNull<u8> {
+        self.ptr
+    }
+
+    /// Get the contents of the allocation.
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: The allocation is live, and the memory was zeroed when it was allocated.
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.layout.size()) }
+    }
+
+    /// Get the contents of the allocation, mutably.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: As above, and `&mut self` guarantees exclusive access.
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.layout.size()) }
+    }
+}
+
+impl Drop for OwnedAllocation {
+    fn drop(&mut self) {
+        // SAFETY: The memory was allocated by `SodiumAllocator` for `layout`, and is only freed
+        // here.
+        unsafe { SodiumAllocator.deallocate(self.ptr, self.layout) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn accessors() -> Result<(), Box<dyn Error>> {
+        for (size, align) in [(0, 1), (1, 1), (32, 8), (4096, 4096), (13, 2 * 4096)] {
+            let layout = Layout::from_size_align(size, align)?;
+            let mut block = SodiumAllocator.allocate_owned(layout)?;
+            assert_eq!(block.layout(), layout);
+            assert_eq!(block.as_ptr().as_ptr() as usize % align, 0);
+            assert_eq!(block.as_slice().len(), size);
+            assert!(block.as_slice().iter().all(|&b| b == 0));
+
+            block.as_mut_slice().fill(0x5a);
+            assert!(block.as_slice().iter().all(|&b| b == 0x5a));
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "track-allocations")]
+    #[test]
+    fn freed_on_drop() -> Result<(), Box<dyn Error>> {
+        use crate::tracking::is_live;
+
+        let mut block = SodiumAllocator.allocate_owned(Layout::from_size_align(32, 1)?)?;
+        block.as_mut_slice().fill(0xff);
+        let ptr = block.as_ptr().as_ptr();
+        assert!(is_live(ptr));
+
+        // Sodium zeroes the memory as it frees it.
+        drop(block);
+        assert!(!is_live(ptr));
+
+        Ok(())
+    }
+}
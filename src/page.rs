@@ -0,0 +1,220 @@
+//! Querying the system page size, and the memory overhead of guarded allocations.
+
+use crate::SodiumAllocator;
+use std::alloc::{AllocError, Allocator, Layout};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The size of the canary Sodium places immediately before each allocation.
+pub(crate) const CANARY_SIZE: usize = 16;
+
+/// The number of pages Sodium reserves for each allocation in addition to those holding the data:
+/// A read-only page storing the size of the allocation, and a guard page either side of the data.
+pub(crate) const OVERHEAD_PAGES: usize = 3;
+
+/// Cached page size, or 0 if it hasn't been queried yet.
+static PAGE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Get the size of a page of memory on this system, in bytes.
+///
+/// Sodium's guarded allocations are built from whole pages: Each allocation is rounded up to a
+/// multiple of the page size, and surrounded by guard pages. The page size is queried from the
+/// operating system the first time this function is called, and cached for subsequent calls.
+pub fn page_size() -> usize {
+    match PAGE_SIZE.load(Ordering::Relaxed) {
+        0 => {
+            // It's fine if multiple threads race to query the page size here, they'll all store
+            // the same value.
+            let size = query_page_size();
+            PAGE_SIZE.store(size, Ordering::Relaxed);
+            size
+        }
+        size => size,
+    }
+}
+
+/// Get the offset of `ptr` from the start of the page containing it, in bytes.
+///
+/// Sodium places each allocation so that it *ends* at the end of a page, immediately followed by
+/// a guard page, so pointers returned by [`SodiumAllocator`](crate::SodiumAllocator) are
+/// generally not page-aligned: A small allocation of `n` bytes will start `n` bytes before the
+/// end of its page, at an offset of `page_size() - n`. Only allocations whose size is a multiple
+/// of the page size (less the 16 byte canary, which precedes the allocation) start close to a
+/// page boundary.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::{page_offset, page_size, SodiumAllocator};
+///
+/// let key = Box::new_in([0u8; 32], SodiumAllocator);
+/// assert_eq!(page_offset(key.as_ptr()), page_size() - 32);
+/// ```
+pub fn page_offset(ptr: *const u8) -> usize {
+    ptr as usize & (page_size() - 1)
+}
+
+/// Round `size` up to a multiple of the page size.
+pub(crate) fn round_to_page(size: usize) -> usize {
+    let mask = page_size() - 1;
+    (size + mask) & !mask
+}
+
+/// Calculate the total number of bytes of memory Sodium reserves for an allocation of `size`
+/// bytes.
+///
+/// This includes the pages holding the data and canary, as well as the guard pages and the page
+/// storing the allocation's size.
+pub(crate) fn allocation_footprint(size: usize) -> usize {
+    round_to_page(size + CANARY_SIZE) + OVERHEAD_PAGES * page_size()
+}
+
+/// The memory overhead of guarded allocations made by the linked libsodium, as measured by
+/// [`overhead_report`].
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct OverheadReport {
+    /// The size of a page of memory on this system, in bytes.
+    pub page_size: usize,
+
+    /// The number of inaccessible guard pages surrounding each allocation.
+    ///
+    /// This is zero if the linked libsodium doesn't support page protection (see
+    /// [`aborts_on_misuse`](crate::aborts_on_misuse)).
+    pub guard_pages: usize,
+
+    /// The size of the canary placed before each allocation, in bytes.
+    ///
+    /// This is zero if the linked libsodium doesn't support page protection.
+    pub canary_bytes: usize,
+
+    /// The total memory reserved for the smallest possible allocation (a single byte), in bytes.
+    ///
+    /// With page protection, this includes the page holding the data and canary, the guard pages,
+    /// and the page Sodium uses to store the size of the allocation. Without it, Sodium falls back
+    /// to `malloc`, so this is just the requested byte (plus any bookkeeping `malloc` does).
+    pub min_allocation_footprint: usize,
+}
+
+/// Measure the memory overhead of guarded allocations on this system.
+///
+/// The overhead of each allocation depends on the page size, and on whether the linked libsodium
+/// supports page protection. This function makes a 1-byte allocation, and measures how many pages
+/// it occupies, to report the overhead in the current environment. This is useful for capacity
+/// planning, for example when choosing an `RLIMIT_MEMLOCK` limit.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::overhead_report;
+///
+/// let report = overhead_report()?;
+/// println!("each small secret costs {} bytes", report.min_allocation_footprint);
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+///
+/// Returns [`AllocError`] if the allocation failed.
+pub fn overhead_report() -> Result<OverheadReport, AllocError> {
+    let page_size = page_size();
+    if !crate::aborts_on_misuse() {
+        return Ok(OverheadReport {
+            page_size,
+            guard_pages: 0,
+            canary_bytes: 0,
+            min_allocation_footprint: 1,
+        });
+    }
+
+    let layout = Layout::new::<u8>();
+    let ptr = SodiumAllocator.allocate(layout)?.cast::<u8>();
+    // The data and canary end at the end of a page, and start somewhere within the pages before.
+    let start = (ptr.as_ptr() as usize - CANARY_SIZE) & !(page_size - 1);
+    let end = ptr.as_ptr() as usize + layout.size();
+    let data_pages = (end - start).div_ceil(page_size);
+    // SAFETY: We just allocated `ptr` with this layout.
+    unsafe { SodiumAllocator.deallocate(ptr, layout) };
+
+    Ok(OverheadReport {
+        page_size,
+        guard_pages: 2,
+        canary_bytes: CANARY_SIZE,
+        min_allocation_footprint: (data_pages + OVERHEAD_PAGES) * page_size,
+    })
+}
+
+#[cfg(unix)]
+fn query_page_size() -> usize {
+    // SAFETY: `sysconf` has no safety requirements, and `_SC_PAGESIZE` is always supported.
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    assert!(size > 0, "failed to query page size");
+    size as usize
+}
+
+#[cfg(windows)]
+fn query_page_size() -> usize {
+    use std::mem::MaybeUninit;
+    use windows_sys::Win32::System::SystemInformation::{GetSystemInfo, SYSTEM_INFO};
+
+    let mut info = MaybeUninit::<SYSTEM_INFO>::uninit();
+    // SAFETY: `GetSystemInfo` always initialises the structure it is passed.
+    let info = unsafe {
+        GetSystemInfo(info.as_mut_ptr());
+        info.assume_init()
+    };
+    info.dwPageSize as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_size_plausible() {
+        let size = page_size();
+        assert!(size.is_power_of_two());
+        assert!(size >= 1024);
+
+        // Subsequent calls should return the cached value
+        assert_eq!(page_size(), size);
+    }
+
+    #[test]
+    fn small_allocation_near_page_end() -> Result<(), AllocError> {
+        for size in [1, 32, 100] {
+            let layout = Layout::from_size_align(size, 1).unwrap();
+            let ptr = SodiumAllocator.allocate(layout)?;
+            let offset = page_offset(ptr.cast::<u8>().as_ptr());
+            assert_eq!(offset, page_size() - size);
+
+            unsafe { SodiumAllocator.deallocate(ptr.cast(), layout) };
+        }
+
+        assert_eq!(page_offset(std::ptr::null()), 0);
+        assert_eq!(page_offset(page_size() as *const u8), 0);
+        assert_eq!(page_offset((page_size() + 5) as *const u8), 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn footprint() {
+        let page = page_size();
+        assert_eq!(allocation_footprint(0), 4 * page);
+        assert_eq!(allocation_footprint(1), 4 * page);
+        assert_eq!(allocation_footprint(page - CANARY_SIZE), 4 * page);
+        assert_eq!(allocation_footprint(page - CANARY_SIZE + 1), 5 * page);
+    }
+
+    #[test]
+    fn overhead_measured() -> Result<(), AllocError> {
+        let report = overhead_report()?;
+        assert_eq!(report.page_size, page_size());
+
+        if crate::aborts_on_misuse() {
+            let footprint = report.min_allocation_footprint;
+            assert_eq!(footprint % page_size(), 0);
+            assert!(footprint > page_size());
+            assert_eq!(footprint, allocation_footprint(1));
+            assert_eq!(report.guard_pages, 2);
+            assert_eq!(report.canary_bytes, CANARY_SIZE);
+        }
+
+        Ok(())
+    }
+}
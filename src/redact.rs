@@ -0,0 +1,222 @@
+//! Redacted [`Debug`](fmt::Debug) output for secrets.
+//!
+//! Every type defined by this crate which stores a secret (including type-erased and generic ones,
+//! such as [`SecureAny`](crate::SecureAny) and [`SecureCell`](crate::SecureCell)) implements
+//! `Debug` by printing `[REDACTED]`, rather than its contents. This means `#[derive(Debug)]` can
+//! be used on a struct with fields of these types: The secret fields are redacted, and all other
+//! fields are printed normally. Other secrets (such as a [`SecureBox`](crate::SecureBox), whose
+//! `Debug` implementation comes from the standard library) can be wrapped in [`Redacted`].
+
+use crate::{
+    NoAccessSecret, OwnedAllocation, SecureArray, SecureCString, SecureString, SecureVec,
+    SecureView,
+};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+/// The text printed in place of a secret.
+const REDACTED: &str = "[REDACTED]";
+
+/// A wrapper which prints `[REDACTED]` in place of the wrapped value's [`Debug`](fmt::Debug)
+/// output.
+///
+/// This is useful for fields of a struct deriving `Debug` which hold secrets of types with their
+/// own (revealing) `Debug` implementations. The wrapper is transparent, and dereferences to the
+/// wrapped value.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::{Redacted, SecureArray, SecureBox, SodiumAllocator};
+///
+/// #[derive(Debug)]
+/// struct Credentials {
+///     user: &'static str,
+///     key: SecureArray<32>,
+///     pin: Redacted<SecureBox<u32>>,
+/// }
+///
+/// let creds = Credentials {
+///     user: "alice",
+///     key: SecureArray::new()?,
+///     pin: Redacted(Box::new_in(1234, SodiumAllocator)),
+/// };
+/// assert_eq!(
+///     format!("{:?}", creds),
+///     r#"Credentials { user: "alice", key: [REDACTED], pin: [REDACTED] }"#,
+/// );
+/// assert_eq!(**creds.pin, 1234);
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+#[derive(Copy, Clone, Default, Eq, Hash, PartialEq)]
+#[repr(transparent)]
+pub struct Redacted<T>(pub T);
+
+impl<T> Redacted<T> {
+    /// Unwrap the wrapped value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Redacted<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Redacted<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<T> From<T> for Redacted<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Implement `Debug` for secret types by printing [`REDACTED`].
+macro_rules! redacted_debug {
+    ($([$($generics:tt)*] $ty:ty),* $(,)?) => {
+        $(
+            impl<$($generics)*> fmt::Debug for $ty {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    f.write_str(REDACTED)
+                }
+            }
+        )*
+    };
+}
+
+redacted_debug! {
+    [const N: usize] SecureArray<N>,
+    [] SecureVec,
+    [] SecureString,
+    [] SecureCString,
+    [] SecureView,
+    [T] crate::SecureMatrix<T>,
+    [T] crate::SecureOption<T>,
+    [] crate::SecureSlice,
+    ['a] crate::DrainSecure<'a>,
+    ['a] crate::SecureCow<'a>,
+    [] crate::SecureAny,
+    [T] crate::SecureCell<T>,
+    [] crate::SecureStringBuilder,
+    [] crate::SecureWriter,
+    [] crate::SecureReader,
+    [] crate::SecureCursor,
+    ['a, const SLOT: usize, P: crate::PageProvider] crate::Slot<'a, SLOT, P>,
+    [const SLOT: usize, P: crate::PageProvider] crate::SharedSlot<SLOT, P>,
+    [] NoAccessSecret,
+    [] OwnedAllocation,
+}
+
+#[cfg(feature = "generic-array")]
+redacted_debug! {
+    [N: generic_array::ArrayLength] crate::SecureGenericArray<N>,
+}
+
+#[cfg(feature = "rustls")]
+redacted_debug! {
+    [] crate::GuardedEd25519Key,
+    [] crate::tls::Ed25519Signer,
+}
+
+#[cfg(target_os = "linux")]
+redacted_debug! {
+    [] crate::HugePageBuffer,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{allocate_noaccess, SodiumAllocator};
+    use std::alloc::Layout;
+    use std::error::Error;
+
+    #[derive(Debug)]
+    struct Credentials {
+        user: &'static str,
+        key: SecureArray<32>,
+        attempts: u32,
+    }
+
+    #[test]
+    fn derive_redacts_secret_fields() -> Result<(), Box<dyn Error>> {
+        let mut key = SecureArray::new()?;
+        key.fill(0x41);
+        let creds = Credentials {
+            user: "alice",
+            key,
+            attempts: 3,
+        };
+
+        let debug = format!("{:?}", creds);
+        assert_eq!(
+            debug,
+            r#"Credentials { user: "alice", key: [REDACTED], attempts: 3 }"#
+        );
+        assert!(!debug.contains("65"));
+        assert!(format!("{:#?}", creds).contains("key: [REDACTED]"));
+        // The fields themselves are untouched
+        assert_eq!(
+            (creds.user, creds.key[0], creds.attempts),
+            ("alice", 0x41, 3)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn secret_types_redacted() -> Result<(), Box<dyn Error>> {
+        let vec = SecureVec::from(b"hunter2".to_vec());
+        let string = SecureString::from_string_wiping(String::from("hunter2"))?;
+        let c_string = SecureCString::new(b"hunter2")?;
+        let noaccess = allocate_noaccess(7, |buf| buf.copy_from_slice(b"hunter2"))?;
+        let owned = SodiumAllocator.allocate_owned(Layout::new::<[u8; 7]>())?;
+        let (view, _) = SecureVec::from(b"hunter2".to_vec()).split_at_secure(7);
+        let borrowed = crate::SecureCow::Borrowed(b"hunter2");
+        let mut cow_owned = crate::SecureCow::Borrowed(b"hunter2");
+        cow_owned.to_mut()?;
+        let any = crate::SecureAny::new(*b"hunter2")?;
+        let cell = crate::SecureCell::new(*b"hunter2")?;
+        let slab = crate::SecureSlab::<7>::new();
+        let mut slot = slab.allocate()?;
+        slot.copy_from_slice(b"hunter2");
+
+        let debug = [
+            format!("{:?}", vec),
+            format!("{:?}", string),
+            format!("{:?}", c_string),
+            format!("{:?}", noaccess),
+            format!("{:?}", owned),
+            format!("{:?}", view),
+            format!("{:?}", borrowed),
+            format!("{:?}", cow_owned),
+            format!("{:?}", any),
+            format!("{:?}", cell),
+            format!("{:?}", slot),
+        ];
+        assert!(debug.iter().all(|d| d == "[REDACTED]"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn wrapper_redacts() {
+        let pin = Redacted(Box::new_in(1234u32, SodiumAllocator));
+        assert_eq!(format!("{:?}", pin), "[REDACTED]");
+        assert_eq!(format!("{:#?}", Some(&pin)), "Some(\n    [REDACTED],\n)");
+        assert_eq!(**pin, 1234);
+        assert_eq!(*pin.into_inner(), 1234);
+    }
+}
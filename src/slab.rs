@@ -0,0 +1,763 @@
+//! Slab allocation of many small, fixed-size secrets.
+
+use crate::page::{page_size, CANARY_SIZE};
+use crate::{init, memzero, sys};
+use std::alloc::AllocError;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A source of pages of memory for a [`SecureSlab`].
+///
+/// The slab only decides how pages are divided into slots, and when new pages are needed. The
+/// pages themselves come from a `PageProvider`, which by default is [`SodiumPages`]. Other
+/// providers are mainly useful for testing code built on a `SecureSlab` in isolation, without
+/// depending on the exact behaviour of libsodium.
+///
+/// # Safety
+/// A pointer returned by [`allocate_page`](Self::allocate_page) must be valid for reads and
+/// writes of [`page_size`](Self::page_size) bytes, and must not be used by anything else, until
+/// it is passed to [`release_page`](Self::release_page).
+pub unsafe trait PageProvider {
+    /// The number of usable bytes in each page.
+    ///
+    /// This must always return the same value.
+    fn page_size() -> usize;
+
+    /// Allocate a new page of [`page_size`](Self::page_size) bytes.
+    ///
+    /// The contents of the page don't need to be initialised, the slab zeroes them before use.
+    ///
+    /// Returns [`AllocError`] if the page could not be allocated.
+    fn allocate_page(&self) -> Result<NonNull<u8>, AllocError>;
+
+    /// Release a page which was allocated by [`allocate_page`](Self::allocate_page).
+    ///
+    /// Every slot is securely zeroed when it is freed, and a page is only released once all of
+    /// its slots have been freed, so the page is always zeroed when this is called.
+    ///
+    /// # Safety
+    /// `ptr` must have been returned by `allocate_page` on this provider, and not yet released.
+    unsafe fn release_page(&self, ptr: NonNull<u8>);
+}
+
+/// The default [`PageProvider`], which allocates each page using Sodium.
+///
+/// Each page is a separate Sodium allocation, surrounded by guard pages and preceded by a canary,
+/// and locked into memory.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SodiumPages;
+
+// SAFETY: Each page is a separate allocation of `page_size()` bytes, only freed on release.
+unsafe impl PageProvider for SodiumPages {
+    /// Sodium places a canary before each allocation, so we request a little less than a full
+    /// page to fit the allocation in a single page.
+    fn page_size() -> usize {
+        page_size() - CANARY_SIZE
+    }
+
+    fn allocate_page(&self) -> Result<NonNull<u8>, AllocError> {
+        init()?;
+        // SAFETY: libsodium has been initialised. We check if the returned pointer is NULL.
+        let ptr = unsafe { sys::malloc(Self::page_size()) };
+        NonNull::new(ptr).ok_or(AllocError)
+    }
+
+    unsafe fn release_page(&self, ptr: NonNull<u8>) {
+        sys::free(ptr.as_ptr());
+    }
+}
+
+/// A page of memory from a [`PageProvider`], subdivided into slots.
+struct Page {
+    /// Pointer to the start of the usable memory in the page.
+    ptr: NonNull<u8>,
+    /// Bitmap of free slots: Bit `i % 64` of `free[i / 64]` is set if slot `i` is free.
+    free: Vec<u64>,
+}
+
+impl Page {
+    /// Allocate a new page from `provider`, split into `slots` free slots.
+    fn new<P: PageProvider>(provider: &P, slots: usize) -> Result<Self, AllocError> {
+        let ptr = provider.allocate_page()?;
+        // Sodium fills new allocations with garbage, slots should always start out zeroed.
+        // SAFETY: The provider guarantees the page is valid for writes of `page_size()` bytes.
+        memzero(unsafe { std::slice::from_raw_parts_mut(ptr.as_ptr(), P::page_size()) });
+
+        let mut free = vec![u64::MAX; slots.div_ceil(64)];
+        if !slots.is_multiple_of(64) {
+            // Clear the bits for slots past the end of the page
+            *free.last_mut().unwrap() = (1 << (slots % 64)) - 1;
+        }
+
+        Ok(Self { ptr, free })
+    }
+
+    /// Find a free slot in this page, and mark it as used.
+    fn take_slot(&mut self) -> Option<usize> {
+        let (word_idx, word) = self.free.iter_mut().enumerate().find(|(_, w)| **w != 0)?;
+        let bit = word.trailing_zeros() as usize;
+        *word &= !(1 << bit);
+
+        Some(word_idx * 64 + bit)
+    }
+
+    /// Mark the slot at `index` as free.
+    fn release_slot(&mut self, index: usize) {
+        self.free[index / 64] |= 1 << (index % 64);
+    }
+}
+
+// SAFETY: The pages are only accessed while holding the mutex, or via a `Slot` with exclusive
+// access to part of a page.
+unsafe impl Send for Page {}
+
+/// A slab allocator, which stores many fixed-size secrets of `SLOT` bytes in shared pages of
+/// memory allocated using Sodium.
+///
+/// Every allocation made by [`SodiumAllocator`](crate::SodiumAllocator) consumes at least one
+/// whole page of memory for its data, plus guard pages. For applications storing many small
+/// secrets of the same size, this is very wasteful. A `SecureSlab` instead allocates pages as
+/// needed, and subdivides each page into slots of `SLOT` bytes, which are handed out by
+/// [`SecureSlab::allocate`]. Each page is surrounded by guard pages as usual, but the slots within
+/// a page are not separated from each other by guard pages or canaries.
+///
+/// Slots are securely zeroed when they are freed, and the pages are freed (and therefore zeroed)
+/// once the `SecureSlab` is dropped.
+///
+/// Pages are allocated using Sodium by default. A different [`PageProvider`] can be supplied
+/// using [`SecureSlab::with_provider`]. To share one slab between several owners, wrap it in a
+/// [`SharedSlab`].
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::SecureSlab;
+///
+/// let slab = SecureSlab::<32>::new();
+/// let mut key_a = slab.allocate()?;
+/// let mut key_b = slab.allocate()?;
+/// key_a.fill(0xaa);
+/// key_b.fill(0xbb);
+/// assert_eq!(slab.page_count(), 1);
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+pub struct SecureSlab<const SLOT: usize, P: PageProvider = SodiumPages> {
+    provider: P,
+    pages: Mutex<Vec<Page>>,
+}
+
+impl<const SLOT: usize> SecureSlab<SLOT> {
+    /// Create a new, empty `SecureSlab`.
+    ///
+    /// No memory is allocated until the first call to [`SecureSlab::allocate`].
+    pub const fn new() -> Self {
+        Self::with_provider(SodiumPages)
+    }
+
+    /// Create a new `SecureSlab`, allocating enough pages up front to hold `slots` slots.
+    ///
+    /// Allocating from a slab only needs to allocate a new page when every existing page is full.
+    /// If the number of live secrets is known in advance, pre-reserving space for them at startup
+    /// means the first `slots` calls to [`SecureSlab::allocate`] never need to allocate, giving
+    /// more predictable latency, and any failure to allocate the pages is reported immediately.
+    /// Note that Sodium doesn't treat failure to lock memory as an error, so reaching the
+    /// `RLIMIT_MEMLOCK` limit won't cause this function to fail.
+    ///
+    /// Returns [`AllocError`] if the pages could not be allocated, or if `slots` is non-zero, but
+    /// slots of `SLOT` bytes can't be allocated by this slab (see [`SecureSlab::slots_per_page`]).
+    pub fn with_prereserved(slots: usize) -> Result<Self, AllocError> {
+        let slab = Self::new();
+        slab.reserve(slots)?;
+
+        Ok(slab)
+    }
+}
+
+impl<const SLOT: usize, P: PageProvider> SecureSlab<SLOT, P> {
+    /// Create a new, empty `SecureSlab`, which allocates its pages from `provider`.
+    ///
+    /// No memory is allocated until the first call to [`SecureSlab::allocate`].
+    pub const fn with_provider(provider: P) -> Self {
+        Self {
+            provider,
+            pages: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Allocate enough pages to hold at least `slots` slots in total.
+    ///
+    /// Returns [`AllocError`] if the pages could not be allocated, or if `slots` is non-zero, but
+    /// slots of `SLOT` bytes can't be allocated by this slab (see [`SecureSlab::slots_per_page`]).
+    fn reserve(&self, slots: usize) -> Result<(), AllocError> {
+        if slots == 0 {
+            return Ok(());
+        }
+
+        let per_page = Self::slots_per_page();
+        if per_page == 0 {
+            return Err(AllocError);
+        }
+
+        let mut pages = self.pages();
+        while pages.len() * per_page < slots {
+            pages.push(Page::new(&self.provider, per_page)?);
+        }
+
+        Ok(())
+    }
+
+    /// The number of slots which fit in a single page.
+    ///
+    /// This depends on the page size of the provider, which for [`SodiumPages`] depends on the
+    /// system page size. It is zero if `SLOT` is zero, or too large to fit in a single page.
+    pub fn slots_per_page() -> usize {
+        match SLOT {
+            0 => 0,
+            _ => P::page_size() / SLOT,
+        }
+    }
+
+    /// The number of pages currently allocated by this slab.
+    pub fn page_count(&self) -> usize {
+        self.pages().len()
+    }
+
+    /// Allocate a slot from the slab.
+    ///
+    /// The slot is initially zeroed, and is securely zeroed again when the returned [`Slot`] is
+    /// dropped. If there are no free slots in any existing pages, a new page is allocated.
+    ///
+    /// Returns [`AllocError`] if a new page could not be allocated, or if slots of `SLOT` bytes
+    /// can't be allocated by this slab (see [`SecureSlab::slots_per_page`]).
+    pub fn allocate(&self) -> Result<Slot<'_, SLOT, P>, AllocError> {
+        let (page, index, ptr) = self.claim()?;
+
+        Ok(Slot {
+            slab: self,
+            page,
+            index,
+            ptr,
+        })
+    }
+
+    /// Find a free slot, allocating a new page if necessary, and mark it as used.
+    ///
+    /// Returns the index of the page, the index of the slot within the page, and a pointer to the
+    /// slot.
+    fn claim(&self) -> Result<(usize, usize, NonNull<[u8; SLOT]>), AllocError> {
+        let slots = Self::slots_per_page();
+        if slots == 0 {
+            return Err(AllocError);
+        }
+
+        let mut pages = self.pages();
+        let found = pages
+            .iter_mut()
+            .enumerate()
+            .find_map(|(p, page)| Some((p, page.take_slot()?)));
+        let (page, index) = match found {
+            Some(found) => found,
+            None => {
+                let mut new_page = Page::new(&self.provider, slots)?;
+                // A new page always has a free slot
+                let index = new_page.take_slot().ok_or(AllocError)?;
+                pages.push(new_page);
+                (pages.len() - 1, index)
+            }
+        };
+
+        // SAFETY: `index < slots`, so the slot is in bounds of the page's allocation.
+        let ptr = unsafe { pages[page].ptr.as_ptr().add(index * SLOT) };
+        // SAFETY: The page pointer is non-null, so an offset from it is too.
+        let ptr = unsafe { NonNull::new_unchecked(ptr as *mut [u8; SLOT]) };
+
+        Ok((page, index, ptr))
+    }
+
+    /// Securely zero the slot at `ptr`, and return it to the slab.
+    ///
+    /// # Safety
+    /// The slot must have been returned by [`claim`](Self::claim) with these indices, and must
+    /// not be used again.
+    unsafe fn release(&self, page: usize, index: usize, ptr: NonNull<[u8; SLOT]>) {
+        memzero(&mut *ptr.as_ptr());
+        self.pages()[page].release_slot(index);
+    }
+
+    /// Lock the list of pages.
+    ///
+    /// The list is always left in a consistent state, so it's fine to continue using it if
+    /// another thread panicked while holding the lock.
+    fn pages(&self) -> MutexGuard<'_, Vec<Page>> {
+        self.pages.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<const SLOT: usize> Default for SecureSlab<SLOT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SLOT: usize, P: PageProvider> Drop for SecureSlab<SLOT, P> {
+    fn drop(&mut self) {
+        let pages = self.pages.get_mut().unwrap_or_else(|e| e.into_inner());
+        for page in pages.drain(..) {
+            // SAFETY: Every page was allocated by `self.provider`, and is only released here.
+            // Every `Slot` borrows the slab, so none of the pages are still in use.
+            unsafe { self.provider.release_page(page.ptr) };
+        }
+    }
+}
+
+/// A shared handle to a [`SecureSlab`].
+///
+/// Cloning a `SharedSlab` doesn't duplicate the slab: Every clone allocates slots from the same
+/// pages, and the pages are freed (and therefore zeroed) exactly once, when the last clone of the
+/// handle and every slot allocated from it have been dropped.
+///
+/// [`SharedSlab::allocate`] returns a [`SharedSlot`], which holds its own handle to the slab
+/// rather than borrowing it, so slots can be stored in structs independently of any handle. The
+/// handle also dereferences to the `SecureSlab`, so [`SecureSlab::allocate`] can still be used to
+/// allocate a [`Slot`] which borrows the handle.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::{SharedSlab, SharedSlot};
+///
+/// struct SessionKeys {
+///     enc_key: SharedSlot<32>,
+///     mac_key: SharedSlot<32>,
+/// }
+///
+/// let slab = SharedSlab::<32>::new();
+/// let mut keys = SessionKeys {
+///     enc_key: slab.allocate()?,
+///     mac_key: slab.clone().allocate()?,
+/// };
+/// keys.enc_key.fill(0x13);
+/// // Both keys are stored in the same page, which outlives the handle
+/// assert_eq!(slab.page_count(), 1);
+/// drop(slab);
+/// assert_eq!(*keys.mac_key, [0; 32]);
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+pub struct SharedSlab<const SLOT: usize, P: PageProvider = SodiumPages>(Arc<SecureSlab<SLOT, P>>);
+
+impl<const SLOT: usize> SharedSlab<SLOT> {
+    /// Create a new, empty slab, shared through the returned handle.
+    ///
+    /// No memory is allocated until the first slot is allocated.
+    pub fn new() -> Self {
+        Self::from(SecureSlab::new())
+    }
+}
+
+impl<const SLOT: usize, P: PageProvider> SharedSlab<SLOT, P> {
+    /// Allocate a slot from the slab, which holds its own handle to the slab.
+    ///
+    /// This behaves like [`SecureSlab::allocate`], but the returned [`SharedSlot`] doesn't borrow
+    /// this handle, so it can be stored alongside (or instead of) a handle to the slab. The slab's
+    /// pages are freed once every handle and every `SharedSlot` has been dropped. Use
+    /// [`SecureSlab::allocate`] (via `Deref`) to allocate a [`Slot`] which borrows the handle.
+    ///
+    /// Returns [`AllocError`] if a new page could not be allocated, or if slots of `SLOT` bytes
+    /// can't be allocated by this slab (see [`SecureSlab::slots_per_page`]).
+    pub fn allocate(&self) -> Result<SharedSlot<SLOT, P>, AllocError> {
+        let (page, index, ptr) = self.0.claim()?;
+
+        Ok(SharedSlot {
+            slab: self.clone(),
+            page,
+            index,
+            ptr,
+        })
+    }
+
+    /// Returns whether `self` and `other` are handles to the same slab.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<const SLOT: usize> Default for SharedSlab<SLOT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SLOT: usize, P: PageProvider> From<SecureSlab<SLOT, P>> for SharedSlab<SLOT, P> {
+    fn from(slab: SecureSlab<SLOT, P>) -> Self {
+        Self(Arc::new(slab))
+    }
+}
+
+impl<const SLOT: usize, P: PageProvider> Clone for SharedSlab<SLOT, P> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<const SLOT: usize, P: PageProvider> Deref for SharedSlab<SLOT, P> {
+    type Target = SecureSlab<SLOT, P>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// A slot of `SLOT` bytes allocated from a [`SecureSlab`].
+///
+/// The slot dereferences to `[u8; SLOT]`. When it is dropped, its contents are securely zeroed,
+/// and it is returned to the slab for reuse.
+pub struct Slot<'a, const SLOT: usize, P: PageProvider = SodiumPages> {
+    slab: &'a SecureSlab<SLOT, P>,
+    page: usize,
+    index: usize,
+    ptr: NonNull<[u8; SLOT]>,
+}
+
+impl<const SLOT: usize, P: PageProvider> Deref for Slot<'_, SLOT, P> {
+    type Target = [u8; SLOT];
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: The slot is valid for as long as the slab is alive, and this `Slot` has
+        // exclusive access to it.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<const SLOT: usize, P: PageProvider> DerefMut for Slot<'_, SLOT, P> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: The slot is valid for as long as the slab is alive, and this `Slot` has
+        // exclusive access to it.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<const SLOT: usize, P: PageProvider> Drop for Slot<'_, SLOT, P> {
+    fn drop(&mut self) {
+        // SAFETY: The slot was claimed from `slab` when this `Slot` was created, and is only
+        // released here.
+        unsafe { self.slab.release(self.page, self.index, self.ptr) };
+    }
+}
+
+/// A slot of `SLOT` bytes allocated from a [`SharedSlab`].
+///
+/// Unlike a [`Slot`], this doesn't borrow the slab: It holds its own handle to the slab, which
+/// keeps the slab's pages alive for as long as the slot exists. The slot dereferences to
+/// `[u8; SLOT]`. When it is dropped, its contents are securely zeroed, and it is returned to the
+/// slab for reuse.
+pub struct SharedSlot<const SLOT: usize, P: PageProvider = SodiumPages> {
+    slab: SharedSlab<SLOT, P>,
+    page: usize,
+    index: usize,
+    ptr: NonNull<[u8; SLOT]>,
+}
+
+impl<const SLOT: usize, P: PageProvider> Deref for SharedSlot<SLOT, P> {
+    type Target = [u8; SLOT];
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: The slot is valid for as long as the slab is alive, which `self.slab` ensures,
+        // and this `SharedSlot` has exclusive access to it.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<const SLOT: usize, P: PageProvider> DerefMut for SharedSlot<SLOT, P> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: As above.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<const SLOT: usize, P: PageProvider> Drop for SharedSlot<SLOT, P> {
+    fn drop(&mut self) {
+        // SAFETY: The slot was claimed from `slab` when this `SharedSlot` was created, and is
+        // only released here.
+        unsafe { self.slab.release(self.page, self.index, self.ptr) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{self, Layout};
+
+    /// Pages handed out and released by a [`MockPages`].
+    #[derive(Default)]
+    struct MockState {
+        /// The pages which have been allocated, and not yet released.
+        live: Vec<usize>,
+        /// The total number of pages which have been allocated.
+        allocated: usize,
+        /// The number of pages which were zeroed when they were released.
+        released_zeroed: usize,
+        /// The number of further pages which may be allocated before allocation fails.
+        remaining: usize,
+    }
+
+    /// A [`PageProvider`] using plain page-aligned allocations from the global allocator, which
+    /// records how the slab uses its pages.
+    ///
+    /// Memory is only obtained from the global allocator, so this also works under Miri.
+    #[derive(Clone, Default)]
+    struct MockPages(Arc<Mutex<MockState>>);
+
+    impl MockPages {
+        const PAGE_SIZE: usize = 256;
+
+        fn with_limit(remaining: usize) -> Self {
+            let pages = Self::default();
+            pages.state().remaining = remaining;
+            pages
+        }
+
+        fn state(&self) -> MutexGuard<'_, MockState> {
+            self.0.lock().unwrap_or_else(|e| e.into_inner())
+        }
+
+        fn layout() -> Layout {
+            Layout::from_size_align(Self::PAGE_SIZE, Self::PAGE_SIZE).unwrap()
+        }
+    }
+
+    unsafe impl PageProvider for MockPages {
+        fn page_size() -> usize {
+            Self::PAGE_SIZE
+        }
+
+        fn allocate_page(&self) -> Result<NonNull<u8>, AllocError> {
+            let mut state = self.state();
+            state.remaining = state.remaining.checked_sub(1).ok_or(AllocError)?;
+            let ptr = NonNull::new(unsafe { alloc::alloc(Self::layout()) }).ok_or(AllocError)?;
+            // Like Sodium, hand out garbage-filled pages.
+            unsafe { ptr.as_ptr().write_bytes(0xdb, Self::PAGE_SIZE) };
+
+            state.live.push(ptr.as_ptr() as usize);
+            state.allocated += 1;
+            Ok(ptr)
+        }
+
+        unsafe fn release_page(&self, ptr: NonNull<u8>) {
+            let mut state = self.state();
+            let idx = state
+                .live
+                .iter()
+                .position(|&p| p == ptr.as_ptr() as usize)
+                .expect("released a page which isn't live");
+            state.live.swap_remove(idx);
+
+            let page = std::slice::from_raw_parts(ptr.as_ptr(), Self::PAGE_SIZE);
+            if page.iter().all(|&b| b == 0) {
+                state.released_zeroed += 1;
+            }
+            alloc::dealloc(ptr.as_ptr(), Self::layout());
+        }
+    }
+
+    #[test]
+    fn shared_slab_clones_share_pages() -> Result<(), AllocError> {
+        let pages = MockPages::with_limit(usize::MAX);
+        let a = SharedSlab::from(SecureSlab::<64, _>::with_provider(pages.clone()));
+        let b = a.clone();
+        assert!(a.ptr_eq(&b));
+
+        // Both clones allocate from the same page
+        let mut slot_a = a.allocate()?;
+        let mut slot_b = b.allocate()?;
+        slot_a.fill(0xaa);
+        slot_b.fill(0xbb);
+        assert_eq!(pages.state().allocated, 1);
+        assert_eq!(a.page_count(), 1);
+        assert_eq!(b.page_count(), 1);
+        let page = pages.state().live[0];
+        for slot in [slot_a.as_ptr() as usize, slot_b.as_ptr() as usize] {
+            assert!((page..page + MockPages::PAGE_SIZE).contains(&slot));
+        }
+
+        // The page is only released once the last clone is dropped
+        drop(slot_a);
+        drop(a);
+        assert_eq!(pages.state().live.len(), 1);
+        drop(slot_b);
+        drop(b);
+        let state = pages.state();
+        assert!(state.live.is_empty());
+        assert_eq!(state.allocated, 1);
+        assert_eq!(state.released_zeroed, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn struct_owns_shared_slots() -> Result<(), AllocError> {
+        struct Keys {
+            enc: SharedSlot<64, MockPages>,
+            mac: SharedSlot<64, MockPages>,
+        }
+
+        let pages = MockPages::with_limit(usize::MAX);
+        let slab = SharedSlab::from(SecureSlab::<64, _>::with_provider(pages.clone()));
+        let mut keys = Keys {
+            enc: slab.allocate()?,
+            mac: slab.clone().allocate()?,
+        };
+        keys.enc.fill(0xaa);
+        keys.mac.fill(0xbb);
+        assert_eq!(pages.state().allocated, 1);
+
+        // The slots keep the page alive after every handle is dropped
+        drop(slab);
+        assert_eq!(pages.state().live.len(), 1);
+        assert_eq!(*keys.enc, [0xaa; 64]);
+        assert_eq!(*keys.mac, [0xbb; 64]);
+
+        // The page is released, wiped, once the last slot is dropped
+        drop(keys);
+        let state = pages.state();
+        assert!(state.live.is_empty());
+        assert_eq!(state.allocated, 1);
+        assert_eq!(state.released_zeroed, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn mock_page_management() -> Result<(), AllocError> {
+        let pages = MockPages::with_limit(usize::MAX);
+        let slab = SecureSlab::<64, _>::with_provider(pages.clone());
+        assert_eq!(SecureSlab::<64, MockPages>::slots_per_page(), 4);
+        assert_eq!(pages.state().allocated, 0);
+
+        let mut slots = (0..5)
+            .map(|_| slab.allocate())
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(pages.state().allocated, 2);
+        assert_eq!(slab.page_count(), 2);
+
+        // Every slot lies within a live page, and new pages start out zeroed
+        let live = pages.state().live.clone();
+        for slot in &mut slots {
+            assert_eq!(**slot, [0; 64]);
+            let addr = slot.as_ptr() as usize;
+            assert!(live
+                .iter()
+                .any(|&p| (p..p + MockPages::PAGE_SIZE).contains(&addr)));
+            slot.fill(0xff);
+        }
+
+        // Freeing a slot makes it available again without allocating another page, and pages
+        // aren't released while the slab is alive
+        slots.truncate(3);
+        for _ in 0..5 {
+            slots.push(slab.allocate()?);
+        }
+        assert_eq!(pages.state().allocated, 2);
+        slots.push(slab.allocate()?);
+        assert_eq!(pages.state().allocated, 3);
+        assert_eq!(pages.state().live.len(), 3);
+
+        // Every page is released, wiped, when the slab is dropped
+        drop(slots);
+        drop(slab);
+        let state = pages.state();
+        assert!(state.live.is_empty());
+        assert_eq!(state.released_zeroed, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn mock_allocation_failure() -> Result<(), AllocError> {
+        let pages = MockPages::with_limit(1);
+        let slab = SecureSlab::<128, _>::with_provider(pages.clone());
+
+        let a = slab.allocate()?;
+        let b = slab.allocate()?;
+        // The only page is full, and the provider refuses to allocate another
+        assert!(slab.allocate().is_err());
+        assert_eq!(slab.page_count(), 1);
+
+        // A freed slot can still be allocated
+        drop(b);
+        let b = slab.allocate()?;
+        drop((a, b));
+        drop(slab);
+        assert!(pages.state().live.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn allocate_across_pages() -> Result<(), AllocError> {
+        let slab = SecureSlab::<32>::new();
+        let per_page = SecureSlab::<32>::slots_per_page();
+        assert!(per_page > 1);
+        assert_eq!(slab.page_count(), 0);
+
+        let mut slots = Vec::new();
+        for i in 0..(per_page + 1) {
+            let mut slot = slab.allocate()?;
+            assert_eq!(*slot, [0; 32]);
+            slot.fill(i as u8);
+            slots.push(slot);
+        }
+        assert_eq!(slab.page_count(), 2);
+
+        for (i, slot) in slots.iter().enumerate() {
+            assert_eq!(**slot, [i as u8; 32]);
+        }
+
+        drop(slots);
+        assert_eq!(slab.page_count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn slots_reused_and_wiped() -> Result<(), AllocError> {
+        let slab = SecureSlab::<16>::new();
+
+        let mut slot = slab.allocate()?;
+        slot.fill(0xff);
+        let ptr = slot.as_ptr();
+        drop(slot);
+
+        // The freed slot should be reused, and should have been wiped
+        let slot = slab.allocate()?;
+        assert_eq!(slot.as_ptr(), ptr);
+        assert_eq!(*slot, [0; 16]);
+        assert_eq!(slab.page_count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_slot_sizes() {
+        assert!(SecureSlab::<0>::new().allocate().is_err());
+        assert!(SecureSlab::<{ 1 << 20 }>::new().allocate().is_err());
+    }
+
+    #[test]
+    fn prereserved_pages() -> Result<(), AllocError> {
+        let per_page = SecureSlab::<64>::slots_per_page();
+        let slab = SecureSlab::<64>::with_prereserved(per_page + 1)?;
+        assert_eq!(slab.page_count(), 2);
+
+        // Allocating the reserved slots shouldn't require any more pages
+        let slots = (0..(2 * per_page))
+            .map(|_| slab.allocate())
+            .collect::<Result<Vec<_>, _>>()?;
+        assert!(slots.iter().all(|slot| **slot == [0; 64]));
+        assert_eq!(slab.page_count(), 2);
+
+        drop(slots);
+        assert_eq!(SecureSlab::<64>::with_prereserved(0)?.page_count(), 0);
+        assert!(SecureSlab::<0>::with_prereserved(1).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+//! Owned secret byte strings which can be compared and hashed without early exits.
+
+use crate::ops::constant_time_eq_padded;
+use crate::SodiumAllocator;
+use std::alloc::AllocError;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+/// A fixed-length byte string stored in memory allocated using [`SodiumAllocator`], whose
+/// [`PartialEq`] and [`Hash`] implementations avoid data-dependent early exits.
+///
+/// The standard comparison of byte slices returns as soon as it finds a difference, so the time it
+/// takes reveals the length of the common prefix of the two inputs. `SecureSlice` instead compares
+/// using [`constant_time_eq_padded`](crate::constant_time_eq_padded), which takes time depending
+/// only on the length of the longer input (unlike `sodium_memcmp`, it also handles inputs of
+/// different lengths). Its `Hash` implementation feeds the length and every byte to the hasher in
+/// one go, so it doesn't branch on the contents either. This makes `SecureSlice` suitable as the
+/// key of a [`HashMap`](std::collections::HashMap) or `HashSet` used for secret lookups, such as
+/// a table of API tokens:
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::SecureSlice;
+/// use std::collections::HashMap;
+///
+/// let mut tokens = HashMap::new();
+/// tokens.insert(SecureSlice::from_slice(b"token-for-alice")?, "alice");
+/// tokens.insert(SecureSlice::from_slice(b"token-for-bob")?, "bob");
+///
+/// let presented = SecureSlice::from_slice(b"token-for-bob")?;
+/// assert_eq!(tokens.get(&presented), Some(&"bob"));
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+///
+/// # Limitations
+/// Hashing a secret has risks of its own, which this type can't address:
+///
+/// * A hash value is derived from the secret. With a weak or unkeyed hasher, an attacker who can
+///   observe hash values (or which bucket a key lands in) can learn about the secret. Only use a
+///   keyed hasher, such as the default [`RandomState`](std::collections::hash_map::RandomState)
+///   of `HashMap`, and never expose hash values.
+/// * A `HashMap` compares the full hashes of keys before comparing the keys themselves, and
+///   probes differently depending on the hash, so the time taken by a lookup still depends on the
+///   secret to some extent, although not in a way that reveals a prefix of it.
+/// * The hasher's own implementation may not run in constant time.
+///
+/// `SecureSlice` deliberately doesn't implement `Borrow<[u8]>`: This would allow map lookups with
+/// an ordinary `&[u8]`, which would be compared using the standard, early-exit comparison.
+/// Lookups must therefore use a `SecureSlice` too.
+pub struct SecureSlice(Box<[u8], SodiumAllocator>);
+
+impl SecureSlice {
+    /// Copy `bytes` into a new `SecureSlice`.
+    ///
+    /// Returns [`AllocError`] if the memory could not be allocated.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, AllocError> {
+        let mut buf = Box::try_new_uninit_slice_in(bytes.len(), SodiumAllocator)?;
+        buf.write_copy_of_slice(bytes);
+        // SAFETY: Every byte has just been initialised.
+        Ok(Self(unsafe { buf.assume_init() }))
+    }
+
+    /// Move the contents of `vec` into a new `SecureSlice`.
+    ///
+    /// If `vec` has spare capacity, its contents are moved into a new allocation of exactly the
+    /// right size, and the old allocation is zeroed as it is freed.
+    pub fn from_vec(vec: Vec<u8, SodiumAllocator>) -> Self {
+        Self(vec.into_boxed_slice())
+    }
+}
+
+impl Deref for SecureSlice {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl PartialEq for SecureSlice {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time_eq_padded(&self.0, &other.0)
+    }
+}
+
+impl Eq for SecureSlice {}
+
+impl Hash for SecureSlice {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Equivalent to the `Hash` implementation for `[u8]`, which doesn't branch on the
+        // contents, but spelled out so it doesn't depend on the standard library's
+        // implementation details.
+        state.write_usize(self.0.len());
+        state.write(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::{HashMap, HashSet};
+
+    fn secure(bytes: &[u8]) -> SecureSlice {
+        SecureSlice::from_slice(bytes).unwrap()
+    }
+
+    fn hash(slice: &SecureSlice) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        slice.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn equality() {
+        assert!(secure(b"hunter2") == secure(b"hunter2"));
+        assert!(secure(b"") == secure(b""));
+        assert!(secure(b"hunter2") != secure(b"hunter3"));
+        assert!(secure(b"hunter2") != secure(b"hunter"));
+        assert!(secure(b"hunter") != secure(b"hunter\0"));
+
+        let mut vec = Vec::with_capacity_in(64, SodiumAllocator);
+        vec.extend_from_slice(b"hunter2");
+        let moved = SecureSlice::from_vec(vec);
+        assert_eq!(&moved[..], b"hunter2");
+        assert!(moved == secure(b"hunter2"));
+    }
+
+    #[test]
+    fn hash_consistent_with_eq() {
+        assert_eq!(hash(&secure(b"hunter2")), hash(&secure(b"hunter2")));
+        assert_ne!(hash(&secure(b"hunter2")), hash(&secure(b"hunter3")));
+        // The length is hashed, so trailing zeroes make a difference
+        assert_ne!(hash(&secure(b"")), hash(&secure(b"\0")));
+    }
+
+    #[test]
+    fn map_key() {
+        let mut map = HashMap::new();
+        for i in 0..32u8 {
+            map.insert(secure(&[i; 16]), i);
+        }
+        assert_eq!(map.len(), 32);
+        assert_eq!(map.get(&secure(&[7; 16])), Some(&7));
+        assert_eq!(map.get(&secure(&[7; 15])), None);
+        assert_eq!(map.remove(&secure(&[31; 16])), Some(31));
+
+        let mut set = HashSet::new();
+        for key in [b"a", b"a", b"b"] {
+            set.insert(secure(key));
+        }
+        assert_eq!(set.len(), 2);
+    }
+}
@@ -0,0 +1,288 @@
+//! Tracking of whether libsodium has been initialised.
+
+use crate::error::AllocationError;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// The initialisation state of this library, as returned by [`init_state`].
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum InitState {
+    /// Initialisation hasn't been attempted yet, or the last attempt failed in a way which may be
+    /// transient, so it will be retried on the next allocation.
+    Uninitialized,
+
+    /// libsodium has been initialised, and memory can be allocated.
+    Ready,
+
+    /// Initialisation failed permanently, and every allocation will fail with
+    /// [`AllocationError::InitFailed`].
+    Failed,
+}
+
+/// The result of a single attempt to initialise the library.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum InitOutcome {
+    /// Initialisation succeeded.
+    Ready,
+
+    /// Initialisation failed, but might succeed if retried later.
+    Transient,
+
+    /// Initialisation failed, and retrying won't help.
+    ///
+    /// Currently only returned by the `require-guard-pages` check.
+    #[cfg_attr(
+        any(not(feature = "require-guard-pages"), feature = "test-backend"),
+        allow(dead_code)
+    )]
+    Permanent,
+}
+
+const UNINITIALIZED: u8 = 0;
+const INITIALIZING: u8 = 1;
+const READY: u8 = 2;
+const FAILED: u8 = 3;
+
+/// Caches the outcome of initialisation, so that the initialisation function only needs to run
+/// until it succeeds or fails permanently.
+///
+/// The state is a small state machine stored in an atomic: `UNINITIALIZED -> INITIALIZING ->
+/// {READY, FAILED, UNINITIALIZED}`. A thread must win a `compare_exchange` from `UNINITIALIZED` to
+/// `INITIALIZING` to run the initialisation function, so only one thread ever runs it at a time.
+/// Other threads wait for that attempt to finish, and then observe its result. Once the cell is
+/// `READY`, checking it is a single atomic load.
+pub(crate) struct InitCell {
+    state: AtomicU8,
+}
+
+impl InitCell {
+    pub(crate) const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(UNINITIALIZED),
+        }
+    }
+
+    pub(crate) fn state(&self) -> InitState {
+        match self.state.load(Ordering::Acquire) {
+            READY => InitState::Ready,
+            FAILED => InitState::Failed,
+            // Report an attempt in progress as uninitialised: It might still fail transiently.
+            _ => InitState::Uninitialized,
+        }
+    }
+
+    /// Run `init`, unless a previous call already succeeded or failed permanently, in which case
+    /// its cached result is returned.
+    ///
+    /// If another thread is already running its initialisation function, this waits for it to
+    /// finish, and returns its result instead of running `init`. If that attempt failed
+    /// transiently, this returns an error without retrying: The next call will try again.
+    pub(crate) fn get_or_init(
+        &self,
+        init: impl FnOnce() -> InitOutcome,
+    ) -> Result<(), AllocationError> {
+        let mut waited = false;
+
+        loop {
+            match self.state.load(Ordering::Acquire) {
+                READY => return Ok(()),
+                FAILED => return Err(AllocationError::InitFailed),
+                // The attempt we were waiting for failed transiently.
+                UNINITIALIZED if waited => return Err(AllocationError::InitFailed),
+                UNINITIALIZED => {
+                    if self
+                        .state
+                        .compare_exchange(
+                            UNINITIALIZED,
+                            INITIALIZING,
+                            Ordering::Acquire,
+                            Ordering::Acquire,
+                        )
+                        .is_ok()
+                    {
+                        return self.run(init);
+                    }
+                }
+                _ => {
+                    // Initialisation is quick, so just spin until it's finished.
+                    waited = true;
+                    std::hint::spin_loop();
+                    std::thread::yield_now();
+                }
+            }
+        }
+    }
+
+    /// Run `init`, once this thread has moved the state to `INITIALIZING`, and store its result.
+    fn run(&self, init: impl FnOnce() -> InitOutcome) -> Result<(), AllocationError> {
+        /// Resets the state if `init` panics, so waiting threads don't wait forever.
+        struct Reset<'a>(&'a AtomicU8);
+
+        impl Drop for Reset<'_> {
+            fn drop(&mut self) {
+                self.0.store(UNINITIALIZED, Ordering::Release);
+            }
+        }
+
+        let reset = Reset(&self.state);
+        let outcome = init();
+        std::mem::forget(reset);
+
+        let (state, result) = match outcome {
+            InitOutcome::Ready => (READY, Ok(())),
+            InitOutcome::Transient => (UNINITIALIZED, Err(AllocationError::InitFailed)),
+            InitOutcome::Permanent => (FAILED, Err(AllocationError::InitFailed)),
+        };
+        self.state.store(state, Ordering::Release);
+
+        result
+    }
+}
+
+/// The initialisation state used by every allocation.
+pub(crate) static INIT: InitCell = InitCell::new();
+
+/// Returns the initialisation state of this library.
+///
+/// libsodium is initialised on the first allocation (or before `main`, with the `global-init`
+/// feature enabled). If `sodium_init` fails, this is treated as transient: For example, this can
+/// happen if a service starts before the system's entropy source is available. The allocation
+/// fails, but initialisation is retried on the next allocation, and the state remains
+/// [`InitState::Uninitialized`]. Failures which can't be fixed by retrying, such as the linked
+/// libsodium not supporting guard pages when the `require-guard-pages` feature is enabled, are
+/// cached, and the state becomes [`InitState::Failed`].
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::{init_state, warmup, InitState};
+///
+/// warmup()?;
+/// assert_eq!(init_state(), InitState::Ready);
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+pub fn init_state() -> InitState {
+    INIT.state()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Barrier;
+    use std::thread;
+    use std::time::Duration;
+
+    const THREADS: usize = 64;
+
+    /// Call `get_or_init` on `cell` from many threads at once, using an initialisation function
+    /// which takes a while and returns `outcome`. Returns the results, and the number of times
+    /// the initialisation function ran.
+    fn race(cell: &InitCell, outcome: InitOutcome) -> (Vec<Result<(), AllocationError>>, usize) {
+        let runs = AtomicUsize::new(0);
+        let barrier = Barrier::new(THREADS);
+
+        let results = thread::scope(|s| {
+            let handles: Vec<_> = (0..THREADS)
+                .map(|_| {
+                    s.spawn(|| {
+                        barrier.wait();
+                        cell.get_or_init(|| {
+                            runs.fetch_add(1, Ordering::Relaxed);
+                            thread::sleep(Duration::from_millis(50));
+                            outcome
+                        })
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        (results, runs.into_inner())
+    }
+
+    #[test]
+    fn transient_failure_retried() {
+        let cell = InitCell::new();
+        assert_eq!(cell.state(), InitState::Uninitialized);
+
+        let attempts = Cell::new(0);
+        let attempt = |outcome| {
+            attempts.set(attempts.get() + 1);
+            outcome
+        };
+
+        assert_eq!(
+            cell.get_or_init(|| attempt(InitOutcome::Transient)),
+            Err(AllocationError::InitFailed)
+        );
+        assert_eq!(cell.state(), InitState::Uninitialized);
+
+        assert_eq!(cell.get_or_init(|| attempt(InitOutcome::Ready)), Ok(()));
+        assert_eq!(cell.state(), InitState::Ready);
+
+        // Once ready, the initialisation function isn't run again
+        assert_eq!(cell.get_or_init(|| attempt(InitOutcome::Permanent)), Ok(()));
+        assert_eq!(cell.state(), InitState::Ready);
+        assert_eq!(attempts.get(), 2);
+    }
+
+    #[test]
+    fn permanent_failure_cached() {
+        let cell = InitCell::new();
+        let attempts = Cell::new(0);
+        let attempt = |outcome| {
+            attempts.set(attempts.get() + 1);
+            outcome
+        };
+
+        assert_eq!(
+            cell.get_or_init(|| attempt(InitOutcome::Permanent)),
+            Err(AllocationError::InitFailed)
+        );
+        assert_eq!(cell.state(), InitState::Failed);
+
+        assert_eq!(
+            cell.get_or_init(|| attempt(InitOutcome::Ready)),
+            Err(AllocationError::InitFailed)
+        );
+        assert_eq!(cell.state(), InitState::Failed);
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[test]
+    fn concurrent_init_runs_once() {
+        let cell = InitCell::new();
+        let (results, runs) = race(&cell, InitOutcome::Ready);
+        assert_eq!(runs, 1);
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(cell.state(), InitState::Ready);
+    }
+
+    #[test]
+    fn concurrent_failure_observed() {
+        let cell = InitCell::new();
+        let (results, runs) = race(&cell, InitOutcome::Permanent);
+        assert_eq!(runs, 1);
+        assert!(results
+            .iter()
+            .all(|r| *r == Err(AllocationError::InitFailed)));
+        assert_eq!(cell.state(), InitState::Failed);
+    }
+
+    #[test]
+    fn panic_during_init_resets() {
+        let cell = InitCell::new();
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cell.get_or_init(|| panic!("init failed"))
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(cell.state(), InitState::Uninitialized);
+        assert_eq!(cell.get_or_init(|| InitOutcome::Ready), Ok(()));
+    }
+
+    #[test]
+    fn ready_after_allocation() {
+        let _ = Box::new_in(0u8, crate::SodiumAllocator);
+        assert_eq!(init_state(), InitState::Ready);
+    }
+}
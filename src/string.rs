@@ -0,0 +1,352 @@
+//! Strings stored in memory allocated using Sodium.
+
+use crate::error::StringError;
+use crate::{memzero, secure_vec_from_slice_with_capacity, SecureVecExt, SodiumAllocator};
+use std::alloc::AllocError;
+use std::ffi::CStr;
+use std::ops::Deref;
+
+/// A UTF-8 string, stored in memory allocated using [`SodiumAllocator`].
+///
+/// The string dereferences to [`str`]. Its memory is securely zeroed when it is dropped. A
+/// `SecureString` can be built up piece by piece using a [`SecureStringBuilder`].
+pub struct SecureString {
+    /// Always valid UTF-8.
+    buf: Vec<u8, SodiumAllocator>,
+}
+
+impl SecureString {
+    /// Create a new, empty `SecureString`.
+    ///
+    /// No memory is allocated until something is added to the string.
+    pub const fn new() -> Self {
+        Self {
+            buf: Vec::new_in(SodiumAllocator),
+        }
+    }
+
+    /// Move the contents of `s` into guarded memory, wiping the original.
+    ///
+    /// The contents are copied into a new guarded allocation, and then the *entire* capacity of
+    /// `s` (including any stale data in its spare capacity) is securely zeroed before it is freed.
+    /// This is useful for secrets received as a `String` from another API, such as a password
+    /// read from a prompt: Unlike a string literal, its heap buffer can be wiped.
+    ///
+    /// Returns [`AllocError`] if the memory could not be allocated. `s` is wiped regardless.
+    ///
+    /// ```
+    /// # #![feature(allocator_api)]
+    /// use sodium_alloc::SecureString;
+    ///
+    /// let password = String::from("hunter2");
+    /// let password = SecureString::from_string_wiping(password)?;
+    /// assert_eq!(&*password, "hunter2");
+    /// # Ok::<(), std::alloc::AllocError>(())
+    /// ```
+    pub fn from_string_wiping(mut s: String) -> Result<Self, AllocError> {
+        Self::copy_and_wipe(&mut s)
+    }
+
+    /// Copy the contents of `s` into a new `SecureString`, then zero the entire capacity of `s`,
+    /// leaving it empty.
+    fn copy_and_wipe(s: &mut String) -> Result<Self, AllocError> {
+        let copy = secure_vec_from_slice_with_capacity(s.as_bytes(), 0);
+
+        // SAFETY: The buffer is filled with zeroes and then cleared, so `s` remains valid UTF-8.
+        let vec = unsafe { s.as_mut_vec() };
+        // Initialise the spare capacity so that it can be wiped along with the rest of the
+        // buffer. This never reallocates, as the new length is the existing capacity.
+        vec.resize(vec.capacity(), 0);
+        memzero(vec);
+        vec.clear();
+
+        Ok(Self { buf: copy? })
+    }
+
+    /// Get the contents of the string.
+    pub fn as_str(&self) -> &str {
+        // SAFETY: `buf` always contains valid UTF-8.
+        unsafe { std::str::from_utf8_unchecked(&self.buf) }
+    }
+}
+
+impl Default for SecureString {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for SecureString {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.as_str()
+    }
+}
+
+/// A builder which assembles a [`SecureString`] from pieces, entirely in guarded memory.
+///
+/// Text can be appended as `&str`s, `char`s, or raw bytes. Bytes are validated as UTF-8
+/// incrementally, and a multi-byte character may be split across several calls to
+/// [`push_bytes`](Self::push_bytes): This is useful when assembling a secret from chunks of bytes,
+/// such as a passphrase received over a channel. No part of the string is ever stored in memory
+/// from the standard allocator. If the buffer needs to grow, the old buffer is freed using Sodium,
+/// so it is securely zeroed.
+///
+/// ```
+/// use sodium_alloc::SecureStringBuilder;
+///
+/// let mut builder = SecureStringBuilder::new();
+/// builder.push_str("pass")?;
+/// builder.push('ф')?;
+/// // The first half of a two-byte character, the second half comes later
+/// builder.push_bytes(&[0xc3])?;
+/// builder.push_bytes(&[0xa9])?;
+/// let password = builder.build()?;
+/// assert_eq!(&*password, "passфé");
+/// # Ok::<(), sodium_alloc::StringError>(())
+/// ```
+pub struct SecureStringBuilder {
+    buf: Vec<u8, SodiumAllocator>,
+    /// The length of the valid UTF-8 prefix of `buf`. Any bytes after this point form the
+    /// incomplete start of a multi-byte character.
+    valid: usize,
+}
+
+impl SecureStringBuilder {
+    /// Create a new, empty `SecureStringBuilder`.
+    pub const fn new() -> Self {
+        Self {
+            buf: Vec::new_in(SodiumAllocator),
+            valid: 0,
+        }
+    }
+
+    /// Append a string slice.
+    ///
+    /// Returns [`StringError::InvalidUtf8`] if an incomplete character was pushed using
+    /// [`push_bytes`](Self::push_bytes) immediately before this, or
+    /// [`StringError::OutOfMemory`] if the buffer could not be grown. The builder is unchanged if
+    /// an error is returned.
+    pub fn push_str(&mut self, s: &str) -> Result<(), StringError> {
+        self.push_bytes(s.as_bytes())
+    }
+
+    /// Append a single character.
+    ///
+    /// Errors are as for [`push_str`](Self::push_str).
+    pub fn push(&mut self, c: char) -> Result<(), StringError> {
+        self.push_str(c.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Append raw bytes, which must be UTF-8. The bytes may end partway through a multi-byte
+    /// character, in which case the rest of the character must be appended next.
+    ///
+    /// Returns [`StringError::InvalidUtf8`] if the bytes (following any incomplete character
+    /// from the previous call) are not valid UTF-8, or [`StringError::OutOfMemory`] if the buffer
+    /// could not be grown. The builder is unchanged if an error is returned.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> Result<(), StringError> {
+        let len = self.buf.len();
+        self.buf
+            .try_reserve(bytes.len())
+            .map_err(|_| StringError::OutOfMemory)?;
+        self.buf.extend_from_slice(bytes);
+
+        match std::str::from_utf8(&self.buf[self.valid..]) {
+            Ok(_) => self.valid = self.buf.len(),
+            // The bytes end with the start of a multi-byte character
+            Err(e) if e.error_len().is_none() => self.valid += e.valid_up_to(),
+            Err(_) => {
+                self.buf.truncate_wiping(len);
+                return Err(StringError::InvalidUtf8);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finish building the string.
+    ///
+    /// Returns [`StringError::InvalidUtf8`] if the string ends with an incomplete character.
+    pub fn build(mut self) -> Result<SecureString, StringError> {
+        if self.valid != self.buf.len() {
+            self.buf.truncate_wiping(self.valid);
+            return Err(StringError::InvalidUtf8);
+        }
+
+        Ok(SecureString { buf: self.buf })
+    }
+}
+
+impl Default for SecureStringBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A NUL-terminated C string, stored in memory allocated using [`SodiumAllocator`].
+///
+/// This is the guarded equivalent of [`CString`](std::ffi::CString), for passing secrets (such as
+/// passphrases) to C APIs which expect a NUL-terminated string, without making an unguarded copy.
+/// The string dereferences to [`CStr`], and its memory is securely zeroed when it is dropped.
+///
+/// ```
+/// use sodium_alloc::SecureCString;
+///
+/// let passphrase = SecureCString::new(b"hunter2")?;
+/// assert_eq!(passphrase.as_c_str().to_bytes(), b"hunter2");
+/// // `passphrase.as_ptr()` can be passed to C
+/// # Ok::<(), sodium_alloc::StringError>(())
+/// ```
+pub struct SecureCString {
+    /// Always ends with the only NUL byte in the buffer.
+    buf: Vec<u8, SodiumAllocator>,
+}
+
+impl SecureCString {
+    /// Copy `bytes` into a new `SecureCString`, adding a NUL terminator.
+    ///
+    /// Returns [`StringError::InteriorNul`] if `bytes` contains a NUL byte, or
+    /// [`StringError::OutOfMemory`] if the memory could not be allocated.
+    pub fn new(bytes: &[u8]) -> Result<Self, StringError> {
+        if bytes.contains(&0) {
+            return Err(StringError::InteriorNul);
+        }
+
+        let mut buf = Vec::new_in(SodiumAllocator);
+        buf.try_reserve_exact(bytes.len() + 1)
+            .map_err(|_| StringError::OutOfMemory)?;
+        buf.extend_from_slice(bytes);
+        buf.push(0);
+
+        Ok(Self { buf })
+    }
+
+    /// Get the contents of the string as a [`CStr`].
+    pub fn as_c_str(&self) -> &CStr {
+        // SAFETY: `buf` ends with a NUL byte, and contains no other NUL bytes.
+        unsafe { CStr::from_bytes_with_nul_unchecked(&self.buf) }
+    }
+}
+
+impl Deref for SecureCString {
+    type Target = CStr;
+
+    fn deref(&self) -> &Self::Target {
+        self.as_c_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_multibyte() -> Result<(), StringError> {
+        let text = "κλειδί 🔑 ключ";
+
+        // Push the text in chunks of 3 bytes, so most characters are split across chunks
+        let mut builder = SecureStringBuilder::new();
+        for chunk in text.as_bytes().chunks(3) {
+            builder.push_bytes(chunk)?;
+        }
+        builder.push('!')?;
+        builder.push_str(" ok")?;
+
+        let string = builder.build()?;
+        assert_eq!(string.as_str(), "κλειδί 🔑 ключ! ok");
+        assert_eq!(string.len(), text.len() + 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_utf8_rejected() -> Result<(), StringError> {
+        let mut builder = SecureStringBuilder::new();
+        builder.push_str("key")?;
+        assert_eq!(builder.push_bytes(&[0xff]), Err(StringError::InvalidUtf8));
+
+        // An incomplete character can't be followed by the start of another
+        builder.push_bytes(&[0xe2, 0x82])?;
+        assert_eq!(builder.push_str("a"), Err(StringError::InvalidUtf8));
+        builder.push_bytes(&[0xac])?;
+
+        assert_eq!(builder.build()?.as_str(), "key€");
+
+        Ok(())
+    }
+
+    #[test]
+    fn incomplete_character_at_end() -> Result<(), StringError> {
+        let mut builder = SecureStringBuilder::new();
+        builder.push_bytes(&[b'a', 0xf0, 0x9f])?;
+        assert_eq!(builder.build().err(), Some(StringError::InvalidUtf8));
+
+        assert!(SecureStringBuilder::new().build()?.is_empty());
+        assert!(SecureString::new().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_string_wiping() -> Result<(), AllocError> {
+        let password = String::from("correct horse battery staple");
+        let secret = SecureString::from_string_wiping(password)?;
+        assert_eq!(&*secret, "correct horse battery staple");
+
+        Ok(())
+    }
+
+    #[test]
+    fn source_string_wiped() -> Result<(), AllocError> {
+        let mut password = String::with_capacity(64);
+        password.push_str("hunter2 and then some");
+        // Leave stale data in the spare capacity, too
+        password.truncate(7);
+
+        let secret = SecureString::copy_and_wipe(&mut password)?;
+        assert_eq!(&*secret, "hunter2");
+        assert!(password.is_empty());
+        assert_eq!(password.capacity(), 64);
+        // SAFETY: The entire capacity was initialised before it was wiped.
+        let buf = unsafe { std::slice::from_raw_parts(password.as_ptr(), password.capacity()) };
+        assert!(buf.iter().all(|&b| b == 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn c_string_from_bytes() -> Result<(), StringError> {
+        let string = SecureCString::new(b"hunter2")?;
+        let c_str = string.as_c_str();
+        assert_eq!(c_str.to_bytes(), b"hunter2");
+        assert_eq!(c_str.to_bytes_with_nul(), b"hunter2\0");
+        assert_eq!(unsafe { strlen(string.as_ptr()) }, 7);
+
+        let empty = SecureCString::new(b"")?;
+        assert_eq!(empty.to_bytes_with_nul(), b"\0");
+
+        Ok(())
+    }
+
+    /// Count the bytes before the terminator, as a C function would.
+    unsafe fn strlen(ptr: *const std::ffi::c_char) -> usize {
+        let mut len = 0;
+        while *ptr.add(len) != 0 {
+            len += 1;
+        }
+        len
+    }
+
+    #[test]
+    fn c_string_interior_nul() {
+        assert_eq!(
+            SecureCString::new(b"hun\0ter2").err(),
+            Some(StringError::InteriorNul)
+        );
+        assert_eq!(
+            SecureCString::new(b"hunter2\0").err(),
+            Some(StringError::InteriorNul)
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Structs of secrets stored in memory allocated using Sodium.
+//!
+//! Such structs are defined using the declarative [`secure_struct!`](crate::secure_struct) macro,
+//! rather than a `#[derive(SecureStruct)]` attribute: A derive macro must be a procedural macro,
+//! which would have to live in a separate crate.
+
+/// Types for which a value with every byte set to zero is valid.
+///
+/// This allows values of the type to be allocated directly in zeroed, guarded memory using
+/// [`secure_box_zeroed`](crate::secure_box_zeroed), without ever constructing a value on the stack.
+/// It is implemented for the primitive integer types, and arrays of `Zeroable` types, and is
+/// implemented automatically for structs defined using [`secure_struct!`](crate::secure_struct).
+///
+/// # Safety
+/// The all-zero bit pattern must be a valid value of the type.
+pub unsafe trait Zeroable {}
+
+macro_rules! impl_zeroable {
+    ($($ty:ty),*) => {
+        $(
+            // SAFETY: Zero is a valid integer.
+            unsafe impl Zeroable for $ty {}
+        )*
+    };
+}
+
+impl_zeroable!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+// SAFETY: An array of zeroed `T`s is valid if a zeroed `T` is.
+unsafe impl<T: Zeroable, const N: usize> Zeroable for [T; N] {}
+
+/// Define a `#[repr(C)]` struct of secrets, which is always stored in guarded memory.
+///
+/// The struct must be `#[repr(C)]`, so its layout is fully specified, and every field must be
+/// [`Zeroable`] (for example, a fixed-size byte array). The macro defines the struct, and a
+/// `new_secure` constructor which allocates a zeroed instance in a single allocation made using
+/// [`SodiumAllocator`](crate::SodiumAllocator), returning it in a [`SecureBox`](crate::SecureBox).
+/// The fields can then be filled in place. As with any `SecureBox`, the whole struct is securely
+/// zeroed when the box is dropped.
+///
+/// This is a declarative macro, so it only accepts a limited form of struct definition: A
+/// non-generic struct with named fields. Only doc comments can be placed on the struct and its
+/// fields: Other attributes (in particular `#[derive(Clone, Debug)]`, which could copy or print
+/// the secrets) are rejected.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::secure_struct;
+///
+/// secure_struct! {
+///     /// Keys derived for a session.
+///     #[repr(C)]
+///     pub struct SessionKeys {
+///         /// Key for encrypting messages.
+///         pub enc_key: [u8; 32],
+///         /// Key for authenticating messages.
+///         pub mac_key: [u8; 32],
+///         pub counter: u64,
+///     }
+/// }
+///
+/// let mut keys = SessionKeys::new_secure()?;
+/// keys.enc_key.fill(0x13);
+/// keys.counter += 1;
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+///
+/// ```compile_fail
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::secure_struct;
+///
+/// secure_struct! {
+///     #[derive(Clone, Debug)]
+///     #[repr(C)]
+///     pub struct Leaky {
+///         pub key: [u8; 32],
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! secure_struct {
+    (
+        $(#[doc = $doc:expr])*
+        #[repr(C)]
+        $vis:vis struct $name:ident {
+            $(
+                $(#[doc = $field_doc:expr])*
+                $field_vis:vis $field:ident : $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[doc = $doc])*
+        #[repr(C)]
+        $vis struct $name {
+            $(
+                $(#[doc = $field_doc])*
+                $field_vis $field: $ty,
+            )*
+        }
+
+        // Every field must be `Zeroable` for the struct to be.
+        const _: fn() = || {
+            fn assert_zeroable<T: $crate::Zeroable>() {}
+            $(assert_zeroable::<$ty>();)*
+        };
+
+        // SAFETY: Every field is `Zeroable` (checked above), so a zeroed struct is valid. Any
+        // padding bytes may be zero.
+        unsafe impl $crate::Zeroable for $name {}
+
+        impl $name {
+            /// Allocate a new instance in memory allocated using Sodium, with every field zeroed.
+            ///
+            /// Returns `AllocError` if the memory could not be allocated.
+            $vis fn new_secure(
+            ) -> ::std::result::Result<$crate::SecureBox<Self>, ::std::alloc::AllocError> {
+                $crate::secure_box_zeroed()
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::SecureBox;
+    use std::alloc::AllocError;
+
+    secure_struct! {
+        /// A struct with several fields, and some padding.
+        #[repr(C)]
+        struct KeyMaterial {
+            enc_key: [u8; 32],
+            flags: u8,
+            /// Preceded by padding.
+            counter: u64,
+            mac_key: [u8; 16],
+        }
+    }
+
+    #[test]
+    fn single_zeroed_allocation() -> Result<(), AllocError> {
+        let mut keys: SecureBox<KeyMaterial> = KeyMaterial::new_secure()?;
+        assert_eq!(keys.enc_key, [0; 32]);
+        assert_eq!(keys.flags, 0);
+        assert_eq!(keys.counter, 0);
+        assert_eq!(keys.mac_key, [0; 16]);
+
+        keys.enc_key.fill(0xaa);
+        keys.mac_key.fill(0xbb);
+        keys.counter = u64::MAX;
+
+        #[cfg(feature = "track-allocations")]
+        {
+            use crate::tracking::{allocation_size, is_live};
+
+            let ptr = &*keys as *const KeyMaterial;
+            assert!(is_live(ptr));
+            // The fields are all stored in the one allocation
+            assert!(allocation_size(ptr as *const u8).is_some());
+            assert!(!is_live(&keys.mac_key));
+
+            // Freed memory can't be inspected, so we only check that the struct is freed: Memory
+            // freed by `SodiumAllocator` is always zeroed by `sodium_free` (the shim's zeroing
+            // is tested in `sys`).
+            drop(keys);
+            assert!(!is_live(ptr));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+//! Thin wrappers around the libsodium functions used for guarded allocation.
+//!
+//! Everything else in this crate manages guarded memory through these functions, rather than
+//! calling libsodium directly. This allows the foreign functions to be swapped out when running
+//! under [Miri](https://github.com/rust-lang/miri), which can't call foreign code: Under Miri, a
+//! shim implemented using the standard allocator is used instead. The same shim is used when the
+//! `test-backend` feature is enabled.
+//!
+//! The shim is for testing only. It provides the same observable behaviour as Sodium with respect
+//! to size, alignment, and zeroing memory on free, so that the memory safety of the Rust code in
+//! this crate (and code using it) can be checked. It does **not** provide guard pages or locked
+//! memory, behaving like a libsodium built for a platform without page protection. A fixed canary
+//! is placed before each allocation, but it is never checked when memory is freed.
+
+#[cfg(not(any(miri, feature = "test-backend")))]
+pub(crate) use self::sodium_impl::*;
+
+#[cfg(any(miri, feature = "test-backend"))]
+pub(crate) use self::shim_impl::*;
+
+#[cfg(not(any(miri, feature = "test-backend")))]
+mod sodium_impl {
+    use libsodium_sys as sodium;
+    use std::ffi::c_void;
+
+    /// Initialise libsodium, returning whether initialisation was successful.
+    pub(crate) fn init() -> bool {
+        // SAFETY: `sodium_init` can be called any number of times, from any thread.
+        unsafe { sodium::sodium_init() >= 0 }
+    }
+
+    /// Allocate `size` bytes of guarded memory, returning NULL on failure.
+    ///
+    /// The end of the allocation is placed at the end of a page. The allocated memory is filled
+    /// with the garbage value `0xdb`.
+    ///
+    /// # Safety
+    /// libsodium must have been initialised.
+    pub(crate) unsafe fn malloc(size: usize) -> *mut u8 {
+        sodium::sodium_malloc(size) as *mut u8
+    }
+
+    /// Zero and free memory allocated using [`malloc`]. Does nothing if `ptr` is NULL.
+    ///
+    /// # Safety
+    /// `ptr` must be NULL, or a pointer returned by [`malloc`] which has not yet been freed.
+    pub(crate) unsafe fn free(ptr: *mut u8) {
+        sodium::sodium_free(ptr as *mut c_void);
+    }
+
+    /// Mark memory allocated using [`malloc`] as readable and writable, returning whether this
+    /// was successful.
+    ///
+    /// # Safety
+    /// `ptr` must be a pointer returned by [`malloc`] which has not yet been freed.
+    pub(crate) unsafe fn mprotect_readwrite(ptr: *mut u8) -> bool {
+        sodium::sodium_mprotect_readwrite(ptr as *mut c_void) == 0
+    }
+
+    /// Mark memory allocated using [`malloc`] as read-only, returning whether this was
+    /// successful.
+    ///
+    /// # Safety
+    /// `ptr` must be a pointer returned by [`malloc`] which has not yet been freed.
+    pub(crate) unsafe fn mprotect_readonly(ptr: *mut u8) -> bool {
+        sodium::sodium_mprotect_readonly(ptr as *mut c_void) == 0
+    }
+
+    /// Mark memory allocated using [`malloc`] as inaccessible, returning whether this was
+    /// successful.
+    ///
+    /// # Safety
+    /// `ptr` must be a pointer returned by [`malloc`] which has not yet been freed.
+    pub(crate) unsafe fn mprotect_noaccess(ptr: *mut u8) -> bool {
+        sodium::sodium_mprotect_noaccess(ptr as *mut c_void) == 0
+    }
+
+    /// Unlock `len` bytes of memory at `ptr` which were locked by [`malloc`], allowing them to be
+    /// swapped to disk. Failure is ignored, leaving the memory locked.
+    ///
+    /// We don't use `sodium_munlock` for this, as it also re-enables including the memory in core
+    /// dumps.
+    ///
+    /// # Safety
+    /// `ptr` must be page-aligned, and the region must lie within the unprotected pages of an
+    /// allocation returned by [`malloc`] which has not yet been freed.
+    pub(crate) unsafe fn munlock(ptr: *mut u8, len: usize) {
+        #[cfg(unix)]
+        libc::munlock(ptr as *const c_void, len);
+
+        #[cfg(windows)]
+        windows_sys::Win32::System::Memory::VirtualUnlock(ptr as *const c_void, len);
+    }
+
+    /// Fill `buf` with random bytes.
+    ///
+    /// # Safety
+    /// libsodium must have been initialised.
+    pub(crate) unsafe fn randombytes(buf: &mut [u8]) {
+        sodium::randombytes_buf(buf.as_mut_ptr() as *mut c_void, buf.len());
+    }
+
+    /// Get the version of libsodium in use.
+    pub(crate) fn version() -> &'static str {
+        // SAFETY: `sodium_version_string` returns a pointer to a static, NUL-terminated string.
+        let version = unsafe { std::ffi::CStr::from_ptr(sodium::sodium_version_string()) };
+        version.to_str().unwrap_or("")
+    }
+
+    /// Compare two equal-length buffers in constant time, returning whether they are equal.
+    ///
+    /// # Panics
+    /// Panics if the two buffers have different lengths.
+    pub(crate) fn memcmp(a: &[u8], b: &[u8]) -> bool {
+        assert_eq!(a.len(), b.len());
+        // SAFETY: Both pointers are valid for reads of `a.len()` bytes.
+        unsafe { sodium::sodium_memcmp(a.as_ptr().cast(), b.as_ptr().cast(), a.len()) == 0 }
+    }
+}
+
+#[cfg(any(miri, feature = "test-backend"))]
+mod shim_impl {
+    use crate::page::{page_size, round_to_page, CANARY_SIZE};
+    use crate::wipe::memzero;
+    use std::alloc::{self, Layout};
+
+    /// Space reserved before each allocation, enough to store the size of the underlying
+    /// allocation, followed by a canary immediately before the allocation.
+    const HEADER_SIZE: usize = 16 + CANARY_SIZE;
+
+    /// The shim's canary. Unlike Sodium's, this is the same in every process.
+    const CANARY: [u8; CANARY_SIZE] = [0xca; CANARY_SIZE];
+
+    pub(crate) fn init() -> bool {
+        true
+    }
+
+    pub(crate) unsafe fn malloc(size: usize) -> *mut u8 {
+        // Like Sodium, we allocate whole pages, and place the returned pointer so that the end of
+        // the allocation is at the end of a page.
+        let total = match size.checked_add(HEADER_SIZE + page_size()) {
+            Some(_) => round_to_page(size + HEADER_SIZE),
+            None => return std::ptr::null_mut(),
+        };
+        let layout = match Layout::from_size_align(total, page_size()) {
+            Ok(layout) => layout,
+            Err(_) => return std::ptr::null_mut(),
+        };
+
+        let base = alloc::alloc(layout);
+        if base.is_null() {
+            return base;
+        }
+        // Sodium fills new allocations with garbage, so do the same here.
+        base.write_bytes(0xdb, total);
+        (base as *mut usize).write(total);
+        let ptr = base.add(total - size);
+        ptr.sub(CANARY_SIZE)
+            .copy_from_nonoverlapping(CANARY.as_ptr(), CANARY_SIZE);
+
+        ptr
+    }
+
+    pub(crate) unsafe fn free(ptr: *mut u8) {
+        if ptr.is_null() {
+            return;
+        }
+
+        let (base, total) = wipe(ptr);
+        alloc::dealloc(base, Layout::from_size_align_unchecked(total, page_size()));
+    }
+
+    /// Zero the whole underlying allocation for `ptr`, including its header, returning its base
+    /// pointer and total size.
+    ///
+    /// # Safety
+    /// `ptr` must be a pointer returned by [`malloc`] which has not yet been freed.
+    unsafe fn wipe(ptr: *mut u8) -> (*mut u8, usize) {
+        // The header always lies within the first page of the allocation.
+        let offset = (ptr.addr() - HEADER_SIZE) % page_size() + HEADER_SIZE;
+        let base = ptr.sub(offset);
+        let total = (base as *mut usize).read();
+
+        memzero(std::slice::from_raw_parts_mut(base, total));
+        (base, total)
+    }
+
+    pub(crate) unsafe fn mprotect_readwrite(_ptr: *mut u8) -> bool {
+        // Page protection isn't supported by the shim.
+        false
+    }
+
+    pub(crate) unsafe fn mprotect_readonly(_ptr: *mut u8) -> bool {
+        false
+    }
+
+    pub(crate) unsafe fn mprotect_noaccess(_ptr: *mut u8) -> bool {
+        false
+    }
+
+    pub(crate) unsafe fn munlock(_ptr: *mut u8, _len: usize) {
+        // The shim never locks memory.
+    }
+
+    pub(crate) unsafe fn randombytes(buf: &mut [u8]) {
+        // Not remotely random, but the shim only needs to produce non-zero data.
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = (i as u8) | 1;
+        }
+    }
+
+    pub(crate) fn version() -> &'static str {
+        "shim"
+    }
+
+    pub(crate) fn memcmp(a: &[u8], b: &[u8]) -> bool {
+        assert_eq!(a.len(), b.len());
+        a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn zeroed_on_free() {
+            for size in [0, 1, 32, 4096, 10000] {
+                unsafe {
+                    let ptr = malloc(size);
+                    assert!(!ptr.is_null());
+                    assert_eq!((ptr.addr() + size) % page_size(), 0);
+                    ptr.write_bytes(0xff, size);
+
+                    // `free` is `wipe` followed by deallocation, and freed memory can't be
+                    // inspected, so check what `wipe` leaves behind instead.
+                    let (base, total) = wipe(ptr);
+                    let block = std::slice::from_raw_parts(base, total);
+                    assert!(block.iter().all(|&b| b == 0));
+                    alloc::dealloc(base, Layout::from_size_align_unchecked(total, page_size()));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,22 @@
+//! Helpers shared by the unit tests.
+
+/// Fork the process, running `child` in the child process, and return whether it exited
+/// successfully.
+///
+/// `child` must not allocate, or do anything else which isn't safe to do in a child process
+/// forked from a multi-threaded parent. If `child` faults, the child is terminated by the signal,
+/// and this returns `false`.
+#[cfg(all(target_os = "linux", not(any(miri, feature = "test-backend"))))]
+pub(crate) fn fork_test(child: impl FnOnce() -> bool) -> bool {
+    // SAFETY: The child only runs `child`, then exits immediately.
+    match unsafe { libc::fork() } {
+        -1 => panic!("fork failed"),
+        0 => unsafe { libc::_exit(if child() { 0 } else { 1 }) },
+        pid => {
+            let mut status = 0;
+            // SAFETY: `pid` is our child process.
+            assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+            libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Signing keys for `rustls` whose secrets are stored in memory allocated using Sodium.
+
+use crate::{memzero, SecureArray};
+use ed25519_dalek::Signer as _;
+use rustls::pki_types::SubjectPublicKeyInfoDer;
+use rustls::sign::{Signer, SigningKey};
+use rustls::{Error, SignatureAlgorithm, SignatureScheme};
+use std::alloc::AllocError;
+use std::sync::Arc;
+
+/// The DER encoding of an Ed25519 `SubjectPublicKeyInfo`, up to the public key itself (RFC 8410).
+const SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+/// An Ed25519 private key for `rustls`, whose secret seed is stored in guarded memory.
+///
+/// `rustls` allows private keys to be stored outside of it (for example, in an HSM) by
+/// implementing its [`SigningKey`] trait, which this type does. Wrap it in a
+/// [`CertifiedKey`](rustls::sign::CertifiedKey) to use it from a server or client config, for
+/// example via [`SingleCertAndKey`](rustls::sign::SingleCertAndKey) and
+/// `ConfigBuilder::with_cert_resolver`. `rustls` never sees the secret: It only asks this key to
+/// sign handshake messages.
+///
+/// The 32-byte seed is kept in a [`SecureArray`]. `ed25519-dalek` can't construct its own key
+/// type in place, so each signature reconstructs an `ed25519_dalek::SigningKey` (including the
+/// expanded secret scalar) on the stack for the duration of the call. It is zeroed when it is
+/// dropped, at the end of the call, but the temporaries used internally by `ed25519-dalek` are
+/// outside of our control. This is still a much smaller window than keeping the whole key in
+/// unguarded memory for the lifetime of the config. TLS session secrets derived during the
+/// handshake remain in `rustls`' own memory.
+///
+/// Only available with the `rustls` feature enabled.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use rustls::sign::SigningKey;
+/// use rustls::SignatureScheme;
+/// use sodium_alloc::{GuardedEd25519Key, SecureArray};
+///
+/// let mut seed = SecureArray::<32>::new()?;
+/// seed.fill(0x13);
+/// let key = GuardedEd25519Key::from_seed(seed);
+///
+/// let signer = key.choose_scheme(&[SignatureScheme::ED25519]).unwrap();
+/// assert_eq!(signer.sign(b"handshake transcript").unwrap().len(), 64);
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+pub struct GuardedEd25519Key {
+    inner: Arc<Inner>,
+}
+
+/// The state shared between a key and the signers it hands out.
+struct Inner {
+    seed: SecureArray<32>,
+    /// The public key, encoded as a `SubjectPublicKeyInfo`. This isn't secret.
+    spki: [u8; 44],
+}
+
+impl GuardedEd25519Key {
+    /// Create a key from a 32-byte Ed25519 seed, which is already stored in guarded memory.
+    pub fn from_seed(seed: SecureArray<32>) -> Self {
+        let public = with_dalek_key(&seed, |key| key.verifying_key().to_bytes());
+        let mut spki = [0; 44];
+        spki[..12].copy_from_slice(&SPKI_PREFIX);
+        spki[12..].copy_from_slice(&public);
+
+        Self {
+            inner: Arc::new(Inner { seed, spki }),
+        }
+    }
+
+    /// Copy `seed` into guarded memory, then securely zero `seed`, and create a key from it.
+    ///
+    /// Returns [`AllocError`] if the memory could not be allocated.
+    pub fn from_seed_wiping(seed: &mut [u8; 32]) -> Result<Self, AllocError> {
+        let mut guarded = SecureArray::new()?;
+        guarded.copy_from_slice(seed);
+        memzero(seed);
+
+        Ok(Self::from_seed(guarded))
+    }
+
+    /// Returns the 32-byte Ed25519 public key.
+    pub fn public_key_bytes(&self) -> [u8; 32] {
+        let mut public = [0; 32];
+        public.copy_from_slice(&self.inner.spki[12..]);
+        public
+    }
+}
+
+impl SigningKey for GuardedEd25519Key {
+    fn choose_scheme(&self, offered: &[SignatureScheme]) -> Option<Box<dyn Signer>> {
+        if offered.contains(&SignatureScheme::ED25519) {
+            Some(Box::new(Ed25519Signer(Arc::clone(&self.inner))))
+        } else {
+            None
+        }
+    }
+
+    fn public_key(&self) -> Option<SubjectPublicKeyInfoDer<'_>> {
+        Some(SubjectPublicKeyInfoDer::from(&self.inner.spki[..]))
+    }
+
+    fn algorithm(&self) -> SignatureAlgorithm {
+        SignatureAlgorithm::ED25519
+    }
+}
+
+/// A [`Signer`] handed out by a [`GuardedEd25519Key`].
+pub(crate) struct Ed25519Signer(Arc<Inner>);
+
+impl Signer for Ed25519Signer {
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        let signature = with_dalek_key(&self.0.seed, |key| key.sign(message));
+        Ok(signature.to_bytes().to_vec())
+    }
+
+    fn scheme(&self) -> SignatureScheme {
+        SignatureScheme::ED25519
+    }
+}
+
+/// Reconstruct the `ed25519-dalek` key for `seed` for the duration of `f`.
+///
+/// The key is zeroed when it is dropped, as `ed25519-dalek`'s `zeroize` feature is enabled.
+fn with_dalek_key<R>(seed: &[u8; 32], f: impl FnOnce(&ed25519_dalek::SigningKey) -> R) -> R {
+    let key = ed25519_dalek::SigningKey::from_bytes(seed);
+    f(&key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+
+    #[test]
+    fn signatures_verify() -> Result<(), AllocError> {
+        let mut seed = [0x42; 32];
+        let key = GuardedEd25519Key::from_seed_wiping(&mut seed)?;
+        assert_eq!(seed, [0; 32]);
+
+        let expected = ed25519_dalek::SigningKey::from_bytes(&[0x42; 32]).verifying_key();
+        assert_eq!(key.public_key_bytes(), expected.to_bytes());
+        assert_eq!(key.algorithm(), SignatureAlgorithm::ED25519);
+
+        let signer = key.choose_scheme(&[SignatureScheme::ED25519]).unwrap();
+        assert_eq!(signer.scheme(), SignatureScheme::ED25519);
+        let signature = signer.sign(b"transcript").unwrap();
+        let signature = Signature::from_slice(&signature).unwrap();
+        let verifying = VerifyingKey::from_bytes(&key.public_key_bytes()).unwrap();
+        assert!(verifying.verify(b"transcript", &signature).is_ok());
+
+        // The signer keeps the seed alive after the key is dropped.
+        drop(key);
+        assert_eq!(signer.sign(b"transcript").unwrap().len(), 64);
+
+        Ok(())
+    }
+
+    #[test]
+    fn other_schemes_declined() -> Result<(), AllocError> {
+        let key = GuardedEd25519Key::from_seed(SecureArray::new()?);
+        let offered = [
+            SignatureScheme::ECDSA_NISTP256_SHA256,
+            SignatureScheme::RSA_PSS_SHA256,
+        ];
+        assert!(key.choose_scheme(&offered).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn public_key_der() -> Result<(), AllocError> {
+        let key = GuardedEd25519Key::from_seed(SecureArray::new()?);
+        let spki = key.public_key().unwrap();
+        assert_eq!(spki.len(), 44);
+        assert_eq!(&spki[..12], &SPKI_PREFIX);
+        assert_eq!(&spki[12..], &key.public_key_bytes());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,467 @@
+//! Tracking of live allocations.
+//!
+//! Only available with the `track-allocations` feature enabled. Every allocation made by
+//! [`SodiumAllocator`](crate::SodiumAllocator) is recorded in a global registry until it is
+//! freed, which can be inspected using [`stats`] to detect leaked secrets.
+//!
+//! With the `debug-backtrace` feature also enabled, a backtrace is captured for every allocation
+//! in debug builds, and included in the [`AllocStats`] report. Capturing a backtrace is very
+//! expensive, so this should only be used while hunting down a leak.
+
+use crate::malloc_size;
+use crate::page::allocation_footprint;
+use std::alloc::Layout;
+#[cfg(feature = "debug-backtrace")]
+use std::backtrace::Backtrace;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ptr::NonNull;
+use std::sync::{Mutex, MutexGuard};
+
+/// Information recorded about a single live allocation.
+struct Record {
+    layout: Layout,
+    size: usize,
+    tag: Option<&'static str>,
+    #[cfg(feature = "debug-backtrace")]
+    backtrace: Option<Backtrace>,
+}
+
+/// Registry of live allocations.
+struct Registry {
+    /// The live allocations, indexed by address.
+    records: BTreeMap<usize, Record>,
+    /// The total size of the live allocations, in bytes.
+    live_bytes: usize,
+    /// The largest value `live_bytes` has reached since the peak was last reset.
+    peak_bytes: usize,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    records: BTreeMap::new(),
+    live_bytes: 0,
+    peak_bytes: 0,
+});
+
+/// Lock the registry.
+///
+/// The registry is always left in a consistent state, so it's fine to continue using it if
+/// another thread panicked while holding the lock.
+fn registry() -> MutexGuard<'static, Registry> {
+    REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Record that `ptr` has been allocated for `layout`, optionally associated with `tag`.
+pub(crate) fn record_allocation(ptr: NonNull<[u8]>, layout: Layout, tag: Option<&'static str>) {
+    let record = Record {
+        layout,
+        size: ptr.len(),
+        tag,
+        // Only capture a backtrace in debug builds: This is far too slow for production use.
+        #[cfg(feature = "debug-backtrace")]
+        backtrace: cfg!(debug_assertions).then(Backtrace::force_capture),
+    };
+    let mut registry = registry();
+    registry.live_bytes += record.size;
+    registry.peak_bytes = registry.peak_bytes.max(registry.live_bytes);
+    registry
+        .records
+        .insert(ptr.cast::<u8>().as_ptr() as usize, record);
+}
+
+/// Record that `ptr` has been freed with the given `layout`.
+///
+/// In debug builds, this panics if `ptr` is not a live allocation, or if `layout` doesn't fit the
+/// allocation, as required by the [`Allocator`](std::alloc::Allocator) contract. The layout must
+/// have the same alignment as the layout used to allocate `ptr`, and a size between the requested
+/// size and the size of the block which was actually allocated.
+pub(crate) fn record_deallocation(ptr: NonNull<u8>, layout: Layout) {
+    // Release the lock before any assertions can panic.
+    let record = {
+        let mut registry = registry();
+        let record = registry.records.remove(&(ptr.as_ptr() as usize));
+        if let Some(record) = &record {
+            registry.live_bytes -= record.size;
+        }
+        record
+    };
+
+    if cfg!(debug_assertions) {
+        let record = record.unwrap_or_else(|| {
+            panic!("deallocating {:p}, which is not a live allocation", ptr);
+        });
+        let fits = layout.align() == record.layout.align()
+            && (record.layout.size()..=record.size).contains(&layout.size());
+        assert!(
+            fits,
+            "deallocating {:p} with {:?}, but it was allocated with {:?}",
+            ptr, layout, record.layout
+        );
+    }
+}
+
+/// Returns whether `ptr` is the start of a live allocation.
+#[cfg(test)]
+pub(crate) fn is_live<T>(ptr: *const T) -> bool {
+    registry().records.contains_key(&(ptr as usize))
+}
+
+/// Get the total number of bytes of memory held for the live allocation starting at `ptr`.
+///
+/// Sodium allocates whole pages of memory: The requested size (plus a 16 byte canary) is rounded
+/// up to a multiple of the page size, and three further pages are reserved for guard pages and
+/// bookkeeping. This function returns the total size of all of these pages, which is likely to be
+/// much larger than the size requested for small allocations.
+///
+/// Returns `None` if `ptr` is not the start of a live allocation made by
+/// [`SodiumAllocator`](crate::SodiumAllocator).
+///
+/// Only available with the `track-allocations` feature enabled.
+pub fn allocation_size(ptr: *const u8) -> Option<usize> {
+    registry()
+        .records
+        .get(&(ptr as usize))
+        .map(|record| allocation_footprint(malloc_size(record.layout).unwrap_or(record.size)))
+}
+
+/// Statistics about the live allocations with a given tag.
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct TagStats {
+    /// The number of live allocations.
+    pub live_allocations: usize,
+
+    /// The total size of the live allocations, in bytes.
+    pub live_bytes: usize,
+}
+
+/// A live allocation, and the backtrace of the code which allocated it.
+///
+/// Only available with the `debug-backtrace` feature enabled.
+#[cfg(feature = "debug-backtrace")]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct LiveAllocation {
+    /// The tag the allocation was made with, if any.
+    pub tag: Option<&'static str>,
+
+    /// The size of the allocation, in bytes.
+    pub size: usize,
+
+    /// The backtrace captured when the allocation was made.
+    pub backtrace: String,
+}
+
+/// Statistics about the allocations made by [`SodiumAllocator`](crate::SodiumAllocator).
+///
+/// The [`Display`](fmt::Display) implementation produces a human-readable report, suitable for
+/// logging when shutting down to detect leaks.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AllocStats {
+    /// Statistics for all live allocations.
+    pub total: TagStats,
+
+    /// Statistics for the live allocations made with each tag.
+    ///
+    /// Allocations made without a tag (i.e: via [`Allocator::allocate`](std::alloc::Allocator))
+    /// are grouped under `None`. Tags with no live allocations are not included.
+    pub by_tag: BTreeMap<Option<&'static str>, TagStats>,
+
+    /// Every live allocation for which a backtrace was captured.
+    ///
+    /// Backtraces are only captured in debug builds, so this is always empty in release builds.
+    ///
+    /// Only available with the `debug-backtrace` feature enabled.
+    #[cfg(feature = "debug-backtrace")]
+    pub allocations: Vec<LiveAllocation>,
+
+    /// The largest total size of live allocations at any point since the program started, or
+    /// since the peak was last reset using [`SodiumAllocator::reset_peak`], in bytes.
+    ///
+    /// [`SodiumAllocator::reset_peak`]: crate::SodiumAllocator::reset_peak
+    pub peak_bytes: usize,
+}
+
+impl AllocStats {
+    /// Take a snapshot of the current statistics.
+    ///
+    /// This is equivalent to [`stats`]. Every field is read at the same point in time, so the
+    /// snapshot is consistent even if other threads are allocating concurrently: For example,
+    /// `peak_bytes` is always at least `total.live_bytes`. Snapshots taken before and after a
+    /// phase of a program can be compared to profile its memory usage.
+    pub fn snapshot() -> Self {
+        stats()
+    }
+}
+
+impl fmt::Display for AllocStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} live allocation(s), {} byte(s)",
+            self.total.live_allocations, self.total.live_bytes
+        )?;
+        writeln!(f, "peak: {} byte(s)", self.peak_bytes)?;
+        for (tag, stats) in &self.by_tag {
+            writeln!(
+                f,
+                "  {}: {} live allocation(s), {} byte(s)",
+                tag.unwrap_or("<untagged>"),
+                stats.live_allocations,
+                stats.live_bytes
+            )?;
+        }
+        #[cfg(feature = "debug-backtrace")]
+        for allocation in &self.allocations {
+            writeln!(
+                f,
+                "  {} byte(s) allocated with tag {} at:",
+                allocation.size,
+                allocation.tag.unwrap_or("<untagged>"),
+            )?;
+            for line in allocation.backtrace.lines() {
+                writeln!(f, "    {}", line)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Get statistics about the currently live allocations.
+///
+/// Only available with the `track-allocations` feature enabled.
+pub fn stats() -> AllocStats {
+    let registry = registry();
+    let mut stats = AllocStats {
+        peak_bytes: registry.peak_bytes,
+        ..AllocStats::default()
+    };
+
+    for record in registry.records.values() {
+        for s in [
+            &mut stats.total,
+            stats.by_tag.entry(record.tag).or_default(),
+        ] {
+            s.live_allocations += 1;
+            s.live_bytes += record.size;
+        }
+
+        #[cfg(feature = "debug-backtrace")]
+        if let Some(backtrace) = &record.backtrace {
+            stats.allocations.push(LiveAllocation {
+                tag: record.tag,
+                size: record.size,
+                backtrace: backtrace.to_string(),
+            });
+        }
+    }
+
+    stats
+}
+
+/// Check the canary of every live allocation, returning the address and tag of each allocation
+/// whose canary has been modified.
+///
+/// The registry is locked while the canaries are checked. Allocations are removed from the
+/// registry before they are freed, so every allocation checked remains live until the check is
+/// complete.
+#[cfg(feature = "watchdog")]
+pub(crate) fn corrupted_allocations() -> Vec<(usize, Option<&'static str>)> {
+    let registry = registry();
+    registry
+        .records
+        .iter()
+        .filter(|(&addr, record)| {
+            // SAFETY: `addr` is a live allocation made by `sodium_allocate` for `record.layout`,
+            // and can't be freed while we hold the lock.
+            unsafe {
+                let ptr = NonNull::new_unchecked(addr as *mut u8);
+                !crate::verify_integrity(crate::sodium_base(ptr, record.layout))
+            }
+        })
+        .map(|(&addr, record)| (addr, record.tag))
+        .collect()
+}
+
+/// Reset the peak recorded in [`AllocStats::peak_bytes`] to the current total size of live
+/// allocations.
+pub(crate) fn reset_peak() {
+    let mut registry = registry();
+    registry.peak_bytes = registry.live_bytes;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{page_size, SodiumAllocator};
+    use std::alloc::Allocator;
+    use std::error::Error;
+
+    // Other tests may be allocating concurrently, so these tests only make assertions about
+    // allocations with tags unique to each test.
+
+    #[test]
+    fn tagged_allocations_reported() -> Result<(), Box<dyn Error>> {
+        let tag = "tracking::tests::tagged_allocations_reported";
+        let layout = Layout::from_size_align(32, 1)?;
+
+        let ptrs = (0..3)
+            .map(|_| SodiumAllocator.tagged_allocate(layout, tag))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let report = stats();
+        let tag_stats = report.by_tag[&Some(tag)];
+        assert_eq!(tag_stats.live_allocations, 3);
+        assert_eq!(tag_stats.live_bytes, 96);
+        assert!(report.total.live_allocations >= 3);
+        assert!(report.to_string().contains(tag));
+
+        for ptr in ptrs {
+            unsafe { SodiumAllocator.deallocate(ptr.cast(), layout) };
+        }
+
+        assert!(!stats().by_tag.contains_key(&Some(tag)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn allocation_size_page_rounded() -> Result<(), Box<dyn Error>> {
+        let layout = Layout::from_size_align(1, 1)?;
+        let ptr = SodiumAllocator.allocate(layout)?.cast::<u8>();
+
+        let size = allocation_size(ptr.as_ptr()).unwrap();
+        assert_eq!(size % page_size(), 0);
+        assert!(size > page_size());
+
+        unsafe { SodiumAllocator.deallocate(ptr, layout) };
+        assert_eq!(allocation_size(ptr.as_ptr()), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn deallocate_fitting_layout() -> Result<(), Box<dyn Error>> {
+        // The layout passed to `deallocate` may have any size between the requested size and the
+        // size which was actually allocated.
+        let layout = Layout::from_size_align(13, 4)?;
+        let ptr = SodiumAllocator.allocate(layout)?;
+        assert_eq!(ptr.len(), 16);
+        unsafe { SodiumAllocator.deallocate(ptr.cast(), Layout::from_size_align(16, 4)?) };
+
+        let ptr = SodiumAllocator.allocate(layout)?;
+        unsafe { SodiumAllocator.deallocate(ptr.cast(), layout) };
+
+        Ok(())
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "but it was allocated with")]
+    fn deallocate_mismatched_size() {
+        let ptr = SodiumAllocator
+            .allocate(Layout::from_size_align(32, 1).unwrap())
+            .unwrap();
+        unsafe { SodiumAllocator.deallocate(ptr.cast(), Layout::from_size_align(64, 1).unwrap()) };
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "but it was allocated with")]
+    fn deallocate_mismatched_align() {
+        let ptr = SodiumAllocator
+            .allocate(Layout::from_size_align(32, 8).unwrap())
+            .unwrap();
+        unsafe { SodiumAllocator.deallocate(ptr.cast(), Layout::from_size_align(32, 4).unwrap()) };
+    }
+
+    /// Make an allocation with `tag`, which will show up in its backtrace.
+    #[cfg(feature = "debug-backtrace")]
+    #[inline(never)]
+    fn allocate_for_backtrace(layout: Layout, tag: &'static str) -> NonNull<[u8]> {
+        SodiumAllocator.tagged_allocate(layout, tag).unwrap()
+    }
+
+    #[cfg(all(feature = "debug-backtrace", debug_assertions))]
+    #[test]
+    fn leak_report_includes_backtrace() -> Result<(), Box<dyn Error>> {
+        let tag = "tracking::tests::leak_report_includes_backtrace";
+        let layout = Layout::from_size_align(24, 1)?;
+        let ptr = allocate_for_backtrace(layout, tag);
+
+        let report = stats();
+        let allocation = report
+            .allocations
+            .iter()
+            .find(|a| a.tag == Some(tag))
+            .unwrap();
+        assert_eq!(allocation.size, 24);
+        assert!(allocation.backtrace.contains("allocate_for_backtrace"));
+        assert!(report.to_string().contains("allocate_for_backtrace"));
+
+        unsafe { SodiumAllocator.deallocate(ptr.cast(), layout) };
+        assert!(!stats().allocations.iter().any(|a| a.tag == Some(tag)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn tags_grouped_separately() -> Result<(), Box<dyn Error>> {
+        let tag_a = "tracking::tests::tags_grouped_separately::a";
+        let tag_b = "tracking::tests::tags_grouped_separately::b";
+        let layout_a = Layout::from_size_align(8, 1)?;
+        let layout_b = Layout::from_size_align(64, 1)?;
+
+        let ptr_a = SodiumAllocator.tagged_allocate(layout_a, tag_a)?;
+        let ptr_b = SodiumAllocator.tagged_allocate(layout_b, tag_b)?;
+
+        let report = stats();
+        assert_eq!(
+            report.by_tag[&Some(tag_a)],
+            TagStats {
+                live_allocations: 1,
+                live_bytes: 8
+            }
+        );
+        assert_eq!(
+            report.by_tag[&Some(tag_b)],
+            TagStats {
+                live_allocations: 1,
+                live_bytes: 64
+            }
+        );
+
+        unsafe {
+            SodiumAllocator.deallocate(ptr_a.cast(), layout_a);
+            SodiumAllocator.deallocate(ptr_b.cast(), layout_b);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn peak_reset() -> Result<(), Box<dyn Error>> {
+        let tag = "tracking::tests::peak_reset";
+        let layout = Layout::from_size_align(1 << 20, 1)?;
+
+        // Other tests allocate and free memory concurrently, so we can only assert what holds
+        // regardless of their allocations. This is the only test which resets the peak, so until
+        // it does, the peak never decreases.
+        let ptr = SodiumAllocator.tagged_allocate(layout, tag)?;
+        let during = AllocStats::snapshot();
+        assert!(during.peak_bytes >= during.total.live_bytes);
+        assert!(during.peak_bytes >= layout.size());
+        unsafe { SodiumAllocator.deallocate(ptr.cast(), layout) };
+
+        // Freeing memory doesn't affect the peak
+        let after = AllocStats::snapshot();
+        assert!(after.peak_bytes >= during.peak_bytes);
+        assert!(after.peak_bytes >= after.total.live_bytes);
+
+        // Resetting the peak keeps it consistent with the live allocations
+        SodiumAllocator::reset_peak();
+        let reset = AllocStats::snapshot();
+        assert!(reset.peak_bytes >= reset.total.live_bytes);
+
+        Ok(())
+    }
+}
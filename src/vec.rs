@@ -0,0 +1,1433 @@
+//! Helpers for working with [`Vec`]s whose memory is managed by [`SodiumAllocator`].
+
+use crate::ops::volatile_copy;
+use crate::page::{round_to_page, CANARY_SIZE};
+use crate::{memzero, SodiumAllocator};
+use std::alloc::{handle_alloc_error, AllocError, Allocator, Layout};
+use std::iter::FusedIterator;
+use std::ops::{Bound, Deref, DerefMut, Range, RangeBounds};
+
+/// Create a new, empty `Vec` with space for at least `capacity` bytes, allocated using Sodium.
+///
+/// Unlike [`Vec::with_capacity_in`], this function writes to the entire allocated capacity before
+/// returning, forcing the operating system to fault in every page of the allocation. This moves
+/// the cost of the page faults to the point of allocation, rather than the first time each page
+/// is written to, which can be useful in latency-sensitive code.
+///
+/// The capacity is filled with zeroes, and the returned `Vec` has a length of 0.
+///
+/// Returns [`AllocError`] if the memory could not be allocated.
+pub fn secure_vec_with_capacity(capacity: usize) -> Result<Vec<u8, SodiumAllocator>, AllocError> {
+    let mut vec: Vec<u8, _> = Vec::new_in(SodiumAllocator);
+    vec.try_reserve_exact(capacity).map_err(|_| AllocError)?;
+
+    // SAFETY: The `Vec` has just allocated space for `vec.capacity()` bytes, so the pointer is
+    // valid for writes of this many bytes. `u8` has no invalid bit patterns, and we don't change
+    // the length of the `Vec`, so the (now initialised) spare capacity is still treated as
+    // uninitialised by the `Vec`.
+    unsafe {
+        vec.as_mut_ptr().write_bytes(0, vec.capacity());
+    }
+
+    Ok(vec)
+}
+
+/// Concatenate `parts` into a single new `Vec`, allocated using Sodium.
+///
+/// The total length is calculated up front, so the result is built in a single allocation of
+/// exactly the right size, and is never reallocated (which would leave partial copies of the
+/// secret in freed memory until it is zeroed).
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::secure_concat;
+///
+/// let input = secure_concat(&[b"salt", b"password", b"pepper"])?;
+/// assert_eq!(&input[..], b"saltpasswordpepper");
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+///
+/// Returns [`AllocError`] if the total length overflows, or the memory could not be allocated.
+pub fn secure_concat(parts: &[&[u8]]) -> Result<Vec<u8, SodiumAllocator>, AllocError> {
+    let len = parts
+        .iter()
+        .try_fold(0usize, |len, part| len.checked_add(part.len()))
+        .ok_or(AllocError)?;
+
+    let mut vec = Vec::new_in(SodiumAllocator);
+    vec.try_reserve_exact(len).map_err(|_| AllocError)?;
+    for part in parts {
+        vec.extend_from_slice(part);
+    }
+
+    Ok(vec)
+}
+
+/// Combine several inputs into an accumulator stored in guarded memory.
+///
+/// A zeroed accumulator of `len` bytes is allocated using Sodium, and `f` is called with the
+/// accumulator and each input in turn, to fold the input into it. The accumulator is returned once
+/// every input has been combined. This keeps intermediate values (such as a partially combined
+/// key) in guarded memory throughout, rather than in a temporary buffer on the stack.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::secure_fold;
+///
+/// // Recombine a secret split into XOR shares
+/// let shares: [&[u8]; 3] = [&[0x0f, 0xf0], &[0x33, 0x33], &[0x55, 0xaa]];
+/// let secret = secure_fold(2, shares, |acc, share| {
+///     acc.iter_mut().zip(share).for_each(|(a, s)| *a ^= s);
+/// })?;
+/// assert_eq!(&secret[..], &[0x0f ^ 0x33 ^ 0x55, 0xf0 ^ 0x33 ^ 0xaa]);
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+///
+/// Returns [`AllocError`] if the memory could not be allocated.
+pub fn secure_fold<'a, I, F>(
+    len: usize,
+    inputs: I,
+    mut f: F,
+) -> Result<Vec<u8, SodiumAllocator>, AllocError>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+    F: FnMut(&mut [u8], &[u8]),
+{
+    let mut acc = secure_vec_with_capacity(len)?;
+    // The capacity has already been reserved, so this never reallocates.
+    acc.resize(len, 0);
+    for input in inputs {
+        f(&mut acc, input);
+    }
+
+    Ok(acc)
+}
+
+/// Call `f` with a zeroed, temporary buffer of `len` bytes in guarded memory, returning its
+/// result.
+///
+/// This is intended for scratch space holding intermediate secrets (such as the output of a key
+/// exchange, before it is passed through a KDF), which shouldn't outlive a single operation. The
+/// buffer is zeroed and freed by Sodium once `f` returns, or if `f` panics: The buffer is owned by
+/// a value which is dropped while unwinding, so the secret is wiped before the panic propagates
+/// any further.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::with_guarded_buffer;
+///
+/// let checksum = with_guarded_buffer(32, |shared_secret| {
+///     shared_secret.fill(0x42);
+///     shared_secret.iter().fold(0u8, |acc, &b| acc ^ b)
+/// })?;
+/// assert_eq!(checksum, 0);
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+///
+/// Returns [`AllocError`] if the memory could not be allocated, in which case `f` isn't called.
+pub fn with_guarded_buffer<R, F: FnOnce(&mut [u8]) -> R>(
+    len: usize,
+    f: F,
+) -> Result<R, AllocError> {
+    let mut buf = secure_vec_with_capacity(len)?;
+    // The capacity has already been reserved, so this never reallocates.
+    buf.resize(len, 0);
+
+    Ok(f(&mut buf))
+}
+
+/// Allocate `len` bytes of guarded memory, filled with random bytes from `rng`.
+///
+/// This allows keys to be generated using any cryptographically secure random number generator
+/// implementing [`rand_core::CryptoRng`], rather than only libsodium's, while still writing the
+/// output directly into guarded memory. The buffer is zeroed before it is passed to
+/// [`RngCore::fill_bytes`](rand_core::RngCore::fill_bytes), so it never holds Sodium's garbage
+/// fill, even if `rng` panics.
+///
+/// Only available with the `rand` feature enabled.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use rand_core::OsRng;
+/// use sodium_alloc::fill_secure_from_rng;
+///
+/// let key = fill_secure_from_rng(&mut OsRng, 32)?;
+/// assert_eq!(key.len(), 32);
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+///
+/// Returns [`AllocError`] if the memory could not be allocated.
+#[cfg(feature = "rand")]
+pub fn fill_secure_from_rng<R>(
+    rng: &mut R,
+    len: usize,
+) -> Result<Vec<u8, SodiumAllocator>, AllocError>
+where
+    R: rand_core::CryptoRng + rand_core::RngCore,
+{
+    let mut buf = secure_vec_with_capacity(len)?;
+    // The capacity has already been reserved, so this never reallocates.
+    buf.resize(len, 0);
+    rng.fill_bytes(&mut buf);
+
+    Ok(buf)
+}
+
+/// Copy `src` into a new `Vec` allocated using Sodium, with space for at least `capacity` bytes.
+///
+/// The `Vec` is allocated with a capacity of at least `max(src.len(), capacity)` bytes, so extra
+/// space can be reserved for data which will be appended later (e.g: an authentication tag),
+/// without needing to reallocate immediately after copying. The returned `Vec` has a length of
+/// `src.len()`.
+///
+/// Returns [`AllocError`] if the memory could not be allocated.
+pub fn secure_vec_from_slice_with_capacity(
+    src: &[u8],
+    capacity: usize,
+) -> Result<Vec<u8, SodiumAllocator>, AllocError> {
+    let mut vec = Vec::new_in(SodiumAllocator);
+    vec.try_reserve_exact(src.len().max(capacity))
+        .map_err(|_| AllocError)?;
+    vec.extend_from_slice(src);
+
+    Ok(vec)
+}
+
+/// Move the bytes of `vec` for which `pred` returns `true` into a new `Vec` allocated using
+/// Sodium, and securely zero everything else.
+///
+/// The kept bytes are copied into a single new guarded allocation of exactly the right size, in
+/// their original order. The *entire* capacity of `vec` (including the discarded bytes, and any
+/// stale data in its spare capacity) is then securely zeroed before it is freed, so no copy of a
+/// discarded secret survives the call. `vec` may use any allocator, so this can also be used to
+/// move part of an ordinary `Vec` into guarded memory.
+///
+/// ```
+/// # #![feature(allocator_api)]
+/// use sodium_alloc::partition_secure;
+///
+/// let combined = vec![1, 2, 3, 4, 5, 6];
+/// let kept = partition_secure(combined, |&b| b % 2 == 0)?;
+/// assert_eq!(&kept[..], &[2, 4, 6]);
+/// # Ok::<(), std::alloc::AllocError>(())
+/// ```
+///
+/// Returns [`AllocError`] if the memory for the kept bytes could not be allocated. `vec` is still
+/// zeroed and freed in this case.
+pub fn partition_secure<A: Allocator, F: FnMut(&u8) -> bool>(
+    vec: Vec<u8, A>,
+    mut pred: F,
+) -> Result<Vec<u8, SodiumAllocator>, AllocError> {
+    // `vec` is wiped when this is dropped, including if `pred` panics.
+    let mut vec = WipeCapacity(vec);
+    let vec = &mut vec.0;
+
+    // Move the kept bytes to the front of `vec`, so they can be copied in one go.
+    let mut kept = 0;
+    for i in 0..vec.len() {
+        if pred(&vec[i]) {
+            vec[kept] = vec[i];
+            kept += 1;
+        }
+    }
+
+    secure_copy(&vec[..kept])
+}
+
+/// Securely zeroes the entire capacity of a `Vec` when dropped, including while unwinding.
+struct WipeCapacity<A: Allocator>(Vec<u8, A>);
+
+impl<A: Allocator> Drop for WipeCapacity<A> {
+    fn drop(&mut self) {
+        let vec = &mut self.0;
+        // This never reallocates, as the new length is the existing capacity.
+        vec.resize(vec.capacity(), 0);
+        memzero(vec);
+    }
+}
+
+/// Copy `src` into a new `Vec` allocated using Sodium.
+pub(crate) fn secure_copy(src: &[u8]) -> Result<Vec<u8, SodiumAllocator>, AllocError> {
+    secure_vec_from_slice_with_capacity(src, 0)
+}
+
+/// A growable byte buffer, stored in memory allocated using [`SodiumAllocator`].
+///
+/// This is a thin wrapper around `Vec<u8, SodiumAllocator>`, which it dereferences to. It exists
+/// mainly so that ordinary `Vec`s can be converted into guarded memory using [`From`]:
+///
+/// ```
+/// use sodium_alloc::SecureVec;
+///
+/// let password = b"hunter2".to_vec();
+/// let password: SecureVec = password.into();
+/// assert_eq!(&password[..], b"hunter2");
+/// ```
+///
+/// As it dereferences to a `Vec`, all of the standard `Vec` methods are available. In particular,
+/// [`Vec::spare_capacity_mut`] and [`Vec::set_len`] can be used to fill the vector's guarded
+/// spare capacity directly (for example, from a foreign function or a `read` call), without first
+/// zeroing it or copying through an unguarded buffer:
+///
+/// ```
+/// use sodium_alloc::SecureVec;
+///
+/// let mut key = SecureVec::new();
+/// key.reserve(32);
+/// for (i, byte) in key.spare_capacity_mut()[..32].iter_mut().enumerate() {
+///     byte.write(i as u8);
+/// }
+/// // SAFETY: The first 32 bytes of the spare capacity have just been initialised.
+/// unsafe { key.set_len(32) };
+/// assert_eq!(key[31], 31);
+/// ```
+///
+/// Moving a `SecureVec` (or a `Vec<u8, SodiumAllocator>`), including swapping two of them with
+/// [`std::mem::swap`], only moves the pointer, length and capacity: The contents stay where they
+/// are, in their guarded allocations, which are simply re-owned. No secret bytes are copied, and
+/// no memory is allocated or freed.
+///
+/// # Capacity
+/// Sodium allocates whole pages of memory, so the memory a guarded vector occupies is always much
+/// larger than its capacity: Even `reserve_exact(10)` occupies a full page for the data and
+/// canary, plus the guard pages (see [`overhead_report`](crate::overhead_report)). However,
+/// [`Vec::capacity`] only ever reports the capacity which was requested, exactly as it would for
+/// a `Vec` using the global allocator. The rest of the page isn't part of the vector's capacity,
+/// and can't be used to grow it without reallocating, so code reasoning about capacity (for
+/// example, to avoid reallocations, see [`SecureVec::will_reallocate`]) should rely on
+/// `capacity()` alone.
+///
+/// The capacity is placed at the very end of its last page, immediately before the guard page
+/// which follows it, so the unused part of the page comes before the vector's buffer, not after:
+///
+/// ```
+/// use sodium_alloc::{page_offset, page_size, SecureVec};
+///
+/// let mut buf = SecureVec::new();
+/// buf.push(1);
+/// buf.reserve_exact(10);
+/// assert_eq!(buf.capacity(), 11);
+/// assert_eq!(page_offset(buf.as_ptr()), page_size() - 11);
+/// ```
+pub struct SecureVec(Vec<u8, SodiumAllocator>);
+
+impl SecureVec {
+    /// Create a new, empty `SecureVec`.
+    ///
+    /// No memory is allocated until bytes are pushed to the vector.
+    pub const fn new() -> Self {
+        Self(Vec::new_in(SodiumAllocator))
+    }
+
+    /// Unwrap the underlying `Vec`.
+    pub fn into_inner(self) -> Vec<u8, SodiumAllocator> {
+        self.0
+    }
+
+    /// Returns whether adding `additional` more bytes to this vector would require a new
+    /// allocation.
+    ///
+    /// Growing a `SecureVec` beyond its capacity is expensive: Sodium can't grow an allocation in
+    /// place, so a whole new guarded allocation must be made with `sodium_malloc`, and the
+    /// contents copied into it, before the old allocation is zeroed and freed. This happens even
+    /// if there is unused space left in the last page of the current allocation, as that space
+    /// isn't part of the vector's capacity. When this returns `true`, latency-sensitive code can
+    /// grow the vector ahead of time (for example, using [`Vec::reserve`] while idle), rather
+    /// than on the hot path.
+    ///
+    /// ```
+    /// use sodium_alloc::SecureVec;
+    ///
+    /// let mut buf = SecureVec::new();
+    /// buf.reserve_exact(16);
+    /// assert!(!buf.will_reallocate(16));
+    /// assert!(buf.will_reallocate(17));
+    /// ```
+    pub fn will_reallocate(&self, additional: usize) -> bool {
+        additional > self.capacity() - self.len()
+    }
+
+    /// Copy the contents of this vector into `dst`, using volatile reads and writes.
+    ///
+    /// Volatile accesses can't be elided or merged by the compiler, which gives exact control
+    /// over when secret bytes are read from guarded memory, and prevents them from being cached
+    /// (e.g: in registers) for longer than necessary.
+    ///
+    /// # Panics
+    /// Panics if `dst` is not the same length as this vector.
+    pub fn read_volatile_into(&self, dst: &mut [u8]) {
+        volatile_copy(dst, &self[..]);
+    }
+
+    /// Overwrite the contents of this vector with `src`, using volatile reads and writes.
+    ///
+    /// See [`SecureVec::read_volatile_into`] for details.
+    ///
+    /// # Panics
+    /// Panics if `src` is not the same length as this vector.
+    pub fn write_volatile_from(&mut self, src: &[u8]) {
+        volatile_copy(&mut self[..], src);
+    }
+}
+
+impl Default for SecureVec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for SecureVec {
+    type Target = Vec<u8, SodiumAllocator>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for SecureVec {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<A: Allocator> From<Vec<u8, A>> for SecureVec {
+    /// Move the contents of `vec` into memory allocated using Sodium.
+    ///
+    /// The contents are copied into a new guarded allocation, and then the *entire* capacity of
+    /// `vec` is securely zeroed before it is freed, so no copy of the secret is left behind in
+    /// its old memory.
+    ///
+    /// Like other infallible `Vec` operations, this aborts the program if the guarded memory could
+    /// not be allocated.
+    fn from(mut vec: Vec<u8, A>) -> Self {
+        let secure = secure_copy(&vec)
+            .unwrap_or_else(|_| handle_alloc_error(Layout::array::<u8>(vec.len()).unwrap()));
+
+        // Initialise the spare capacity so that it can be wiped along with the rest of the `Vec`.
+        // This never reallocates, as the new length is the existing capacity.
+        vec.resize(vec.capacity(), 0);
+        memzero(&mut vec);
+
+        Self(secure)
+    }
+}
+
+/// Extension methods for [`Vec`]s whose memory is managed by [`SodiumAllocator`].
+///
+/// Many of the standard `Vec` methods which reduce the length of a `Vec` leave the removed bytes
+/// in the `Vec`'s spare capacity until the whole `Vec` is freed. The methods of this trait
+/// securely zero any bytes they remove, to minimise the time secret data spends in memory.
+///
+/// Memory allocated by [`SodiumAllocator`] is always zeroed when it is *freed*, so methods which
+/// reallocate (such as growing the vector with `push` or `reserve`, or [`Vec::shrink_to_fit`])
+/// never leave copies of a secret behind in the old allocation. However, the following standard
+/// methods reduce the length of the vector *in place*, leaving stale copies of the removed (or
+/// moved) bytes in the vector's spare capacity, where they remain until the vector is freed:
+///
+/// | Standard method | Wiping variant |
+/// |-----------------|----------------|
+/// | [`Vec::truncate`], [`Vec::clear`] | [`truncate_wiping`](Self::truncate_wiping) |
+/// | [`Vec::resize`] | [`resize_wiping`](Self::resize_wiping) |
+/// | [`Vec::retain`], [`Vec::retain_mut`] | [`retain_wiping`](Self::retain_wiping) |
+/// | [`Vec::dedup`], [`Vec::dedup_by`], [`Vec::dedup_by_key`] | [`dedup_wiping`](Self::dedup_wiping) |
+/// | [`Vec::pop`] | [`pop_wiping`](Self::pop_wiping) |
+/// | [`Vec::remove`] | [`remove_wiping`](Self::remove_wiping) |
+/// | [`Vec::swap_remove`] | [`swap_remove_wiping`](Self::swap_remove_wiping) |
+/// | [`Vec::drain`] | [`drain_secure`](Self::drain_secure) |
+/// | [`Vec::split_off`] | [`split_off_wiping`](Self::split_off_wiping) |
+///
+/// Note that methods which return a removed byte (such as `pop_wiping`) necessarily return a copy
+/// of it, which is no longer in guarded memory.
+pub trait SecureVecExt {
+    /// Shorten the vector, keeping the first `len` bytes, and securely zeroing the rest.
+    ///
+    /// This has no effect if `len` is greater than or equal to the vector's current length. The
+    /// capacity of the vector is unchanged.
+    fn truncate_wiping(&mut self, len: usize);
+
+    /// Resize the vector to `new_len` bytes, securely zeroing any bytes which are removed.
+    ///
+    /// If `new_len` is greater than the current length, the vector is extended with copies of
+    /// `fill`, exactly like [`Vec::resize`]. Spare capacity is used in place if there is enough of
+    /// it, otherwise the vector is reallocated (and the old allocation is zeroed by Sodium as it is
+    /// freed). If `new_len` is less than the current length, this is equivalent to
+    /// [`truncate_wiping`](Self::truncate_wiping). The capacity is never reduced.
+    fn resize_wiping(&mut self, new_len: usize, fill: u8);
+
+    /// Retain only the bytes for which `f` returns `true`, securely zeroing the rest.
+    ///
+    /// This is equivalent to [`Vec::retain`]: The retained bytes are kept in their original
+    /// order, and moved to the front of the vector. The space at the end of the vector which is
+    /// no longer in use, which would otherwise still hold copies of the secret, is then zeroed.
+    /// The capacity of the vector is unchanged.
+    fn retain_wiping<F: FnMut(&u8) -> bool>(&mut self, f: F);
+
+    /// Remove consecutive repeated bytes, securely zeroing the space which is no longer in use.
+    ///
+    /// This is equivalent to [`Vec::dedup`], followed by zeroing the bytes between the new and
+    /// old lengths. The capacity of the vector is unchanged.
+    fn dedup_wiping(&mut self);
+
+    /// Remove the last byte from the vector and return it, or `None` if it is empty, securely
+    /// zeroing its old location.
+    fn pop_wiping(&mut self) -> Option<u8>;
+
+    /// Remove and return the byte at `index`, shifting all bytes after it to the left, and
+    /// securely zeroing the space at the end of the vector which is no longer in use.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds.
+    fn remove_wiping(&mut self, index: usize) -> u8;
+
+    /// Remove and return the byte at `index`, replacing it with the last byte of the vector, and
+    /// securely zeroing the last byte's old location.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds.
+    fn swap_remove_wiping(&mut self, index: usize) -> u8;
+
+    /// Split the vector in two at `at`, returning a new vector containing the bytes from `at`
+    /// onwards, and securely zeroing their old location.
+    ///
+    /// This is equivalent to [`Vec::split_off`]: The returned vector is a new guarded allocation,
+    /// and this vector keeps the bytes before `at`, and its capacity.
+    ///
+    /// # Panics
+    /// Panics if `at > len`.
+    fn split_off_wiping(&mut self, at: usize) -> Vec<u8, SodiumAllocator>;
+
+    /// Append the bytes of `src` to the vector, then securely zero `src`.
+    ///
+    /// This is intended for moving a secret received in unguarded memory (such as a buffer owned
+    /// by a network library) into the vector, without leaving a copy behind in the source. If
+    /// the vector needs to grow, its old allocation is freed (and so zeroed) by Sodium.
+    fn extend_from_slice_wiping(&mut self, src: &mut [u8]);
+
+    /// Remove the bytes in `range` from the vector, returning an iterator over copies of them.
+    ///
+    /// This is equivalent to [`Vec::drain`], but no copies of the drained bytes are left in the
+    /// vector's memory once the iterator is dropped, whether or not it was fully consumed: The
+    /// bytes after the range are moved down to fill it, and the space at the end of the vector
+    /// which is no longer in use is zeroed. The capacity of the vector is unchanged.
+    ///
+    /// The vector is only modified when the iterator is dropped. If the iterator is leaked (for
+    /// example, using [`mem::forget`](std::mem::forget)), the vector is left unchanged.
+    ///
+    /// # Panics
+    /// Panics if the start of the range is greater than the end, or the end is greater than the
+    /// length of the vector.
+    fn drain_secure<R: RangeBounds<usize>>(&mut self, range: R) -> DrainSecure<'_>;
+
+    /// Release the vector's unused capacity if doing so would free any memory, and otherwise
+    /// securely zero it.
+    ///
+    /// Sodium allocates whole pages, so if the vector's contents would still need the same number
+    /// of pages after shrinking, reallocating would free no memory. In this case, the capacity is
+    /// kept unchanged, along with the vector's buffer, and the spare capacity (which may hold stale
+    /// copies of a secret, see the table above) is zeroed. Otherwise, this falls back to
+    /// [`Vec::shrink_to_fit`], which moves the contents into a new, smaller allocation, and frees
+    /// the old allocation, which Sodium zeroes in its entirety. Either way, no bytes outside of
+    /// the vector's length remain in memory afterwards.
+    fn shrink_to_fit_wiping(&mut self);
+
+    /// Returns the range of addresses which make up the vector's allocation, including any spare
+    /// capacity.
+    ///
+    /// This is intended for checking that unsafe code (such as a foreign function writing into
+    /// the vector's buffer) stays within bounds. Unlike [`slice::as_ptr_range`], the range covers
+    /// the whole capacity, not just the initialised length. For a vector allocated using
+    /// [`secure_vec_with_capacity`], the end of the range is immediately followed by a guard page.
+    fn ptr_range(&self) -> Range<*const u8>;
+
+    /// Returns whether `ptr` points to a byte within the vector's allocation.
+    ///
+    /// This is `true` for any address within [`SecureVecExt::ptr_range`], and `false` otherwise,
+    /// including for the address one past the end.
+    fn contains_ptr(&self, ptr: *const u8) -> bool;
+}
+
+impl SecureVecExt for Vec<u8, SodiumAllocator> {
+    fn truncate_wiping(&mut self, len: usize) {
+        if len < self.len() {
+            memzero(&mut self[len..]);
+            self.truncate(len);
+        }
+    }
+
+    fn resize_wiping(&mut self, new_len: usize, fill: u8) {
+        if new_len < self.len() {
+            self.truncate_wiping(new_len);
+        } else {
+            self.resize(new_len, fill);
+        }
+    }
+
+    fn retain_wiping<F: FnMut(&u8) -> bool>(&mut self, mut f: F) {
+        let mut kept = 0;
+        for i in 0..self.len() {
+            if f(&self[i]) {
+                self[kept] = self[i];
+                kept += 1;
+            }
+        }
+
+        self.truncate_wiping(kept);
+    }
+
+    fn dedup_wiping(&mut self) {
+        let old_len = self.len();
+        self.dedup();
+        wipe_vacated(self, old_len);
+    }
+
+    fn pop_wiping(&mut self) -> Option<u8> {
+        let old_len = self.len();
+        let byte = self.pop();
+        wipe_vacated(self, old_len);
+        byte
+    }
+
+    fn remove_wiping(&mut self, index: usize) -> u8 {
+        let old_len = self.len();
+        let byte = self.remove(index);
+        wipe_vacated(self, old_len);
+        byte
+    }
+
+    fn swap_remove_wiping(&mut self, index: usize) -> u8 {
+        let old_len = self.len();
+        let byte = self.swap_remove(index);
+        wipe_vacated(self, old_len);
+        byte
+    }
+
+    fn split_off_wiping(&mut self, at: usize) -> Vec<u8, SodiumAllocator> {
+        let old_len = self.len();
+        let tail = self.split_off(at);
+        wipe_vacated(self, old_len);
+        tail
+    }
+
+    fn extend_from_slice_wiping(&mut self, src: &mut [u8]) {
+        self.extend_from_slice(src);
+        memzero(src);
+    }
+
+    fn drain_secure<R: RangeBounds<usize>>(&mut self, range: R) -> DrainSecure<'_> {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.checked_add(1).expect("range start overflows"),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end.checked_add(1).expect("range end overflows"),
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.len(),
+        };
+        assert!(start <= end, "drain start {} > end {}", start, end);
+        assert!(end <= self.len(), "drain end {} > len {}", end, self.len());
+
+        DrainSecure {
+            vec: self,
+            drained: start..end,
+            remaining: start..end,
+        }
+    }
+
+    fn shrink_to_fit_wiping(&mut self) {
+        let pages = |size: usize| round_to_page(size + CANARY_SIZE);
+        // An empty vector can free its allocation entirely.
+        if self.is_empty() || pages(self.len()) < pages(self.capacity()) {
+            self.shrink_to_fit();
+            return;
+        }
+
+        let len = self.len();
+        // Initialise the spare capacity so that it can be wiped. This never reallocates, as the
+        // new length is the existing capacity.
+        self.resize(self.capacity(), 0);
+        self.truncate_wiping(len);
+    }
+
+    fn ptr_range(&self) -> Range<*const u8> {
+        let start = self.as_ptr();
+        // SAFETY: The allocation is `capacity` bytes long, so this is at most one past the end.
+        start..unsafe { start.add(self.capacity()) }
+    }
+
+    fn contains_ptr(&self, ptr: *const u8) -> bool {
+        self.ptr_range().contains(&ptr)
+    }
+}
+
+/// An iterator over the bytes removed from a vector by [`SecureVecExt::drain_secure`].
+///
+/// Each byte is returned by value, as a copy which is no longer in guarded memory. When the
+/// iterator is dropped, the drained range is removed from the vector, and the space it vacated is
+/// securely zeroed, including any bytes which were never returned.
+pub struct DrainSecure<'a> {
+    vec: &'a mut Vec<u8, SodiumAllocator>,
+    /// The range being drained.
+    drained: Range<usize>,
+    /// The part of the drained range which hasn't been returned yet.
+    remaining: Range<usize>,
+}
+
+impl DrainSecure<'_> {
+    /// Returns the bytes which haven't been returned by the iterator yet.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.vec[self.remaining.clone()]
+    }
+}
+
+impl Iterator for DrainSecure<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        self.remaining.next().map(|i| self.vec[i])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.remaining.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for DrainSecure<'_> {
+    fn next_back(&mut self) -> Option<u8> {
+        self.remaining.next_back().map(|i| self.vec[i])
+    }
+}
+
+impl ExactSizeIterator for DrainSecure<'_> {}
+
+impl FusedIterator for DrainSecure<'_> {}
+
+impl Drop for DrainSecure<'_> {
+    fn drop(&mut self) {
+        let Range { start, end } = self.drained;
+        let old_len = self.vec.len();
+        // Move the tail down over the drained bytes, then zero the stale copies left at the end.
+        self.vec.copy_within(end.., start);
+        self.vec.truncate_wiping(old_len - (end - start));
+    }
+}
+
+/// Securely zero the bytes of `vec` between its current length and `old_len`, which were vacated
+/// by an operation which reduced its length in place.
+fn wipe_vacated(vec: &mut Vec<u8, SodiumAllocator>, old_len: usize) {
+    let len = vec.len();
+    // SAFETY: The bytes up to `old_len` were initialised before the length was reduced, and
+    // reducing the length of a `Vec<u8>` doesn't deinitialise or free them.
+    unsafe { vec.set_len(old_len) };
+    vec.truncate_wiping(len);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::Global;
+    use std::cell::RefCell;
+    use std::ptr::NonNull;
+    use std::rc::Rc;
+
+    /// Get the contents of the entire capacity of `vec`, including any spare capacity.
+    ///
+    /// # Safety
+    /// The entire capacity of `vec` must have been initialised.
+    unsafe fn capacity_contents(vec: &Vec<u8, SodiumAllocator>) -> &[u8] {
+        std::slice::from_raw_parts(vec.as_ptr(), vec.capacity())
+    }
+
+    #[test]
+    fn with_capacity_prefaulted() -> Result<(), AllocError> {
+        // Several pages worth of memory, should be enough to span multiple pages on any platform
+        let capacity = 1 << 18;
+        let mut vec = secure_vec_with_capacity(capacity)?;
+
+        assert!(vec.capacity() >= capacity);
+        assert!(vec.is_empty());
+
+        let ptr = vec.as_ptr();
+        for i in 0..capacity {
+            vec.push(i as u8);
+        }
+
+        // Writes within the requested capacity should never have required a reallocation
+        assert_eq!(vec.as_ptr(), ptr);
+        assert!(vec.iter().enumerate().all(|(i, &b)| b == i as u8));
+
+        Ok(())
+    }
+
+    #[test]
+    fn with_capacity_zero() -> Result<(), AllocError> {
+        let vec = secure_vec_with_capacity(0)?;
+        assert!(vec.is_empty());
+
+        Ok(())
+    }
+
+    /// An allocator which wraps the global allocator, saving the contents of each allocation
+    /// when it is freed.
+    #[derive(Clone, Default)]
+    struct InspectingAllocator(Rc<RefCell<Vec<Vec<u8>>>>);
+
+    unsafe impl Allocator for InspectingAllocator {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            let contents = std::slice::from_raw_parts(ptr.as_ptr(), layout.size());
+            self.0.borrow_mut().push(contents.to_vec());
+            Global.deallocate(ptr, layout);
+        }
+    }
+
+    #[test]
+    fn from_vec_wipes_source() {
+        let alloc = InspectingAllocator::default();
+        let mut vec = Vec::with_capacity_in(64, alloc.clone());
+        vec.extend_from_slice(&[0xff; 64]);
+        // Leave some secret data in the spare capacity too
+        vec.truncate(32);
+
+        let secure = SecureVec::from(vec);
+        assert_eq!(&secure[..], &[0xff; 32]);
+
+        let freed = alloc.0.borrow();
+        assert_eq!(freed.len(), 1);
+        assert_eq!(freed[0].len(), 64);
+        assert!(freed[0].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn volatile_round_trip() {
+        let mut vec = SecureVec::new();
+        vec.resize(64, 0);
+        let data: Vec<u8> = (0..64).collect();
+        vec.write_volatile_from(&data);
+        assert_eq!(&vec[..], &data[..]);
+
+        let mut out = [0; 64];
+        vec.read_volatile_into(&mut out);
+        assert_eq!(&out[..], &data[..]);
+    }
+
+    #[test]
+    fn will_reallocate() {
+        let mut vec = SecureVec::new();
+        assert!(!vec.will_reallocate(0));
+        assert!(vec.will_reallocate(1));
+
+        vec.reserve_exact(100);
+        let capacity = vec.capacity();
+        vec.extend_from_slice(&[0x42; 60]);
+        assert!(!vec.will_reallocate(capacity - 60));
+        assert!(vec.will_reallocate(capacity - 59));
+
+        // Filling the slack doesn't move the buffer, but crossing it does
+        let ptr = vec.as_ptr();
+        vec.resize(capacity, 0x42);
+        assert_eq!(vec.as_ptr(), ptr);
+        assert!(vec.will_reallocate(1));
+        vec.push(0x42);
+        assert!(vec.capacity() > capacity);
+    }
+
+    #[test]
+    fn reserve_exact_capacity() {
+        let mut vec = SecureVec::new();
+        vec.push(0xaa);
+        vec.reserve_exact(10);
+        // The reported capacity is exactly what was requested, not the page-rounded footprint
+        assert_eq!(vec.capacity(), 11);
+        let range = vec.ptr_range();
+        assert_eq!(range.end as usize - range.start as usize, 11);
+        // The buffer ends at the end of its page, so no capacity lies beyond it
+        assert_eq!(range.end as usize % crate::page_size(), 0);
+        assert!(vec.will_reallocate(11));
+
+        // Reserving less than the spare capacity does nothing
+        let ptr = vec.as_ptr();
+        vec.reserve_exact(5);
+        assert_eq!(vec.capacity(), 11);
+        assert_eq!(vec.as_ptr(), ptr);
+
+        // Growing beyond the capacity reallocates, even though the page had room to spare
+        vec.extend_from_slice(&[0xbb; 11]);
+        assert_ne!(vec.as_ptr(), ptr);
+        assert_eq!(vec.len(), 12);
+        assert_eq!(vec[0], 0xaa);
+
+        // Capacity is counted in elements for other types, and still exact
+        let mut words: Vec<u64, SodiumAllocator> = Vec::new_in(SodiumAllocator);
+        words.reserve_exact(3);
+        assert_eq!(words.capacity(), 3);
+        assert_eq!(
+            words.as_ptr() as usize % crate::page_size(),
+            crate::page_size() - 24
+        );
+    }
+
+    #[test]
+    fn swap_reowns_allocations() -> Result<(), AllocError> {
+        let mut a = filled(&[0xaa; 32])?;
+        let mut b = filled(&[0xbb; 5000])?;
+        let (a_ptr, a_cap) = (a.as_ptr(), a.capacity());
+        let (b_ptr, b_cap) = (b.as_ptr(), b.capacity());
+
+        std::mem::swap(&mut a, &mut b);
+        // Only the pointers were exchanged: Each buffer is exactly where it was before
+        assert_eq!((a.as_ptr(), a.capacity()), (b_ptr, b_cap));
+        assert_eq!((b.as_ptr(), b.capacity()), (a_ptr, a_cap));
+        assert_eq!(&a[..], &[0xbb; 5000][..]);
+        assert_eq!(&b[..], &[0xaa; 32]);
+
+        // The same applies to the `SecureVec` wrapper
+        let mut c = SecureVec::new();
+        c.extend_from_slice(&[0xcc; 16]);
+        let c_ptr = c.as_ptr();
+        let mut d = SecureVec::new();
+        std::mem::swap(&mut c, &mut d);
+        assert!(c.is_empty());
+        assert_eq!(d.as_ptr(), c_ptr);
+        assert_eq!(&d[..], &[0xcc; 16]);
+
+        #[cfg(feature = "track-allocations")]
+        {
+            use crate::tracking::is_live;
+
+            // Every allocation is still live, and each is freed exactly once by its new owner
+            assert!(is_live(a_ptr) && is_live(b_ptr) && is_live(c_ptr));
+            drop(b);
+            assert!(!is_live(a_ptr));
+            assert!(is_live(b_ptr));
+            drop(a);
+            assert!(!is_live(b_ptr));
+            drop(c);
+            assert!(is_live(c_ptr));
+            drop(d);
+            assert!(!is_live(c_ptr));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_empty_vec() {
+        let secure = SecureVec::from(Vec::new());
+        assert!(secure.is_empty());
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn fill_from_rng() -> Result<(), AllocError> {
+        use rand_core::{impls, CryptoRng, RngCore};
+
+        /// A deterministic generator producing the bytes 1, 2, 3, ... in turn.
+        struct CountingRng(u8);
+
+        impl RngCore for CountingRng {
+            fn next_u32(&mut self) -> u32 {
+                impls::next_u32_via_fill(self)
+            }
+
+            fn next_u64(&mut self) -> u64 {
+                impls::next_u64_via_fill(self)
+            }
+
+            fn fill_bytes(&mut self, dest: &mut [u8]) {
+                for b in dest {
+                    self.0 = self.0.wrapping_add(1);
+                    *b = self.0;
+                }
+            }
+
+            fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+                self.fill_bytes(dest);
+                Ok(())
+            }
+        }
+
+        // Not actually secure, of course.
+        impl CryptoRng for CountingRng {}
+
+        let mut rng = CountingRng(0);
+        let key = fill_secure_from_rng(&mut rng, 300)?;
+        assert_eq!(key.len(), 300);
+        assert!(key.iter().enumerate().all(|(i, &b)| b == (i + 1) as u8));
+        // The buffer is allocated using Sodium, so it ends at the end of a page
+        assert_eq!(
+            (key.as_ptr() as usize + key.capacity()) % crate::page_size(),
+            0
+        );
+        #[cfg(feature = "track-allocations")]
+        assert!(crate::tracking::is_live(key.as_ptr()));
+
+        // The generator's state carries on between calls
+        let next = fill_secure_from_rng(&mut rng, 2)?;
+        assert_eq!(&next[..], &[45, 46]);
+        assert!(fill_secure_from_rng(&mut rng, 0)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_slice_with_capacity() -> Result<(), AllocError> {
+        let mut vec = secure_vec_from_slice_with_capacity(b"ciphertext", 26)?;
+        assert_eq!(&vec[..], b"ciphertext");
+        assert!(vec.capacity() >= 26);
+
+        let ptr = vec.as_ptr();
+        vec.extend_from_slice(&[0xaa; 16]);
+        assert_eq!(vec.as_ptr(), ptr);
+
+        // A capacity smaller than the slice still allocates enough space to copy it
+        let vec = secure_vec_from_slice_with_capacity(b"ciphertext", 4)?;
+        assert_eq!(&vec[..], b"ciphertext");
+        assert!(vec.capacity() >= 10);
+
+        Ok(())
+    }
+
+    #[test]
+    fn concat_parts() -> Result<(), AllocError> {
+        let vec = secure_concat(&[b"salt", b"password", b"pepper"])?;
+        assert_eq!(&vec[..], b"saltpasswordpepper");
+        assert_eq!(vec.capacity(), 18);
+
+        assert!(secure_concat(&[])?.is_empty());
+        assert_eq!(&secure_concat(&[b"", b"key", b""])?[..], b"key");
+
+        Ok(())
+    }
+
+    #[test]
+    fn xor_fold() -> Result<(), AllocError> {
+        let shares: Vec<Vec<u8>> = (1..=5u8).map(|i| vec![i; 32]).collect();
+        let secret = secure_fold(32, shares.iter().map(|s| &s[..]), |acc, share| {
+            acc.iter_mut().zip(share).for_each(|(a, s)| *a ^= s);
+        })?;
+        let expected = (1..=5u8).fold(0, |acc, i| acc ^ i);
+        assert_eq!(&secret[..], &[expected; 32]);
+        assert_eq!(secret.capacity(), 32);
+
+        // With no inputs, the accumulator is returned zeroed
+        let empty = secure_fold(16, [], |_, _| unreachable!())?;
+        assert_eq!(&empty[..], &[0; 16]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn truncate_wiping() -> Result<(), AllocError> {
+        let mut vec = secure_vec_with_capacity(64)?;
+        vec.extend_from_slice(&[0xff; 64]);
+
+        vec.truncate_wiping(16);
+        assert_eq!(vec.len(), 16);
+        assert!(vec.capacity() >= 64);
+        assert_eq!(&vec[..], &[0xff; 16]);
+        // SAFETY: The whole capacity was initialised by `secure_vec_with_capacity`.
+        let contents = unsafe { capacity_contents(&vec) };
+        assert!(contents[16..].iter().all(|&b| b == 0));
+
+        // Truncating to a greater length should have no effect
+        vec.truncate_wiping(32);
+        assert_eq!(&vec[..], &[0xff; 16]);
+
+        vec.truncate_wiping(0);
+        assert!(vec.is_empty());
+        let contents = unsafe { capacity_contents(&vec) };
+        assert!(contents.iter().all(|&b| b == 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn resize_wiping() -> Result<(), AllocError> {
+        let mut vec = secure_vec_with_capacity(64)?;
+        vec.extend_from_slice(&[0xff; 8]);
+        let ptr = vec.as_ptr();
+
+        // Growing within the capacity happens in place
+        vec.resize_wiping(40, 0x11);
+        assert_eq!(vec.as_ptr(), ptr);
+        assert_eq!(&vec[..8], &[0xff; 8]);
+        assert_eq!(&vec[8..], &[0x11; 32]);
+
+        // Shrinking wipes the removed tail, but keeps the capacity
+        vec.resize_wiping(4, 0x22);
+        assert_eq!(&vec[..], &[0xff; 4]);
+        assert!(vec.capacity() >= 64);
+        // SAFETY: The whole capacity was initialised by `secure_vec_with_capacity`.
+        let contents = unsafe { capacity_contents(&vec) };
+        assert!(contents[4..].iter().all(|&b| b == 0));
+
+        // Resizing to the same length does nothing
+        vec.resize_wiping(4, 0x33);
+        assert_eq!(&vec[..], &[0xff; 4]);
+
+        // Growing beyond the capacity reallocates, keeping the contents
+        vec.resize_wiping(100, 0x44);
+        assert_eq!(&vec[..4], &[0xff; 4]);
+        assert_eq!(&vec[4..], &[0x44; 96][..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn retain_wiping() -> Result<(), AllocError> {
+        let mut vec = secure_vec_with_capacity(64)?;
+        vec.extend(1..=64u8);
+
+        vec.retain_wiping(|&b| b % 3 == 0);
+        let expected: Vec<u8> = (1..=64).filter(|b| b % 3 == 0).collect();
+        assert_eq!(&vec[..], &expected[..]);
+
+        // SAFETY: The whole capacity was initialised by `secure_vec_with_capacity`.
+        let contents = unsafe { capacity_contents(&vec) };
+        assert!(contents[expected.len()..].iter().all(|&b| b == 0));
+
+        vec.retain_wiping(|_| false);
+        assert!(vec.is_empty());
+        let contents = unsafe { capacity_contents(&vec) };
+        assert!(contents.iter().all(|&b| b == 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn drain_secure_partially_consumed() -> Result<(), AllocError> {
+        let mut vec = secure_vec_with_capacity(16)?;
+        vec.extend(0x10..0x1a);
+
+        let mut drain = vec.drain_secure(2..8);
+        assert_eq!(drain.len(), 6);
+        assert_eq!(drain.next(), Some(0x12));
+        assert_eq!(drain.next_back(), Some(0x17));
+        assert_eq!(drain.as_slice(), &[0x13, 0x14, 0x15, 0x16]);
+        drop(drain);
+
+        assert_eq!(&vec[..], &[0x10, 0x11, 0x18, 0x19]);
+        assert!(vec.capacity() >= 16);
+        // SAFETY: The whole capacity was initialised by `secure_vec_with_capacity`.
+        let contents = unsafe { capacity_contents(&vec) };
+        // None of the drained bytes remain anywhere in the allocation, including the ones which
+        // were never returned.
+        assert!(contents[4..].iter().all(|&b| b == 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn drain_secure_ranges() -> Result<(), AllocError> {
+        let mut vec = filled(&[1, 2, 3, 4, 5])?;
+        assert_eq!(vec.drain_secure(3..).collect::<Vec<_>>(), [4, 5]);
+        assert_eq!(&vec[..], &[1, 2, 3]);
+        assert_eq!(vec.drain_secure(..=0).collect::<Vec<_>>(), [1]);
+        assert_eq!(&vec[..], &[2, 3]);
+        assert_eq!(vec.drain_secure(1..1).count(), 0);
+        assert_eq!(&vec[..], &[2, 3]);
+        drop(vec.drain_secure(..));
+        assert!(vec.is_empty());
+
+        // Leaking the iterator leaves the vector unchanged
+        let mut vec = filled(&[1, 2, 3])?;
+        std::mem::forget(vec.drain_secure(1..));
+        assert_eq!(&vec[..], &[1, 2, 3]);
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "drain end 4 > len 3")]
+    fn drain_secure_out_of_bounds() {
+        let mut vec = filled(&[1, 2, 3]).unwrap();
+        vec.drain_secure(1..4);
+    }
+
+    #[test]
+    fn extend_from_slice_wiping() -> Result<(), AllocError> {
+        let mut vec = filled(&[1, 2, 3])?;
+        let mut src = [0xaa; 32];
+
+        vec.extend_from_slice_wiping(&mut src);
+        assert_eq!(vec.len(), 35);
+        assert_eq!(&vec[..3], &[1, 2, 3]);
+        assert_eq!(&vec[3..], &[0xaa; 32]);
+        assert_eq!(src, [0; 32]);
+
+        // Growing beyond the original capacity
+        let mut src = [0xbb; 4096];
+        vec.extend_from_slice_wiping(&mut src);
+        assert_eq!(&vec[35..], &[0xbb; 4096][..]);
+        assert!(src.iter().all(|&b| b == 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn shrink_in_place() -> Result<(), AllocError> {
+        // Both the contents and the capacity fit in a single page
+        let mut vec = filled(&[0xaa; 100])?;
+        vec.truncate(10);
+        let ptr = vec.as_ptr();
+        let capacity = vec.capacity();
+        // The standard `truncate` leaves the removed bytes behind
+        assert!(unsafe { capacity_contents(&vec) }[10..].contains(&0xaa));
+
+        // Shrinking would free no pages, so the buffer and its capacity are kept
+        vec.shrink_to_fit_wiping();
+        assert_eq!(vec.as_ptr(), ptr);
+        assert_eq!(vec.capacity(), capacity);
+        assert!(vec.capacity() > vec.len());
+        assert_eq!(&vec[..], &[0xaa; 10]);
+        let contents = unsafe { capacity_contents(&vec) };
+        assert!(contents[10..].iter().all(|&b| b == 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn shrink_reallocates() -> Result<(), AllocError> {
+        let mut vec = secure_vec_with_capacity(4 * crate::page_size())?;
+        vec.resize(vec.capacity(), 0xaa);
+        vec.truncate(10);
+        let ptr = vec.as_ptr();
+
+        vec.shrink_to_fit_wiping();
+        assert_eq!(vec.capacity(), 10);
+        assert_eq!(&vec[..], &[0xaa; 10]);
+        assert_ne!(vec.as_ptr(), ptr);
+        // The old allocation, including the released capacity, was zeroed by Sodium as it was
+        // freed
+        #[cfg(feature = "track-allocations")]
+        assert!(!crate::tracking::is_live(ptr));
+
+        // Shrinking an empty vector frees its allocation entirely
+        vec.clear();
+        vec.shrink_to_fit_wiping();
+        assert_eq!(vec.capacity(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn ptr_range_bounds() -> Result<(), AllocError> {
+        let mut vec = secure_vec_with_capacity(100)?;
+        vec.extend_from_slice(&[0x13; 10]);
+
+        let range = vec.ptr_range();
+        assert_eq!(range.start, vec.as_ptr());
+        assert_eq!(range.end as usize - range.start as usize, vec.capacity());
+
+        let start = vec.as_ptr();
+        assert!(vec.contains_ptr(start));
+        // Spare capacity is still part of the allocation
+        assert!(vec.contains_ptr(start.wrapping_add(50)));
+        assert!(vec.contains_ptr(start.wrapping_add(vec.capacity() - 1)));
+        assert!(!vec.contains_ptr(start.wrapping_add(vec.capacity())));
+        assert!(!vec.contains_ptr(start.wrapping_sub(1)));
+
+        // The allocation ends at the end of a page, immediately before the guard page
+        assert_eq!(crate::page_offset(range.end), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn partition_wipes_original() -> Result<(), AllocError> {
+        let alloc = InspectingAllocator::default();
+        let mut vec = Vec::with_capacity_in(64, alloc.clone());
+        vec.extend(0..64u8);
+        vec.truncate(48);
+
+        let kept = partition_secure(vec, |&b| b < 16)?;
+        let expected: Vec<u8> = (0..16).collect();
+        assert_eq!(&kept[..], &expected[..]);
+        assert_eq!(kept.capacity(), 16);
+
+        let freed = alloc.0.borrow();
+        assert_eq!(freed.len(), 1);
+        assert_eq!(freed[0].len(), 64);
+        assert!(freed[0].iter().all(|&b| b == 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn partition_wipes_on_panic() {
+        let alloc = InspectingAllocator::default();
+        let mut vec = Vec::with_capacity_in(64, alloc.clone());
+        vec.extend(0..64u8);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            partition_secure(vec, |&b| {
+                if b == 32 {
+                    panic!("predicate panicked")
+                } else {
+                    true
+                }
+            })
+        }));
+        assert!(result.is_err());
+
+        let freed = alloc.0.borrow();
+        assert_eq!(freed.len(), 1);
+        assert!(freed[0].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn guarded_buffer() -> Result<(), AllocError> {
+        let sum = with_guarded_buffer(16, |buf| {
+            assert_eq!(buf, &[0; 16]);
+            buf.copy_from_slice(&[3; 16]);
+            buf.iter().map(|&b| b as u32).sum::<u32>()
+        })?;
+        assert_eq!(sum, 48);
+
+        assert!(with_guarded_buffer(0, |buf| buf.is_empty())?);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "track-allocations")]
+    #[test]
+    fn guarded_buffer_freed_on_panic() {
+        use crate::tracking::is_live;
+        use std::cell::Cell;
+
+        let ptr = Cell::new(std::ptr::null());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            with_guarded_buffer(32, |buf| {
+                buf.fill(0xff);
+                ptr.set(buf.as_ptr());
+                assert!(is_live(buf.as_ptr()));
+                panic!("closure panicked");
+            })
+        }));
+
+        assert!(result.is_err());
+        assert!(!ptr.get().is_null());
+        assert!(!is_live(ptr.get()));
+    }
+
+    #[test]
+    fn partition_guarded() -> Result<(), AllocError> {
+        let mut vec = secure_vec_with_capacity(32)?;
+        vec.extend_from_slice(&[0xaa, 0xbb, 0xaa, 0xbb]);
+
+        assert_eq!(
+            &partition_secure(vec.clone(), |&b| b == 0xbb)?[..],
+            &[0xbb, 0xbb]
+        );
+        assert!(partition_secure(vec, |_| false)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn fill_spare_capacity() {
+        let mut vec = SecureVec::new();
+        vec.extend_from_slice(&[0xff; 4]);
+        vec.reserve(100);
+
+        let spare = vec.spare_capacity_mut();
+        assert!(spare.len() >= 100);
+        for (i, byte) in spare[..100].iter_mut().enumerate() {
+            byte.write(i as u8);
+        }
+        // SAFETY: The first 100 bytes of the spare capacity have been initialised.
+        unsafe { vec.set_len(104) };
+
+        assert_eq!(&vec[..4], &[0xff; 4]);
+        assert!(vec[4..].iter().enumerate().all(|(i, &b)| b == i as u8));
+
+        // The spare capacity is still part of the guarded allocation
+        let end = vec.as_ptr() as usize + vec.capacity();
+        assert_eq!(crate::page_offset(end as *const u8), 0);
+    }
+
+    /// Fill a new vector with `bytes`, with its whole capacity initialised.
+    fn filled(bytes: &[u8]) -> Result<Vec<u8, SodiumAllocator>, AllocError> {
+        let mut vec = secure_vec_with_capacity(bytes.len())?;
+        vec.extend_from_slice(bytes);
+        Ok(vec)
+    }
+
+    #[test]
+    fn std_methods_leave_stale_bytes() -> Result<(), AllocError> {
+        let mut vec = filled(&[1, 1, 2, 2, 3])?;
+        vec.dedup();
+        assert_eq!(&vec[..], &[1, 2, 3]);
+        // SAFETY: The whole capacity was initialised by `secure_vec_with_capacity`.
+        let contents = unsafe { capacity_contents(&vec) };
+        assert_eq!(&contents[3..5], &[2, 3]);
+
+        let mut vec = filled(&[1, 2, 3, 4])?;
+        assert_eq!(vec.swap_remove(0), 1);
+        let contents = unsafe { capacity_contents(&vec) };
+        assert_eq!(&contents[..4], &[4, 2, 3, 4]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn dedup_wiping() -> Result<(), AllocError> {
+        let mut vec = filled(&[1, 1, 2, 2, 2, 3, 1])?;
+        vec.dedup_wiping();
+        assert_eq!(&vec[..], &[1, 2, 3, 1]);
+        // SAFETY: The whole capacity was initialised by `secure_vec_with_capacity`.
+        let contents = unsafe { capacity_contents(&vec) };
+        assert!(contents[4..].iter().all(|&b| b == 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn split_off_wiping() -> Result<(), AllocError> {
+        let mut vec = filled(&[1, 2, 3, 4, 5, 6])?;
+        let tail = vec.split_off_wiping(2);
+        assert_eq!(&vec[..], &[1, 2]);
+        assert_eq!(&tail[..], &[3, 4, 5, 6]);
+        assert!(vec.capacity() >= 6);
+        // SAFETY: The whole capacity was initialised by `secure_vec_with_capacity`.
+        let contents = unsafe { capacity_contents(&vec) };
+        assert!(contents[2..].iter().all(|&b| b == 0));
+
+        // Splitting at the end returns an empty vector, and changes nothing
+        assert!(vec.split_off_wiping(2).is_empty());
+        assert_eq!(&vec[..], &[1, 2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_variants_wiping() -> Result<(), AllocError> {
+        let mut vec = filled(&[1, 2, 3, 4, 5, 6])?;
+
+        assert_eq!(vec.pop_wiping(), Some(6));
+        assert_eq!(vec.remove_wiping(0), 1);
+        assert_eq!(&vec[..], &[2, 3, 4, 5]);
+        assert_eq!(vec.swap_remove_wiping(1), 3);
+        assert_eq!(&vec[..], &[2, 5, 4]);
+
+        // SAFETY: The whole capacity was initialised by `secure_vec_with_capacity`.
+        let contents = unsafe { capacity_contents(&vec) };
+        assert!(contents[3..].iter().all(|&b| b == 0));
+
+        vec.clear();
+        assert_eq!(vec.pop_wiping(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn remove_wiping_out_of_bounds() {
+        let mut vec = secure_copy(&[1, 2, 3]).unwrap();
+        vec.remove_wiping(3);
+    }
+}
@@ -0,0 +1,268 @@
+//! Views into part of a guarded allocation, which share ownership of the allocation.
+
+use crate::vec::SecureVec;
+use crate::SodiumAllocator;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+use std::sync::Arc;
+
+/// A mutable view into part of a guarded allocation, created by splitting a [`SecureVec`].
+///
+/// Each view has exclusive access to its own part of the allocation, and views which came from the
+/// same allocation share ownership of it: The allocation is only freed (and therefore zeroed) once
+/// every view into it has been dropped. Views dereference to `[u8]`, so they can be used much like
+/// a standard slice.
+///
+/// ```
+/// use sodium_alloc::SecureVec;
+///
+/// let mut okm = SecureVec::new();
+/// okm.extend(0..64);
+///
+/// // Split the output of a KDF into two subkeys, without copying either of them
+/// let (enc_key, mac_key) = okm.split_at_secure(32);
+/// assert_eq!(enc_key.len(), 32);
+/// assert_eq!(mac_key[0], 32);
+/// ```
+pub struct SecureView {
+    /// The allocation this view is part of. This is never accessed, only dropped.
+    _owner: Arc<Vec<u8, SodiumAllocator>>,
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// SAFETY: A `SecureView` behaves like a `&mut [u8]` to its own part of the allocation: No other
+// view can access the same bytes, and the shared owner is never accessed, only dropped.
+unsafe impl Send for SecureView {}
+unsafe impl Sync for SecureView {}
+
+impl SecureView {
+    /// Split this view into two views at `mid`, without copying.
+    ///
+    /// The first view contains bytes `[0, mid)`, and the second contains `[mid, len)`. Both views
+    /// continue to share ownership of the original allocation.
+    ///
+    /// # Panics
+    /// Panics if `mid > len`.
+    pub fn split_at_secure(self, mid: usize) -> (SecureView, SecureView) {
+        assert!(mid <= self.len, "mid > len");
+
+        let tail = SecureView {
+            _owner: Arc::clone(&self._owner),
+            // SAFETY: `mid <= len`, so this is within (or one past the end of) this view.
+            ptr: unsafe { NonNull::new_unchecked(self.ptr.as_ptr().add(mid)) },
+            len: self.len - mid,
+        };
+        let head = SecureView { len: mid, ..self };
+
+        (head, tail)
+    }
+
+    /// Split the first `n` bytes off this view, without copying.
+    ///
+    /// Returns the first `n` bytes, followed by the rest of the view. This is intended for
+    /// parsing a secret with a fixed-size header (such as a length prefix or version byte).
+    ///
+    /// # Panics
+    /// Panics if `n > len`.
+    pub fn split_first_secure(self, n: usize) -> (SecureView, SecureView) {
+        assert!(n <= self.len, "n > len");
+        self.split_at_secure(n)
+    }
+
+    /// Split the last `n` bytes off this view, without copying.
+    ///
+    /// Returns the last `n` bytes, followed by the rest of the view, in the same order as
+    /// [`slice::split_last`]. This is intended for parsing a secret with a fixed-size trailer
+    /// (such as an authentication tag).
+    ///
+    /// # Panics
+    /// Panics if `n > len`.
+    pub fn split_last_secure(self, n: usize) -> (SecureView, SecureView) {
+        assert!(n <= self.len, "n > len");
+        let mid = self.len - n;
+        let (rest, last) = self.split_at_secure(mid);
+        (last, rest)
+    }
+}
+
+impl SecureVec {
+    /// Split this vector into two views at `mid`, without copying.
+    ///
+    /// The first view contains bytes `[0, mid)`, and the second contains `[mid, len)`. The views
+    /// share ownership of the vector's allocation, which is only freed once both views have been
+    /// dropped. See [`SecureView`] for details.
+    ///
+    /// # Panics
+    /// Panics if `mid > len`.
+    pub fn split_at_secure(self, mid: usize) -> (SecureView, SecureView) {
+        let mut vec = self.into_inner();
+        let view = SecureView {
+            ptr: NonNull::new(vec.as_mut_ptr()).expect("Vec pointers are never null"),
+            len: vec.len(),
+            // Moving the `Vec` doesn't move its buffer, so `ptr` remains valid.
+            _owner: Arc::new(vec),
+        };
+
+        view.split_at_secure(mid)
+    }
+
+    /// Split the first `n` bytes off this vector, without copying.
+    ///
+    /// Returns the first `n` bytes, followed by the rest of the vector, as views sharing
+    /// ownership of the vector's allocation. See [`SecureView::split_first_secure`].
+    ///
+    /// ```
+    /// use sodium_alloc::SecureVec;
+    ///
+    /// let mut record = SecureVec::new();
+    /// record.extend_from_slice(&[0x01, 0x00, 0x03, 0xaa, 0xbb, 0xcc]);
+    ///
+    /// let (header, body) = record.split_first_secure(3);
+    /// let len = u16::from_be_bytes([header[1], header[2]]) as usize;
+    /// assert_eq!(header[0], 0x01);
+    /// assert_eq!(body.len(), len);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `n > len`.
+    pub fn split_first_secure(self, n: usize) -> (SecureView, SecureView) {
+        assert!(n <= self.len(), "n > len");
+        self.split_at_secure(n)
+    }
+
+    /// Split the last `n` bytes off this vector, without copying.
+    ///
+    /// Returns the last `n` bytes, followed by the rest of the vector, as views sharing
+    /// ownership of the vector's allocation. See [`SecureView::split_last_secure`].
+    ///
+    /// # Panics
+    /// Panics if `n > len`.
+    pub fn split_last_secure(self, n: usize) -> (SecureView, SecureView) {
+        assert!(n <= self.len(), "n > len");
+        let mid = self.len() - n;
+        let (rest, last) = self.split_at_secure(mid);
+        (last, rest)
+    }
+}
+
+impl Deref for SecureView {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: The view lies within the allocation, which is kept alive by `_owner`, and no
+        // other view has access to these bytes.
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for SecureView {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: As above. `&mut self` guarantees exclusive access to this view.
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered(len: u8) -> SecureVec {
+        let mut vec = SecureVec::new();
+        vec.extend(0..len);
+        vec
+    }
+
+    #[test]
+    fn split_halves() {
+        let (mut a, b) = numbered(64).split_at_secure(32);
+        assert_eq!(a.len(), 32);
+        assert_eq!(b.len(), 32);
+        assert!(a.iter().enumerate().all(|(i, &x)| x == i as u8));
+        assert!(b.iter().enumerate().all(|(i, &x)| x == i as u8 + 32));
+
+        // Each half can be modified independently
+        a.fill(0xff);
+        assert_eq!(b[0], 32);
+    }
+
+    #[test]
+    fn split_at_ends() {
+        let (a, b) = numbered(8).split_at_secure(0);
+        assert!(a.is_empty());
+        assert_eq!(&b[..], &[0, 1, 2, 3, 4, 5, 6, 7]);
+
+        let (a, b) = numbered(8).split_at_secure(8);
+        assert_eq!(&a[..], &[0, 1, 2, 3, 4, 5, 6, 7]);
+        assert!(b.is_empty());
+    }
+
+    #[test]
+    fn split_view_again() {
+        let (a, b) = numbered(12).split_at_secure(4);
+        let (b, c) = b.split_at_secure(4);
+        assert_eq!(&a[..], &[0, 1, 2, 3]);
+        assert_eq!(&b[..], &[4, 5, 6, 7]);
+        assert_eq!(&c[..], &[8, 9, 10, 11]);
+    }
+
+    #[test]
+    fn split_header_and_trailer() {
+        // A 2-byte header, a body, and a 4-byte trailer
+        let (header, rest) = numbered(16).split_first_secure(2);
+        let (trailer, body) = rest.split_last_secure(4);
+        assert_eq!(&header[..], &[0, 1]);
+        assert_eq!(&body[..], &[2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+        assert_eq!(&trailer[..], &[12, 13, 14, 15]);
+
+        let (last, rest) = numbered(4).split_last_secure(0);
+        assert!(last.is_empty());
+        assert_eq!(&rest[..], &[0, 1, 2, 3]);
+
+        let (first, rest) = numbered(4).split_first_secure(4);
+        assert_eq!(&first[..], &[0, 1, 2, 3]);
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "n > len")]
+    fn split_last_out_of_bounds() {
+        let _ = numbered(8).split_last_secure(9);
+    }
+
+    #[test]
+    #[should_panic]
+    fn split_out_of_bounds() {
+        let _ = numbered(8).split_at_secure(9);
+    }
+
+    #[cfg(feature = "track-allocations")]
+    #[test]
+    fn freed_after_both_dropped() {
+        use crate::tracking::is_live;
+
+        let vec = numbered(64);
+        let ptr = vec.as_ptr();
+        let (a, b) = vec.split_at_secure(32);
+        assert!(is_live(ptr));
+
+        drop(a);
+        assert!(is_live(ptr));
+        assert_eq!(b[0], 32);
+
+        drop(b);
+        assert!(!is_live(ptr));
+
+        // The allocation outlives whichever views from a multi-way split are dropped first
+        let vec = numbered(64);
+        let ptr = vec.as_ptr();
+        let (header, rest) = vec.split_first_secure(8);
+        let (trailer, body) = rest.split_last_secure(16);
+        drop(body);
+        drop(header);
+        assert!(is_live(ptr));
+        assert_eq!(trailer[0], 48);
+        drop(trailer);
+        assert!(!is_live(ptr));
+    }
+}
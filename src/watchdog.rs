@@ -0,0 +1,243 @@
+//! A background thread which periodically checks live allocations for corruption.
+
+use crate::tracking::corrupted_allocations;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// An allocation whose canary was found to have been modified by a [`Watchdog`].
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct CorruptAllocation {
+    /// The address of the allocation, as returned by the allocator.
+    pub address: usize,
+
+    /// The tag the allocation was made with, if any.
+    pub tag: Option<&'static str>,
+}
+
+/// State shared between a [`Watchdog`] and its thread.
+struct Shared {
+    /// Whether the watchdog has been asked to stop.
+    stopped: Mutex<bool>,
+    /// Notified when the watchdog is asked to stop.
+    wake: Condvar,
+    /// The number of times the live allocations have been checked.
+    sweeps: AtomicUsize,
+}
+
+/// A handle to a background thread which periodically checks the canaries of all live
+/// allocations.
+///
+/// Only available with the `watchdog` feature enabled, which also enables `track-allocations`.
+///
+/// Sodium only checks an allocation's canary when it is freed, so corruption of a long-lived
+/// secret (such as a master key held for the lifetime of a server) might otherwise go unnoticed
+/// indefinitely. The watchdog thread checks every allocation in the registry (see
+/// [`stats`](crate::stats)) using the same non-destructive comparison as
+/// [`verify_integrity`](crate::verify_integrity), and calls a callback with each allocation whose
+/// canary has been modified. The thread runs at a low priority where supported (on Linux, it
+/// lowers its own niceness).
+///
+/// If the linked libsodium doesn't place canaries before allocations (see
+/// [`aborts_on_misuse`](crate::aborts_on_misuse)), there is nothing to check, and the watchdog
+/// never reports any corruption.
+///
+/// The thread is stopped when the `Watchdog` is dropped.
+///
+/// ```
+/// use sodium_alloc::{SecureVec, Watchdog};
+/// use std::time::Duration;
+///
+/// let mut master_key = SecureVec::new();
+/// master_key.extend_from_slice(&[0x42; 32]);
+/// let watchdog = Watchdog::start(Duration::from_secs(60), |corrupt| {
+///     eprintln!("allocation at {:#x} has been corrupted", corrupt.address);
+///     std::process::abort();
+/// })?;
+/// // ...run the server...
+/// drop(watchdog);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct Watchdog {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Start a watchdog thread, which checks the canaries of all live allocations every
+    /// `interval`, calling `on_corruption` for each allocation whose canary has been modified.
+    ///
+    /// The first check happens immediately. `on_corruption` is called from the watchdog thread,
+    /// after the check has finished, so it may allocate or free memory using this crate. It will
+    /// be called again on every later check while the allocation remains corrupt.
+    ///
+    /// Returns an error if the thread could not be spawned.
+    pub fn start<F>(interval: Duration, on_corruption: F) -> io::Result<Self>
+    where
+        F: Fn(CorruptAllocation) + Send + 'static,
+    {
+        let shared = Arc::new(Shared {
+            stopped: Mutex::new(false),
+            wake: Condvar::new(),
+            sweeps: AtomicUsize::new(0),
+        });
+
+        let thread = thread::Builder::new()
+            .name("sodium-alloc-watchdog".into())
+            .spawn({
+                let shared = Arc::clone(&shared);
+                move || run(&shared, interval, on_corruption)
+            })?;
+
+        Ok(Self {
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns the number of times the watchdog has finished checking the live allocations.
+    pub fn sweeps(&self) -> usize {
+        self.shared.sweeps.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        *self
+            .shared
+            .stopped
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = true;
+        self.shared.wake.notify_all();
+        if let Some(thread) = self.thread.take() {
+            // A panic in the callback has already been reported by the thread.
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The body of the watchdog thread.
+fn run<F: Fn(CorruptAllocation)>(shared: &Shared, interval: Duration, on_corruption: F) {
+    #[cfg(all(target_os = "linux", not(miri)))]
+    // SAFETY: `setpriority` has no memory safety requirements. On Linux, this only affects the
+    // calling thread. Failure just leaves the thread at its normal priority.
+    unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, libc::gettid() as libc::id_t, 19);
+    }
+
+    let mut stopped = shared.stopped.lock().unwrap_or_else(|e| e.into_inner());
+    while !*stopped {
+        drop(stopped);
+
+        // Without canaries, every allocation would appear to be corrupt.
+        if cfg!(any(miri, feature = "test-backend")) || crate::aborts_on_misuse() {
+            for (address, tag) in corrupted_allocations() {
+                on_corruption(CorruptAllocation { address, tag });
+            }
+        }
+        shared.sweeps.fetch_add(1, Ordering::Release);
+
+        stopped = shared.stopped.lock().unwrap_or_else(|e| e.into_inner());
+        stopped = shared
+            .wake
+            .wait_timeout_while(stopped, interval, |stopped| !*stopped)
+            .unwrap_or_else(|e| e.into_inner())
+            .0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SodiumAllocator;
+    use std::alloc::{Allocator, Layout};
+    use std::error::Error;
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    /// Wait until `watchdog` has finished at least `n` sweeps.
+    fn wait_for_sweeps(watchdog: &Watchdog, n: usize) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while watchdog.sweeps() < n {
+            assert!(Instant::now() < deadline, "watchdog isn't running");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    // Other tests may corrupt canaries temporarily, so these tests only make assertions about
+    // allocations with tags unique to each test.
+
+    #[test]
+    fn intact_allocations_not_reported() -> Result<(), Box<dyn Error>> {
+        let tag = "watchdog::tests::intact_allocations_not_reported";
+        let layouts = [
+            Layout::from_size_align(32, 1)?,
+            Layout::from_size_align(4096, 8)?,
+            Layout::from_size_align(64, crate::page_size() * 2)?,
+        ];
+        let ptrs = layouts
+            .iter()
+            .map(|&layout| SodiumAllocator.tagged_allocate(layout, tag))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (tx, rx) = mpsc::channel();
+        let watchdog = Watchdog::start(Duration::from_millis(1), move |corrupt| {
+            let _ = tx.send(corrupt);
+        })?;
+        wait_for_sweeps(&watchdog, 3);
+        drop(watchdog);
+
+        assert!(rx.try_iter().all(|corrupt| corrupt.tag != Some(tag)));
+        for (ptr, layout) in ptrs.into_iter().zip(layouts) {
+            unsafe { SodiumAllocator.deallocate(ptr.cast(), layout) };
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn corruption_reported() -> Result<(), Box<dyn Error>> {
+        if !cfg!(any(miri, feature = "test-backend")) && !crate::aborts_on_misuse() {
+            return Ok(());
+        }
+
+        let tag = "watchdog::tests::corruption_reported";
+        let layout = Layout::from_size_align(32, 1)?;
+        let ptr = SodiumAllocator.tagged_allocate(layout, tag)?.cast::<u8>();
+
+        let (tx, rx) = mpsc::channel();
+        unsafe {
+            let canary = ptr.as_ptr().sub(1);
+            let original = canary.read();
+            canary.write(!original);
+
+            let watchdog = Watchdog::start(Duration::from_millis(1), move |corrupt| {
+                let _ = tx.send(corrupt);
+            })?;
+            let corrupt = rx.iter().find(|corrupt| corrupt.tag == Some(tag)).unwrap();
+            drop(watchdog);
+            assert_eq!(corrupt.address, ptr.as_ptr() as usize);
+
+            // Restore the canary, otherwise Sodium would terminate the program on free
+            canary.write(original);
+            SodiumAllocator.deallocate(ptr, layout);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn stops_on_drop() -> Result<(), Box<dyn Error>> {
+        // A long interval, so dropping the watchdog must wake the thread to stop it promptly.
+        let watchdog = Watchdog::start(Duration::from_secs(3600), |_| {})?;
+        wait_for_sweeps(&watchdog, 1);
+
+        let start = Instant::now();
+        drop(watchdog);
+        assert!(start.elapsed() < Duration::from_secs(60));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+//! Secure zeroing of memory.
+
+#[cfg(not(any(feature = "volatile-memzero", miri)))]
+use libsodium_sys as sodium;
+#[cfg(not(any(feature = "volatile-memzero", miri)))]
+use std::ffi::c_void;
+use std::ptr;
+use std::sync::atomic::{compiler_fence, Ordering};
+
+/// Securely zero the contents of `buf`.
+///
+/// This is used by the explicit wiping helpers in this crate, and can also be used directly. The
+/// writes are guaranteed not to be optimised away by the compiler, even if `buf` is never read
+/// again.
+///
+/// By default, this calls Sodium's
+/// [`sodium_memzero`](https://doc.libsodium.org/memory_management#zeroing-memory). If the
+/// `volatile-memzero` feature is enabled, a Rust implementation is used instead, which zeroes the
+/// buffer a word at a time using volatile writes. This can be faster for large buffers when
+/// linking against an older libsodium, whose `sodium_memzero` may write a single byte at a time.
+/// The Rust implementation is also always used under Miri.
+///
+/// Note that memory allocated using [`SodiumAllocator`](crate::SodiumAllocator) is zeroed when
+/// freed regardless, it isn't necessary to call this function before freeing it.
+pub fn memzero(buf: &mut [u8]) {
+    #[cfg(not(any(feature = "volatile-memzero", miri)))]
+    // SAFETY: `buf` is a valid mutable slice, so it's valid to write `buf.len()` bytes to it.
+    unsafe {
+        sodium::sodium_memzero(buf.as_mut_ptr() as *mut c_void, buf.len());
+    }
+
+    #[cfg(any(feature = "volatile-memzero", miri))]
+    volatile_memzero(buf);
+}
+
+/// Securely zero `len` bytes at `ptr`, which may not have been initialised.
+///
+/// This is equivalent to [`memzero`], but can be used to wipe memory which doesn't hold valid
+/// `u8`s (such as padding bytes, or the storage of a value which has been dropped). Afterwards,
+/// every byte is initialised to zero.
+///
+/// # Safety
+/// `ptr` must be valid for writes of `len` bytes.
+pub(crate) unsafe fn memzero_raw(ptr: *mut u8, len: usize) {
+    #[cfg(not(any(feature = "volatile-memzero", miri)))]
+    sodium::sodium_memzero(ptr as *mut c_void, len);
+
+    #[cfg(any(feature = "volatile-memzero", miri))]
+    {
+        // The bytes can't be reinterpreted as words until they're initialised, so write them
+        // individually. This is only used for single values, which are usually small.
+        for i in 0..len {
+            ptr::write_volatile(ptr.add(i), 0);
+        }
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+/// Zero the contents of `buf` using volatile writes.
+///
+/// Volatile writes can't be elided or reordered with respect to other volatile operations by the
+/// compiler, and the compiler fence afterwards prevents later (non-volatile) memory accesses from
+/// being moved before the writes.
+#[cfg_attr(not(any(feature = "volatile-memzero", miri)), allow(dead_code))]
+fn volatile_memzero(buf: &mut [u8]) {
+    // Write as much of the buffer as possible a word at a time, falling back to bytewise writes
+    // for the unaligned start and end of the buffer.
+    // SAFETY: Any bit pattern is a valid `usize`, so it's fine to reinterpret the aligned middle
+    // section of the buffer as `usize`s.
+    let (prefix, words, suffix) = unsafe { buf.align_to_mut::<usize>() };
+
+    for byte in prefix.iter_mut().chain(suffix.iter_mut()) {
+        // SAFETY: `byte` is a valid, aligned mutable reference.
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+    for word in words {
+        // SAFETY: `word` is a valid, aligned mutable reference.
+        unsafe { ptr::write_volatile(word, 0) };
+    }
+
+    compiler_fence(Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memzero_zeroes() {
+        for len in [0, 1, 7, 8, 9, 4095, 4096, 1 << 20] {
+            let mut buf = vec![0xdb; len];
+            memzero(&mut buf);
+            assert!(buf.iter().all(|&b| b == 0));
+        }
+    }
+
+    #[test]
+    fn volatile_memzero_zeroes() {
+        let mut buf = vec![0xdb; 4096];
+        // Try a variety of offsets, so the prefix/suffix handling is exercised
+        for start in 0..16 {
+            for end in (buf.len() - 16)..buf.len() {
+                buf.fill(0xdb);
+                volatile_memzero(&mut buf[start..end]);
+                assert!(buf[start..end].iter().all(|&b| b == 0));
+                assert!(buf[..start].iter().all(|&b| b == 0xdb));
+                assert!(buf[end..].iter().all(|&b| b == 0xdb));
+            }
+        }
+    }
+}
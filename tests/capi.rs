@@ -0,0 +1,41 @@
+#![cfg(feature = "capi")]
+
+use sodium_alloc::capi::{sodium_alloc_free, sodium_alloc_guarded};
+
+#[test]
+fn guarded_alloc_free() {
+    for size in [1, 32, 4096, 1 << 20] {
+        let ptr = sodium_alloc_guarded(size);
+        assert!(!ptr.is_null());
+
+        unsafe {
+            ptr.write_bytes(0x13, size);
+            assert_eq!(*ptr, 0x13);
+            assert_eq!(*ptr.add(size - 1), 0x13);
+
+            sodium_alloc_free(ptr);
+        }
+    }
+}
+
+#[test]
+fn zero_size_alloc() {
+    let ptr = sodium_alloc_guarded(0);
+    assert!(!ptr.is_null());
+
+    unsafe {
+        sodium_alloc_free(ptr);
+    }
+}
+
+#[test]
+fn free_null() {
+    unsafe {
+        sodium_alloc_free(std::ptr::null_mut());
+    }
+}
+
+#[test]
+fn oversized_alloc() {
+    assert!(sodium_alloc_guarded(usize::MAX).is_null());
+}
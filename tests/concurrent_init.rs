@@ -0,0 +1,29 @@
+//! Many threads making their first allocations at the same time, right after program start.
+#![feature(allocator_api)]
+
+use sodium_alloc::{init_state, InitState, SodiumAllocator};
+use std::sync::Barrier;
+use std::thread;
+
+const THREADS: usize = 128;
+
+#[test]
+fn concurrent_first_allocations() {
+    // Nothing else in this test binary allocates, so libsodium hasn't been initialised yet.
+    #[cfg(not(feature = "global-init"))]
+    assert_eq!(init_state(), InitState::Uninitialized);
+
+    let barrier = Barrier::new(THREADS);
+    thread::scope(|s| {
+        for i in 0..THREADS {
+            let barrier = &barrier;
+            s.spawn(move || {
+                barrier.wait();
+                let key = Box::new_in([i as u8; 32], SodiumAllocator);
+                assert_eq!(*key, [i as u8; 32]);
+            });
+        }
+    });
+
+    assert_eq!(init_state(), InitState::Ready);
+}
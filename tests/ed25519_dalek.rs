@@ -0,0 +1,130 @@
+//! Storing an `ed25519-dalek` signing key in guarded memory.
+//!
+//! This is a worked example of the pattern recommended for keys from crypto crates which don't
+//! support custom allocators: Keep the secret *seed* in guarded memory, and only reconstruct the
+//! crate's key type for as long as it is needed.
+//!
+//! `ed25519-dalek` can't construct a `SigningKey` in place, so some unguarded copies are
+//! unavoidable:
+//!
+//! * [`GuardedSigningKey::from_signing_key`] receives the seed from `SigningKey::to_bytes` as an
+//!   array on the stack. We copy it into guarded memory and zero the temporary immediately.
+//! * [`GuardedSigningKey::with_signing_key`] reconstructs a `SigningKey` (including the expanded
+//!   secret scalar) on the stack for the duration of the closure. With `ed25519-dalek`'s default
+//!   `zeroize` feature, the `SigningKey` is zeroed when it is dropped, at the end of the call. The
+//!   temporaries used internally by `ed25519-dalek` while expanding the key are outside of our
+//!   control.
+//!
+//! Moving a `SigningKey` into a `Box<SigningKey, SodiumAllocator>` is *not* an improvement: The
+//! key is still constructed on the stack, and moving it out doesn't zero the original.
+#![feature(allocator_api)]
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sodium_alloc::{memzero, SecureArray};
+use std::alloc::AllocError;
+
+/// An Ed25519 signing key whose secret seed is stored in guarded memory.
+struct GuardedSigningKey {
+    seed: SecureArray<32>,
+    /// The public half of the key isn't secret, so it's kept unguarded.
+    verifying_key: VerifyingKey,
+}
+
+impl GuardedSigningKey {
+    /// Move an existing `SigningKey` into guarded memory.
+    fn from_signing_key(key: SigningKey) -> Result<Self, AllocError> {
+        let mut seed = SecureArray::<32>::new()?;
+        let mut bytes = key.to_bytes();
+        seed.copy_from_slice(&bytes);
+        memzero(&mut bytes);
+
+        let verifying_key = key.verifying_key();
+        // `SigningKey` zeroes itself on drop.
+        drop(key);
+
+        Ok(Self {
+            seed,
+            verifying_key,
+        })
+    }
+
+    /// Load a seed directly into guarded memory (for example, from a file), without an unguarded
+    /// copy of the seed.
+    fn load<F: FnOnce(&mut [u8; 32])>(fill: F) -> Result<Self, AllocError> {
+        let mut seed = SecureArray::<32>::new()?;
+        fill(&mut seed);
+        let verifying_key = SigningKey::from_bytes(&seed).verifying_key();
+
+        Ok(Self {
+            seed,
+            verifying_key,
+        })
+    }
+
+    /// Reconstruct the `SigningKey` for the duration of `f`.
+    fn with_signing_key<R, F: FnOnce(&SigningKey) -> R>(&self, f: F) -> R {
+        let key = SigningKey::from_bytes(&self.seed);
+        f(&key)
+    }
+
+    fn sign(&self, message: &[u8]) -> Signature {
+        self.with_signing_key(|key| key.sign(message))
+    }
+
+    fn verifying_key(&self) -> VerifyingKey {
+        self.verifying_key
+    }
+}
+
+/// A fixed seed, so the tests are deterministic.
+const SEED: [u8; 32] = [0x1f; 32];
+
+#[test]
+fn sign_with_guarded_key() -> Result<(), AllocError> {
+    let original = SigningKey::from_bytes(&SEED);
+    let expected = original.sign(b"attack at dawn");
+    let public = original.verifying_key();
+
+    let guarded = GuardedSigningKey::from_signing_key(original)?;
+    assert_eq!(*guarded.seed, SEED);
+    assert_eq!(guarded.verifying_key(), public);
+
+    // Ed25519 signatures are deterministic, so the reconstructed key signs identically.
+    let signature = guarded.sign(b"attack at dawn");
+    assert_eq!(signature, expected);
+    assert!(public.verify(b"attack at dawn", &signature).is_ok());
+    assert!(public.verify(b"attack at dusk", &signature).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn load_seed_in_place() -> Result<(), AllocError> {
+    let guarded = GuardedSigningKey::load(|seed| seed.copy_from_slice(&SEED))?;
+    assert_eq!(
+        guarded.verifying_key(),
+        SigningKey::from_bytes(&SEED).verifying_key()
+    );
+
+    let signature = guarded.sign(b"message");
+    assert!(guarded
+        .verifying_key()
+        .verify_strict(b"message", &signature)
+        .is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn signing_key_only_lives_for_closure() -> Result<(), AllocError> {
+    let guarded = GuardedSigningKey::load(|seed| seed.copy_from_slice(&SEED))?;
+
+    // The reconstructed key can be used for anything within the closure, but can't escape it.
+    let public = guarded.with_signing_key(|key| {
+        assert_eq!(key.to_bytes(), SEED);
+        key.verifying_key()
+    });
+    assert_eq!(public, guarded.verifying_key());
+
+    Ok(())
+}
@@ -0,0 +1,20 @@
+//! Allocating with libsodium initialised before `main`.
+#![cfg(feature = "global-init")]
+#![feature(allocator_api)]
+
+use sodium_alloc::{init_state, InitState, SodiumAllocator};
+use std::alloc::{Allocator, Layout};
+
+#[test]
+fn allocate_without_init() {
+    // No explicit initialisation here: With `global-init`, libsodium is initialised before any
+    // test runs.
+    assert_eq!(init_state(), InitState::Ready);
+
+    let layout = Layout::new::<[u8; 32]>();
+    let ptr = SodiumAllocator.allocate(layout).unwrap();
+    unsafe { SodiumAllocator.deallocate(ptr.cast(), layout) };
+
+    let key = Box::new_in([0x13u8; 32], SodiumAllocator);
+    assert_eq!(*key, [0x13; 32]);
+}
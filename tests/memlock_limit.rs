@@ -0,0 +1,55 @@
+//! Enforcing the locked memory limit before allocating.
+//!
+//! This is a separate test binary, as it changes the process-wide limit on locked memory.
+#![cfg(unix)]
+#![feature(allocator_api)]
+
+use sodium_alloc::{locked_bytes, page_size, SodiumAllocator};
+use std::alloc::{Allocator, Layout};
+
+#[test]
+fn allocation_refused_at_limit() {
+    let allocator = SodiumAllocator::configure().enforce_memlock_limit(true);
+    let layout = Layout::from_size_align(page_size() / 2, 1).unwrap();
+
+    // Allow room for exactly 8 more allocations of one page each.
+    let mut old = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    unsafe {
+        assert_eq!(libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut old), 0);
+        let limit = libc::rlimit {
+            rlim_cur: (locked_bytes() + 8 * page_size()) as libc::rlim_t,
+            rlim_max: old.rlim_max,
+        };
+        assert_eq!(libc::setrlimit(libc::RLIMIT_MEMLOCK, &limit), 0);
+    }
+
+    let mut ptrs = Vec::new();
+    let refused = loop {
+        match allocator.allocate(layout) {
+            Ok(ptr) => ptrs.push(ptr),
+            Err(_) => break true,
+        }
+        if ptrs.len() > 8 {
+            break false;
+        }
+    };
+    assert!(refused);
+    assert_eq!(ptrs.len(), 8);
+
+    // Freeing memory makes room for more allocations
+    let ptr = ptrs.pop().unwrap();
+    unsafe { allocator.deallocate(ptr.cast(), layout) };
+    ptrs.push(allocator.allocate(layout).unwrap());
+
+    // Allocators which don't enforce the limit are unaffected
+    let unchecked = SodiumAllocator.allocate(layout).unwrap();
+    unsafe { SodiumAllocator.deallocate(unchecked.cast(), layout) };
+
+    for ptr in ptrs {
+        unsafe { allocator.deallocate(ptr.cast(), layout) };
+    }
+    unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &old) };
+}
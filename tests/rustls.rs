@@ -0,0 +1,40 @@
+//! Using a `GuardedEd25519Key` as the private key of a `rustls` server config.
+#![cfg(feature = "rustls")]
+
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use rustls::crypto::ring::default_provider;
+use rustls::pki_types::CertificateDer;
+use rustls::sign::{CertifiedKey, SingleCertAndKey};
+use rustls::{ServerConfig, SignatureScheme};
+use sodium_alloc::{GuardedEd25519Key, SecureArray};
+use std::error::Error;
+use std::sync::Arc;
+
+#[test]
+fn server_config_with_guarded_key() -> Result<(), Box<dyn Error>> {
+    let mut seed = SecureArray::<32>::new()?;
+    seed.fill(0x5a);
+    let key = Arc::new(GuardedEd25519Key::from_seed(seed));
+    let public = VerifyingKey::from_bytes(&key.public_key_bytes())?;
+
+    // The certificate isn't parsed when building the config, so a placeholder is enough here.
+    let certified = CertifiedKey::new(vec![CertificateDer::from(vec![0x30, 0x00])], key);
+    let config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(SingleCertAndKey::from(certified.clone())));
+    assert!(config.alpn_protocols.is_empty());
+
+    // Sign with the key the config holds, as `rustls` would during a handshake.
+    let signer = certified
+        .key
+        .choose_scheme(&[SignatureScheme::RSA_PSS_SHA256, SignatureScheme::ED25519])
+        .unwrap();
+    let signature = signer.sign(b"server handshake transcript")?;
+    let signature = Signature::from_slice(&signature)?;
+    assert!(public
+        .verify(b"server handshake transcript", &signature)
+        .is_ok());
+
+    Ok(())
+}
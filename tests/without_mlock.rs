@@ -0,0 +1,73 @@
+//! Allocating without locking memory, in an environment where locking is forbidden.
+//!
+//! This is a separate test binary, as it changes the process-wide limit on locked memory.
+#![cfg(unix)]
+#![feature(allocator_api)]
+
+use sodium_alloc::{page_size, SodiumAllocator};
+use std::alloc::{Allocator, Layout};
+
+/// Get the amount of memory currently locked by this process, in KiB.
+#[cfg(target_os = "linux")]
+fn locked_kib() -> usize {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap();
+    let line = status.lines().find(|l| l.starts_with("VmLck:")).unwrap();
+    line.split_whitespace().nth(1).unwrap().parse().unwrap()
+}
+
+#[test]
+fn allocate_without_mlock() {
+    // Allocations made without locking should never count against the locked memory limit.
+    #[cfg(target_os = "linux")]
+    {
+        let before = locked_kib();
+        let mut vec = Vec::with_capacity_in(1 << 20, SodiumAllocator::without_mlock());
+        vec.resize(1 << 20, 0xffu8);
+        assert!(locked_kib() < before + 1024);
+
+        // Over-aligned allocations are placed within a larger block, all of which is locked by
+        // Sodium, so all of it must be unlocked.
+        let layout = Layout::from_size_align(1, page_size() * 16).unwrap();
+        let before = locked_kib();
+        let ptrs = (0..64)
+            .map(|_| SodiumAllocator::without_mlock().allocate(layout).unwrap())
+            .collect::<Vec<_>>();
+        assert!(locked_kib() < before + 64 * page_size() / 1024);
+        for ptr in ptrs {
+            unsafe { SodiumAllocator::without_mlock().deallocate(ptr.cast(), layout) };
+        }
+    }
+
+    // Forbid locking any memory. This doesn't stop a privileged process from locking memory, in
+    // which case this test is less meaningful, but should still pass.
+    let mut old = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    unsafe {
+        assert_eq!(libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut old), 0);
+        let limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: old.rlim_max,
+        };
+        assert_eq!(libc::setrlimit(libc::RLIMIT_MEMLOCK, &limit), 0);
+    }
+
+    for allocator in [
+        SodiumAllocator::without_mlock(),
+        SodiumAllocator::configure(),
+    ] {
+        for size in [1, 4096, 1 << 20] {
+            let mut vec = Vec::with_capacity_in(size, allocator);
+            vec.resize(size, 0xffu8);
+            assert!(vec.iter().all(|&b| b == 0xff));
+        }
+
+        let layout = Layout::from_size_align(32, page_size() * 4).unwrap();
+        let ptr = allocator.allocate(layout).unwrap();
+        assert_eq!(ptr.cast::<u8>().as_ptr() as usize % layout.align(), 0);
+        unsafe { allocator.deallocate(ptr.cast(), layout) };
+    }
+
+    unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &old) };
+}